@@ -39,6 +39,16 @@ mod time {
     };
 }
 
+#[cfg(feature = "lock-stat")]
+mod lock_stat {
+    pub use axsync::stat::{
+        reset as ax_lock_stat_reset, top_contended as ax_lock_stat_top,
+        LockStatEntry as AxLockStatEntry,
+    };
+}
+
+#[cfg(feature = "lock-stat")]
+pub use self::lock_stat::*;
 pub use self::mem::*;
 pub use self::stdio::*;
 pub use self::task::*;
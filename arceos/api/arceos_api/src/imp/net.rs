@@ -129,3 +129,46 @@ pub fn ax_poll_interfaces() -> AxResult {
     axnet::poll_interfaces();
     Ok(())
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// Asynchronous operations
+////////////////////////////////////////////////////////////////////////////////
+
+cfg_async! {
+    use core::task::{Context, Poll};
+
+    pub fn ax_tcp_poll_accept(
+        socket: &AxTcpSocketHandle,
+        cx: &mut Context<'_>,
+    ) -> Poll<AxResult<(AxTcpSocketHandle, SocketAddr)>> {
+        socket.0.poll_accept(cx).map(|res| {
+            let new_sock = res?;
+            let addr = new_sock.peer_addr()?;
+            Ok((AxTcpSocketHandle(new_sock), addr))
+        })
+    }
+
+    pub fn ax_tcp_poll_send(
+        socket: &AxTcpSocketHandle,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<AxResult<usize>> {
+        socket.0.poll_send(cx, buf)
+    }
+
+    pub fn ax_tcp_poll_recv(
+        socket: &AxTcpSocketHandle,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<AxResult<usize>> {
+        socket.0.poll_recv(cx, buf)
+    }
+
+    pub fn ax_udp_poll_recv_from(
+        socket: &AxUdpSocketHandle,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<AxResult<(usize, SocketAddr)>> {
+        socket.0.poll_recv_from(cx, buf)
+    }
+}
@@ -26,6 +26,9 @@ pub fn ax_exit(_exit_code: i32) -> ! {
 cfg_task! {
     use core::time::Duration;
 
+    pub use axtask::AxCpuMask;
+    pub use axtask::{TaskSnapshot as AxTaskSnapshot, TaskState as AxTaskState};
+
     /// A handle to a task.
     pub struct AxTaskHandle {
         inner: axtask::AxTaskRef,
@@ -71,6 +74,51 @@ cfg_task! {
         task.inner.join()
     }
 
+    pub fn ax_wait_for_exit_timeout(
+        task: &AxTaskHandle,
+        timeout: Duration,
+    ) -> crate::AxResult<Option<i32>> {
+        #[cfg(feature = "irq")]
+        return Ok(task.inner.join_timeout(timeout));
+
+        #[cfg(not(feature = "irq"))]
+        {
+            let _ = (task, timeout);
+            axerrno::ax_err!(
+                Unsupported,
+                "ax_wait_for_exit_timeout: timeouts need the `irq` feature"
+            )
+        }
+    }
+
+    pub fn ax_wait_any(tasks: &[&AxTaskHandle]) -> crate::AxResult<(usize, i32)> {
+        let inners = tasks
+            .iter()
+            .map(|task| task.inner.clone())
+            .collect::<alloc::vec::Vec<_>>();
+        let (exited, exit_code) = axtask::wait_any(&inners).map_err(|e| match e {
+            axtask::WaitAnyError::Empty => {
+                axerrno::ax_err_type!(InvalidInput, "ax_wait_any: no tasks to wait for")
+            }
+            axtask::WaitAnyError::NotChild(_) => {
+                axerrno::ax_err_type!(NotFound, "ax_wait_any: not a child task")
+            }
+        })?;
+        let index = tasks
+            .iter()
+            .position(|task| task.id == exited.id().as_u64())
+            .unwrap();
+        Ok((index, exit_code))
+    }
+
+    pub fn ax_cancel_task(task: &AxTaskHandle) {
+        task.inner.cancel();
+    }
+
+    pub fn ax_current_task_cancelled() -> bool {
+        axtask::current().is_cancelled()
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
@@ -82,6 +130,25 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult {
+        if axtask::set_affinity(cpumask) {
+            Ok(())
+        } else {
+            axerrno::ax_err!(
+                InvalidInput,
+                "ax_set_current_affinity: empty CPU affinity mask"
+            )
+        }
+    }
+
+    pub fn ax_get_current_affinity() -> AxCpuMask {
+        axtask::current().cpumask()
+    }
+
+    pub fn ax_task_snapshot() -> alloc::vec::Vec<AxTaskSnapshot> {
+        axtask::task_snapshot()
+    }
+
     pub fn ax_wait_queue_wait(
         wq: &AxWaitQueueHandle,
         until_condition: impl Fn() -> bool,
@@ -109,3 +176,26 @@ cfg_task! {
         }
     }
 }
+
+cfg_async! {
+    use core::future::Future;
+
+    pub use axtask::future::{JoinHandle as AxJoinHandle, Sleep as AxSleepFuture};
+    pub use axtask::future::yield_now as ax_yield_now_async;
+
+    pub fn ax_block_on<F: Future>(fut: F) -> F::Output {
+        axtask::future::block_on(fut)
+    }
+
+    pub fn ax_spawn_async<F>(fut: F) -> AxJoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        axtask::future::spawn(fut)
+    }
+
+    pub fn ax_sleep_until_async(deadline: crate::time::AxTimeValue) -> AxSleepFuture {
+        axtask::future::sleep_until(deadline)
+    }
+}
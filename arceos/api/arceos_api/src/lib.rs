@@ -12,6 +12,7 @@
     feature = "fs",
     feature = "net",
     feature = "multitask",
+    feature = "lock-stat",
     feature = "dummy-if-not-enabled"
 ))]
 extern crate alloc;
@@ -33,6 +34,20 @@ pub mod sys {
         /// Shutdown the whole system and all CPUs.
         pub fn ax_terminate() -> !;
     }
+
+    define_api_type! {
+        @cfg "lock-stat";
+        pub type AxLockStatEntry;
+    }
+
+    define_api! {
+        @cfg "lock-stat";
+        /// Returns the contention statistics of at most `n` lock sites with
+        /// the most contended acquisitions, in descending order.
+        pub fn ax_lock_stat_top(n: usize) -> alloc::vec::Vec<AxLockStatEntry>;
+        /// Resets the contention statistics of all lock sites.
+        pub fn ax_lock_stat_reset();
+    }
 }
 
 /// Time-related operations.
@@ -121,6 +136,9 @@ pub mod task {
         @cfg "multitask";
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
+        pub type AxCpuMask;
+        pub type AxTaskSnapshot;
+        pub type AxTaskState;
     }
 
     define_api! {
@@ -151,10 +169,45 @@ pub mod task {
             stack_size: usize
         ) -> AxTaskHandle;
         /// Waits for the given task to exit, and returns its exit code (the
-        /// argument of [`ax_exit`]).
+        /// argument of [`ax_exit`]), or [`None`] if the current task is
+        /// cancelled while waiting.
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Waits for the given task to exit with a timeout, and returns its
+        /// exit code, or [`None`] if the task has not exited after `timeout`.
+        ///
+        /// Returns [`Unsupported`](crate::AxError::Unsupported) if the `irq`
+        /// feature is not enabled, as there is no timer to end the wait.
+        pub fn ax_wait_for_exit_timeout(
+            task: &AxTaskHandle,
+            timeout: core::time::Duration,
+        ) -> crate::AxResult<Option<i32>>;
+        /// Waits for any of the given tasks, which must be spawned by the
+        /// current task, to exit, and returns the index of the first exited
+        /// one in `tasks` and its exit code.
+        ///
+        /// Returns [`InvalidInput`](crate::AxError::InvalidInput) if `tasks`
+        /// is empty, or [`NotFound`](crate::AxError::NotFound) if it contains
+        /// tasks not spawned by the current task.
+        pub fn ax_wait_any(tasks: &[&AxTaskHandle]) -> crate::AxResult<(usize, i32)>;
+        /// Requests the given task to be cancelled.
+        ///
+        /// The task is woken up if it is blocked in an interruptible wait. It
+        /// can check the request by [`ax_current_task_cancelled`] and exit by
+        /// itself.
+        pub fn ax_cancel_task(task: &AxTaskHandle);
+        /// Returns whether the current task has been requested to be cancelled.
+        pub fn ax_current_task_cancelled() -> bool;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
+        /// Sets the CPU affinity of the current task.
+        ///
+        /// The current task will be migrated immediately if the current CPU
+        /// is not in the given mask.
+        pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult;
+        /// Returns the CPU affinity of the current task.
+        pub fn ax_get_current_affinity() -> AxCpuMask;
+        /// Takes snapshots of all live tasks, in the order of task IDs.
+        pub fn ax_task_snapshot() -> alloc::vec::Vec<AxTaskSnapshot>;
 
         /// Blocks the current task and put it into the wait queue, until the
         /// given condition becomes true, or the the given duration has elapsed
@@ -170,6 +223,44 @@ pub mod task {
         /// `count` is `u32::MAX`, it will wake up all tasks in the wait queue.
         pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32);
     }
+
+    define_api_type! {
+        @cfg "async";
+        pub type AxJoinHandle;
+        pub type AxSleepFuture;
+    }
+
+    define_api! {
+        @cfg "async";
+
+        /// Returns a future that completes at the given deadline.
+        ///
+        /// If the feature `irq` is not enabled, the future is polled
+        /// repeatedly until the deadline.
+        pub fn ax_sleep_until_async(deadline: crate::time::AxTimeValue) -> AxSleepFuture;
+    }
+
+    /// Runs a future to completion on the current task, which is blocked
+    /// while the future is pending.
+    #[cfg(feature = "async")]
+    pub fn ax_block_on<F: core::future::Future>(fut: F) -> F::Output {
+        crate::imp::ax_block_on(fut)
+    }
+
+    /// Spawns a future to run in the background, and returns a handle to
+    /// await its output.
+    #[cfg(feature = "async")]
+    pub fn ax_spawn_async<F>(fut: F) -> AxJoinHandle<F::Output>
+    where
+        F: core::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        crate::imp::ax_spawn_async(fut)
+    }
+
+    /// Returns a future that yields to other futures once.
+    #[cfg(feature = "async")]
+    pub use crate::imp::ax_yield_now_async;
 }
 
 /// Filesystem manipulation operations.
@@ -261,6 +352,11 @@ pub mod fs {
 pub mod net {
     use crate::{io::AxPollState, AxResult};
     use core::net::{IpAddr, SocketAddr};
+    #[cfg(all(
+        feature = "net",
+        any(feature = "async", feature = "dummy-if-not-enabled")
+    ))]
+    use core::task::{Context, Poll};
 
     define_api_type! {
         @cfg "net";
@@ -350,6 +446,39 @@ pub mod net {
         /// packets to the NIC.
         pub fn ax_poll_interfaces() -> AxResult;
     }
+
+    cfg_net! {
+        define_api! {
+            @cfg "async";
+
+            /// Polls for a new connection on the TCP socket.
+            ///
+            /// If there is no new connection, the waker of `cx` is registered
+            /// to be woken up later, and [`Poll::Pending`] is returned.
+            pub fn ax_tcp_poll_accept(
+                socket: &AxTcpSocketHandle,
+                cx: &mut Context<'_>,
+            ) -> Poll<AxResult<(AxTcpSocketHandle, SocketAddr)>>;
+            /// Polls to transmit data in the given buffer on the TCP socket.
+            pub fn ax_tcp_poll_send(
+                socket: &AxTcpSocketHandle,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<AxResult<usize>>;
+            /// Polls to receive data on the TCP socket.
+            pub fn ax_tcp_poll_recv(
+                socket: &AxTcpSocketHandle,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<AxResult<usize>>;
+            /// Polls to receive a single datagram message on the UDP socket.
+            pub fn ax_udp_poll_recv_from(
+                socket: &AxUdpSocketHandle,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<AxResult<(usize, SocketAddr)>>;
+        }
+    }
 }
 
 /// Graphics manipulation operations.
@@ -375,6 +504,20 @@ pub mod io {
     }
 }
 
+/// Synchronization primitives.
+pub mod sync {
+    #[cfg(all(feature = "multitask", feature = "irq"))]
+    pub use axsync::WaitTimeoutResult;
+    #[cfg(feature = "multitask")]
+    pub use axsync::{
+        Barrier, BarrierWaitResult, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard,
+        RwLockUpgradableGuard, RwLockWriteGuard, Semaphore,
+    };
+
+    #[cfg(feature = "multitask")]
+    pub use axsync::mpsc;
+}
+
 /// Re-exports of ArceOS modules.
 ///
 /// You should prefer to use other APIs rather than these modules. The modules
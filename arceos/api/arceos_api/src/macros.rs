@@ -108,3 +108,7 @@ macro_rules! cfg_display {
 macro_rules! cfg_task {
     ($($item:item)*) => { _cfg_common!{ "multitask" $($item)* } }
 }
+
+macro_rules! cfg_async {
+    ($($item:item)*) => { _cfg_common!{ "async" $($item)* } }
+}
@@ -3,15 +3,23 @@ fn main() {
 
     fn gen_pthread_mutex(out_file: &str) -> std::io::Result<()> {
         // TODO: generate size and initial content automatically.
-        let (mutex_size, mutex_init) = if cfg!(feature = "multitask") {
+        let (mut mutex_size, mutex_init) = if cfg!(feature = "multitask") {
             if cfg!(feature = "smp") {
-                (6, "{0, 0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(()))
+                (7, "{0, 0, 8, 0, 0, 0, 0}") // core::mem::transmute::<_, [usize; 7]>(axsync::Mutex::new(()))
             } else {
-                (5, "{0, 8, 0, 0, 0}") // core::mem::transmute::<_, [usize; 5]>(axsync::Mutex::new(()))
+                (6, "{0, 8, 0, 0, 0, 0}") // core::mem::transmute::<_, [usize; 6]>(axsync::Mutex::new(()))
             }
         } else {
             (1, "{0}")
         };
+        // The lock class of `lockdep` and the statistics of `lock-stat` are
+        // appended, which are all-zero in the initializer.
+        if cfg!(feature = "lockdep") {
+            mutex_size += 2;
+        }
+        if cfg!(feature = "lock-stat") {
+            mutex_size += 3;
+        }
 
         let mut output = Vec::new();
         writeln!(
@@ -46,12 +54,20 @@ typedef struct {{
             "timeval",
             "pthread_t",
             "pthread_attr_t",
+            "pthread_key_t",
             "pthread_mutex_t",
             "pthread_mutexattr_t",
+            "pthread_cond_t",
+            "pthread_condattr_t",
+            "pthread_rwlock_t",
+            "pthread_rwlockattr_t",
+            "sem_t",
             "epoll_event",
             "iovec",
             "clockid_t",
             "rlimit",
+            "rusage",
+            "cpu_set_t",
             "aibuf",
         ];
         let allow_vars = [
@@ -66,8 +82,12 @@ typedef struct {{
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
+            "RUSAGE_.*",
             "EAI_.*",
             "MAXADDRS",
+            "PROT_.*",
+            "MAP_.*",
+            "MS_.*",
         ];
 
         #[derive(Debug)]
@@ -3,8 +3,8 @@ use core::ffi::c_int;
 
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
+use axsync::RwLock;
 use flatten_objects::FlattenObjects;
-use spin::RwLock;
 
 use super::stdio::{stdin, stdout};
 use crate::ctypes;
@@ -75,6 +75,13 @@ impl FileLike for File {
     }
 }
 
+/// Returns a new handle of the opened file of `fd`, which is still usable
+/// after `fd` is closed.
+#[cfg(feature = "mmap")]
+pub(crate) fn dup_file(fd: c_int) -> LinuxResult<axfs::fops::File> {
+    Ok(File::from_fd(fd)?.inner.lock().try_clone()?)
+}
+
 /// Convert open flags to [`OpenOptions`].
 fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     let flags = flags as u32;
@@ -0,0 +1,361 @@
+use alloc::sync::Arc;
+use core::ffi::{c_int, c_void};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, MappedFile, SharedPages};
+use memory_addr::VirtAddrRange;
+
+use crate::ctypes;
+
+/// Returns whether `aspace` is the kernel address space, where page faults
+/// are not handled, so that mappings have to be populated when created.
+fn is_kernel(aspace: &AddrSpace) -> bool {
+    aspace.base() == VirtAddr::from(axconfig::KERNEL_ASPACE_BASE)
+}
+
+fn prot_to_flags(prot: c_int) -> MappingFlags {
+    let prot = prot as u32;
+    let mut flags = MappingFlags::empty();
+    if prot & ctypes::PROT_READ != 0 {
+        flags |= MappingFlags::READ;
+    }
+    if prot & ctypes::PROT_WRITE != 0 {
+        flags |= MappingFlags::WRITE;
+    }
+    if prot & ctypes::PROT_EXEC != 0 {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
+
+/// Rounds up the length of a mapping to pages.
+fn page_len(len: usize) -> LinuxResult<usize> {
+    if len == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let len = len
+        .checked_add(PAGE_SIZE_4K - 1)
+        .ok_or(LinuxError::ENOMEM)?;
+    Ok(len & !(PAGE_SIZE_4K - 1))
+}
+
+/// Checks the range of existing mappings.
+fn page_range(addr: usize, len: usize) -> LinuxResult<(VirtAddr, usize)> {
+    if addr % PAGE_SIZE_4K != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok((VirtAddr::from(addr), page_len(len)?))
+}
+
+/// Opens a new handle of the file of `fd` for a file mapping, which is
+/// not affected by closing `fd`.
+#[cfg(feature = "fs")]
+fn mapped_file(fd: c_int) -> LinuxResult<Arc<dyn MappedFile>> {
+    struct File(axfs::fops::File);
+
+    impl MappedFile for File {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> axerrno::AxResult<usize> {
+            self.0.read_at(offset, buf)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> axerrno::AxResult<usize> {
+            self.0.write_at(offset, buf)
+        }
+
+        fn size(&self) -> axerrno::AxResult<u64> {
+            Ok(self.0.get_attr()?.size())
+        }
+
+        fn identity(&self) -> usize {
+            self.0.node_id()
+        }
+    }
+
+    Ok(Arc::new(File(super::fs::dup_file(fd)?)))
+}
+
+#[cfg(not(feature = "fs"))]
+fn mapped_file(_fd: c_int) -> LinuxResult<Arc<dyn MappedFile>> {
+    Err(LinuxError::EBADF)
+}
+
+/// A shared memory object opened as a file, e.g., by `shm_open`.
+///
+/// It is empty when created, and its pages are allocated when its size is
+/// set by `ftruncate`. The size cannot be changed after that, as the pages
+/// may have been mapped.
+#[cfg(feature = "fd")]
+pub struct SharedMemory {
+    pages: axsync::Mutex<Option<Arc<SharedPages>>>,
+}
+
+#[cfg(feature = "fd")]
+impl SharedMemory {
+    /// Creates a new empty shared memory object.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            pages: axsync::Mutex::new(None),
+        })
+    }
+
+    /// Opens the object as a new file descriptor.
+    pub fn add_to_fd_table(self: Arc<Self>) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like(self)
+    }
+
+    /// Returns the shared memory object opened as `fd`.
+    pub fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        let f = super::fd_ops::get_file_like(fd)?;
+        f.into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+
+    /// Returns the size of the object.
+    pub fn size(&self) -> usize {
+        self.pages.lock().as_ref().map_or(0, |pages| pages.size())
+    }
+
+    /// Sets the size of the object, which is rounded up to pages.
+    ///
+    /// Returns `EINVAL` if the object already has a different size.
+    pub fn set_size(&self, size: usize) -> LinuxResult {
+        let size = memory_addr::align_up_4k(size);
+        let mut pages = self.pages.lock();
+        match pages.as_ref() {
+            Some(pages) if pages.size() == size => Ok(()),
+            Some(_) => Err(LinuxError::EINVAL),
+            None if size == 0 => Ok(()),
+            None => {
+                *pages = Some(SharedPages::new(size)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn pages(&self) -> Option<Arc<SharedPages>> {
+        self.pages.lock().clone()
+    }
+}
+
+#[cfg(feature = "fd")]
+impl super::fd_ops::FileLike for SharedMemory {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL) // accessed by mapping only
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o100000 | 0o600u32; // S_IFREG | rw-------
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            st_uid: 1000,
+            st_gid: 1000,
+            st_size: self.size() as _,
+            st_blksize: PAGE_SIZE_4K as _,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<axio::PollState> {
+        Ok(axio::PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Returns the pages of the shared memory object of `fd`, or `None` if `fd`
+/// is not a shared memory object.
+#[cfg(feature = "fd")]
+fn shared_pages(fd: c_int) -> LinuxResult<Option<Arc<SharedPages>>> {
+    match SharedMemory::from_fd(fd) {
+        // Mapping an empty object is like mapping beyond the end of it.
+        Ok(shm) => shm.pages().map(Some).ok_or(LinuxError::EINVAL),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(not(feature = "fd"))]
+fn shared_pages(_fd: c_int) -> LinuxResult<Option<Arc<SharedPages>>> {
+    Ok(None)
+}
+
+/// Creates a new mapping in the address space, like `mmap(2)`.
+///
+/// It is shared by [`sys_mmap`] and the `mmap` syscalls of monolithic
+/// kernels, which pass the address space of the current process. If the
+/// mapping is not fixed, it is placed at the first free area above `addr`,
+/// or above the middle of the address space if `addr` is zero.
+///
+/// Mappings in the kernel address space are populated immediately, so the
+/// file is read with the address space locked.
+///
+/// Returns the start address of the mapping.
+pub fn aspace_mmap(
+    aspace: &mut AddrSpace,
+    addr: usize,
+    len: usize,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> LinuxResult<usize> {
+    let len = page_len(len)?;
+    if off < 0 || off as usize % PAGE_SIZE_4K != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let flags = flags as u32;
+    let shared = match flags & ctypes::MAP_TYPE {
+        ctypes::MAP_SHARED | ctypes::MAP_SHARED_VALIDATE => true,
+        ctypes::MAP_PRIVATE => false,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let kernel = is_kernel(aspace);
+    let mut map_flags = prot_to_flags(prot);
+    if !kernel {
+        map_flags |= MappingFlags::USER;
+    }
+
+    let start = if flags & ctypes::MAP_FIXED != 0 {
+        let (start, len) = page_range(addr, len)?;
+        if start.as_usize() == 0 || !aspace.contains_range(start, len) {
+            return Err(LinuxError::EINVAL);
+        }
+        aspace.unmap(start, len)?;
+        start
+    } else {
+        let hint = if addr == 0 {
+            aspace.base() + aspace.size() / 2
+        } else {
+            VirtAddr::from(addr)
+        };
+        let limit = VirtAddrRange::from_start_size(aspace.base(), aspace.size());
+        aspace
+            .find_free_area(hint.align_down_4k(), len, limit)
+            .ok_or(LinuxError::ENOMEM)?
+    };
+
+    let populate = kernel || flags & ctypes::MAP_POPULATE != 0;
+    if flags & ctypes::MAP_ANONYMOUS != 0 {
+        aspace.map_alloc(start, len, map_flags, populate)?;
+        return Ok(start.as_usize());
+    }
+
+    // Shared mappings of shared memory objects map the pages of the objects,
+    // while private ones map them as files, i.e., get copies of them.
+    let file: Arc<dyn MappedFile> = match shared_pages(fd)? {
+        Some(pages) if shared => {
+            aspace.map_shared(start, len, map_flags, pages, off as usize)?;
+            return Ok(start.as_usize());
+        }
+        Some(pages) => pages,
+        None => mapped_file(fd)?,
+    };
+    aspace.map_file(start, len, map_flags, file, off as u64, shared)?;
+    let access_flags = map_flags & (MappingFlags::READ | MappingFlags::WRITE);
+    if populate && !access_flags.is_empty() {
+        if let Err(e) = aspace.populate(start, len, access_flags) {
+            aspace.unmap(start, len)?;
+            return Err(e.into());
+        }
+    }
+    Ok(start.as_usize())
+}
+
+/// Removes the mappings in the address space, like `munmap(2)`.
+///
+/// Changes to shared file mappings are written back to the files.
+pub fn aspace_munmap(aspace: &mut AddrSpace, addr: usize, len: usize) -> LinuxResult {
+    let (start, len) = page_range(addr, len)?;
+    if !aspace.contains_range(start, len) {
+        return Err(LinuxError::EINVAL);
+    }
+    aspace.unmap(start, len)?;
+    Ok(())
+}
+
+/// Writes the changes to shared file mappings in the address space back to
+/// the files, like `msync(2)`.
+///
+/// The writes are always synchronous, i.e., `MS_ASYNC` is treated as
+/// `MS_SYNC`.
+pub fn aspace_msync(aspace: &mut AddrSpace, addr: usize, len: usize, flags: c_int) -> LinuxResult {
+    let flags = flags as u32;
+    if flags & !(ctypes::MS_ASYNC | ctypes::MS_SYNC | ctypes::MS_INVALIDATE) != 0
+        || flags & (ctypes::MS_ASYNC | ctypes::MS_SYNC) == ctypes::MS_ASYNC | ctypes::MS_SYNC
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let (start, len) = page_range(addr, len)?;
+    if !aspace.contains_range(start, len) {
+        return Err(LinuxError::ENOMEM);
+    }
+    aspace.msync(start, len)?;
+    Ok(())
+}
+
+/// Creates a new mapping in the kernel address space.
+///
+/// Returns the start address of the mapping, or a negative error code cast
+/// to a pointer, as the `mmap` syscall does.
+pub fn sys_mmap(
+    addr: *mut c_void,
+    len: ctypes::size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> *mut c_void {
+    debug!(
+        "sys_mmap <= addr: {:#x}, len: {:#x}, prot: {:#x}, flags: {:#x}, fd: {}, off: {:#x}",
+        addr as usize, len, prot, flags, fd, off
+    );
+    syscall_body!(sys_mmap, {
+        let mut aspace = axmm::kernel_aspace().lock();
+        let start = aspace_mmap(&mut aspace, addr as _, len as _, prot, flags, fd, off)?;
+        Ok(start as *mut c_void)
+    })
+}
+
+/// Removes the mappings in the kernel address space.
+pub fn sys_munmap(addr: *mut c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_munmap <= addr: {:#x}, len: {:#x}", addr as usize, len);
+    syscall_body!(sys_munmap, {
+        aspace_munmap(&mut axmm::kernel_aspace().lock(), addr as _, len as _)?;
+        Ok(0)
+    })
+}
+
+/// Writes the changes to the shared file mappings in the kernel address space
+/// back to the files.
+pub fn sys_msync(addr: *mut c_void, len: ctypes::size_t, flags: c_int) -> c_int {
+    debug!(
+        "sys_msync <= addr: {:#x}, len: {:#x}, flags: {:#x}",
+        addr as usize, len, flags
+    );
+    syscall_body!(sys_msync, {
+        aspace_msync(
+            &mut axmm::kernel_aspace().lock(),
+            addr as _,
+            len as _,
+            flags,
+        )?;
+        Ok(0)
+    })
+}
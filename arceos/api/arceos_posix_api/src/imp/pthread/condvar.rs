@@ -0,0 +1,164 @@
+//! Condition variables, used along with [`PthreadMutex`]es.
+
+use core::ffi::c_int;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use axerrno::LinuxResult;
+use axtask::WaitQueue;
+
+use super::mutex::PthreadMutex;
+use super::{drop_lazy_object, lazy_object};
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+static_assertions::const_assert!(size_of::<ctypes::pthread_cond_t>() >= size_of::<PthreadCond>());
+
+struct CondInner {
+    wq: WaitQueue,
+    /// Incremented on every notification, so that a notification between
+    /// unlocking the mutex and blocking is not lost.
+    seq: AtomicU64,
+}
+
+impl Default for CondInner {
+    fn default() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The `pthread_cond_t` in C, whose first word points to the condition
+/// variable.
+#[repr(C)]
+pub struct PthreadCond(AtomicPtr<CondInner>);
+
+impl PthreadCond {
+    fn inner(&self) -> &CondInner {
+        lazy_object(&self.0)
+    }
+
+    /// Unlocks the mutex, blocks until notified or timed out, and locks the
+    /// mutex again.
+    ///
+    /// Returns `true` if timed out.
+    fn wait(
+        &self,
+        mutex: &PthreadMutex,
+        timeout: Option<core::time::Duration>,
+    ) -> LinuxResult<bool> {
+        let inner = self.inner();
+        let seq = inner.seq.load(Ordering::Acquire);
+        mutex.unlock()?;
+        let notified = || inner.seq.load(Ordering::Acquire) != seq;
+        let timed_out = match timeout {
+            #[cfg(feature = "irq")]
+            Some(dur) => inner.wq.wait_timeout_until(dur, notified),
+            _ => {
+                inner.wq.wait_until(notified);
+                false
+            }
+        };
+        mutex.lock()?;
+        Ok(timed_out)
+    }
+
+    fn notify(&self, all: bool) {
+        let inner = self.inner();
+        inner.seq.fetch_add(1, Ordering::Release);
+        if all {
+            inner.wq.notify_all(true);
+        } else {
+            inner.wq.notify_one(true);
+        }
+    }
+}
+
+unsafe fn cond_from<'a>(cond: *mut ctypes::pthread_cond_t) -> LinuxResult<&'a PthreadCond> {
+    check_null_mut_ptr(cond)?;
+    Ok(&*cond.cast::<PthreadCond>())
+}
+
+unsafe fn mutex_from<'a>(mutex: *mut ctypes::pthread_mutex_t) -> LinuxResult<&'a PthreadMutex> {
+    check_null_mut_ptr(mutex)?;
+    Ok(&*mutex.cast::<PthreadMutex>())
+}
+
+/// Initialize a condition variable.
+pub fn sys_pthread_cond_init(
+    cond: *mut ctypes::pthread_cond_t,
+    _attr: *const ctypes::pthread_condattr_t,
+) -> c_int {
+    debug!("sys_pthread_cond_init <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_init, {
+        check_null_mut_ptr(cond)?;
+        unsafe {
+            cond.write_bytes(0, 1);
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy a condition variable.
+pub fn sys_pthread_cond_destroy(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_destroy <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_destroy, {
+        unsafe { drop_lazy_object(&cond_from(cond)?.0) };
+        Ok(0)
+    })
+}
+
+/// Wait on a condition variable, with the mutex locked.
+pub fn sys_pthread_cond_wait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_wait <= {:#x} {:#x}",
+        cond as usize, mutex as usize
+    );
+    syscall_body!(sys_pthread_cond_wait, {
+        unsafe { cond_from(cond)?.wait(mutex_from(mutex)?, None)? };
+        Ok(0)
+    })
+}
+
+/// Wait on a condition variable until the absolute `CLOCK_REALTIME`
+/// deadline, with the mutex locked.
+#[cfg(feature = "irq")]
+pub fn sys_pthread_cond_timedwait(
+    cond: *mut ctypes::pthread_cond_t,
+    mutex: *mut ctypes::pthread_mutex_t,
+    abstime: *const ctypes::timespec,
+) -> c_int {
+    debug!(
+        "sys_pthread_cond_timedwait <= {:#x} {:#x}",
+        cond as usize, mutex as usize
+    );
+    syscall_body!(sys_pthread_cond_timedwait, {
+        let timeout = super::timeout_until(abstime)?;
+        if unsafe { cond_from(cond)?.wait(mutex_from(mutex)?, Some(timeout))? } {
+            return Err(axerrno::LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Wake up one task waiting on a condition variable.
+pub fn sys_pthread_cond_signal(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_signal <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_signal, {
+        unsafe { cond_from(cond)?.notify(false) };
+        Ok(0)
+    })
+}
+
+/// Wake up all tasks waiting on a condition variable.
+pub fn sys_pthread_cond_broadcast(cond: *mut ctypes::pthread_cond_t) -> c_int {
+    debug!("sys_pthread_cond_broadcast <= {:#x}", cond as usize);
+    syscall_body!(sys_pthread_cond_broadcast, {
+        unsafe { cond_from(cond)?.notify(true) };
+        Ok(0)
+    })
+}
@@ -0,0 +1,134 @@
+//! Thread-specific data, backed by the task-local storage of axtask.
+
+use alloc::collections::BTreeMap;
+use core::cell::RefCell;
+use core::ffi::{c_int, c_void};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+/// The maximum number of keys, `PTHREAD_KEYS_MAX` in C.
+const PTHREAD_KEYS_MAX: usize = 128;
+
+type Destructor = unsafe extern "C" fn(*mut c_void);
+
+#[derive(Clone, Copy)]
+struct KeySlot {
+    in_use: bool,
+    /// Incremented on every creation, so that values of deleted keys are not
+    /// visible to a new key in the same slot.
+    seq: u64,
+    dtor: Option<Destructor>,
+}
+
+static KEYS: Mutex<[KeySlot; PTHREAD_KEYS_MAX]> = Mutex::new(
+    [KeySlot {
+        in_use: false,
+        seq: 0,
+        dtor: None,
+    }; PTHREAD_KEYS_MAX],
+);
+
+/// Returns the sequence number and the destructor of the key, if it is in use.
+fn key_slot(key: ctypes::pthread_key_t) -> LinuxResult<KeySlot> {
+    KEYS.lock()
+        .get(key as usize)
+        .copied()
+        .filter(|slot| slot.in_use)
+        .ok_or(LinuxError::EINVAL)
+}
+
+/// The thread-specific values of a thread, indexed by keys, along with the
+/// sequence numbers of the keys when they were set.
+struct Specific(RefCell<BTreeMap<ctypes::pthread_key_t, (u64, *mut c_void)>>);
+
+impl Drop for Specific {
+    fn drop(&mut self) {
+        for (key, (seq, value)) in core::mem::take(self.0.get_mut()) {
+            if value.is_null() {
+                continue;
+            }
+            match key_slot(key) {
+                Ok(KeySlot {
+                    seq: key_seq,
+                    dtor: Some(dtor),
+                    ..
+                }) if key_seq == seq => unsafe { dtor(value) },
+                _ => {}
+            }
+        }
+    }
+}
+
+axtask::task_local! {
+    static SPECIFIC: Specific = Specific(RefCell::new(BTreeMap::new()));
+}
+
+/// Creates a key for thread-specific data, with an optional destructor which
+/// is called with the non-null value of the key when a thread exits.
+pub unsafe fn sys_pthread_key_create(
+    key: *mut ctypes::pthread_key_t,
+    dtor: Option<Destructor>,
+) -> c_int {
+    debug!("sys_pthread_key_create <= {:#x}", key as usize);
+    syscall_body!(sys_pthread_key_create, {
+        check_null_mut_ptr(key)?;
+        let mut keys = KEYS.lock();
+        let (idx, slot) = keys
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| !slot.in_use)
+            .ok_or(LinuxError::EAGAIN)?;
+        slot.in_use = true;
+        slot.seq += 1;
+        slot.dtor = dtor;
+        unsafe { key.write(idx as _) };
+        Ok(0)
+    })
+}
+
+/// Deletes a key for thread-specific data.
+///
+/// Destructors are not called for the values of the key.
+pub fn sys_pthread_key_delete(key: ctypes::pthread_key_t) -> c_int {
+    debug!("sys_pthread_key_delete <= {}", key);
+    syscall_body!(sys_pthread_key_delete, {
+        let mut keys = KEYS.lock();
+        let slot = keys
+            .get_mut(key as usize)
+            .filter(|slot| slot.in_use)
+            .ok_or(LinuxError::EINVAL)?;
+        slot.in_use = false;
+        slot.dtor = None;
+        Ok(0)
+    })
+}
+
+/// Returns the value of the key in the current thread, or null if it is not
+/// set or the key is invalid.
+pub fn sys_pthread_getspecific(key: ctypes::pthread_key_t) -> *mut c_void {
+    let Ok(slot) = key_slot(key) else {
+        return core::ptr::null_mut();
+    };
+    SPECIFIC.with(|specific| match specific.0.borrow().get(&key) {
+        Some(&(seq, value)) if seq == slot.seq => value,
+        _ => core::ptr::null_mut(),
+    })
+}
+
+/// Sets the value of the key in the current thread.
+pub fn sys_pthread_setspecific(key: ctypes::pthread_key_t, value: *const c_void) -> c_int {
+    debug!("sys_pthread_setspecific <= {}, {:#x}", key, value as usize);
+    syscall_body!(sys_pthread_setspecific, {
+        let slot = key_slot(key)?;
+        SPECIFIC.with(|specific| {
+            specific
+                .0
+                .borrow_mut()
+                .insert(key, (slot.seq, value as *mut c_void))
+        });
+        Ok(0)
+    })
+}
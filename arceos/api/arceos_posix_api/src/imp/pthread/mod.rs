@@ -1,14 +1,21 @@
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::cell::UnsafeCell;
 use core::ffi::{c_int, c_void};
+use core::sync::atomic::{AtomicPtr, Ordering};
 
 use axerrno::{LinuxError, LinuxResult};
+use axsync::RwLock;
 use axtask::AxTaskRef;
-use spin::RwLock;
 
 use crate::ctypes;
 
+pub mod condvar;
+pub mod key;
 pub mod mutex;
+pub mod rwlock;
+
+/// The return value of cancelled threads, `PTHREAD_CANCELED` in C.
+const PTHREAD_CANCELED: *mut c_void = -1isize as _;
 
 lazy_static::lazy_static! {
     static ref TID_TO_PTHREAD: RwLock<BTreeMap<u64, ForceSendSync<ctypes::pthread_t>>> = {
@@ -27,6 +34,55 @@ lazy_static::lazy_static! {
     };
 }
 
+/// Returns the object pointed to by the first word of a C synchronization
+/// object, allocating it on first use.
+///
+/// Thus an all-zero C object (e.g., `PTHREAD_COND_INITIALIZER`) is valid.
+pub(crate) fn lazy_object<T: Default>(slot: &AtomicPtr<T>) -> &T {
+    let mut ptr = slot.load(Ordering::Acquire);
+    if ptr.is_null() {
+        let new = Box::into_raw(Box::default());
+        ptr = match slot.compare_exchange(
+            core::ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(old) => {
+                drop(unsafe { Box::from_raw(new) });
+                old
+            }
+        };
+    }
+    unsafe { &*ptr }
+}
+
+/// Frees the object allocated by [`lazy_object`], and resets the C object to
+/// all-zero.
+///
+/// # Safety
+///
+/// The object must not be in use.
+pub(crate) unsafe fn drop_lazy_object<T>(slot: &AtomicPtr<T>) {
+    let ptr = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr));
+    }
+}
+
+/// Converts an absolute `CLOCK_REALTIME` deadline to a timeout from now.
+#[cfg(feature = "irq")]
+pub(crate) fn timeout_until(abstime: *const ctypes::timespec) -> LinuxResult<core::time::Duration> {
+    crate::utils::check_null_ptr(abstime)?;
+    let abstime = unsafe { *abstime };
+    if !(0..1_000_000_000).contains(&abstime.tv_nsec) || abstime.tv_sec < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let deadline = core::time::Duration::from(abstime);
+    Ok(deadline.saturating_sub(axhal::time::wall_time()))
+}
+
 struct Packet<T> {
     result: UnsafeCell<T>,
 }
@@ -88,13 +144,30 @@ impl Pthread {
         axtask::exit(0);
     }
 
+    fn cancel(ptr: ctypes::pthread_t) -> LinuxResult {
+        // Hold the lock while using the thread, as a concurrent `join` frees
+        // it after removing it from the map.
+        let threads = TID_TO_PTHREAD.read();
+        if !threads.values().any(|thread| core::ptr::eq(thread.0, ptr)) {
+            return Err(LinuxError::ESRCH);
+        }
+        let thread = unsafe { &*(ptr as *const Pthread) };
+        thread.inner.cancel();
+        Ok(())
+    }
+
     fn join(ptr: ctypes::pthread_t) -> LinuxResult<*mut c_void> {
         if core::ptr::eq(ptr, Self::current_ptr() as _) {
             return Err(LinuxError::EDEADLK);
         }
 
+        if unsafe { &*(ptr as *const Pthread) }.inner.join().is_none() {
+            // `pthread_join` is a cancellation point, and the thread can still
+            // be joined later if the current one is not a pthread.
+            sys_pthread_testcancel();
+            return Err(LinuxError::EINTR);
+        }
         let thread = unsafe { Box::from_raw(ptr as *mut Pthread) };
-        thread.inner.join();
         let tid = thread.inner.id().as_u64();
         let retval = unsafe { *thread.retval.result.get() };
         TID_TO_PTHREAD.write().remove(&tid);
@@ -147,6 +220,28 @@ pub unsafe fn sys_pthread_join(thread: ctypes::pthread_t, retval: *mut *mut c_vo
     })
 }
 
+/// Requests the given thread to be cancelled.
+///
+/// Only deferred cancellation is supported: the thread exits with
+/// `PTHREAD_CANCELED` at the next cancellation point, i.e., calling
+/// [`sys_pthread_testcancel`] or being woken up from `nanosleep` or
+/// [`sys_pthread_join`].
+pub fn sys_pthread_cancel(thread: ctypes::pthread_t) -> c_int {
+    debug!("sys_pthread_cancel <= {:#x}", thread as usize);
+    syscall_body!(sys_pthread_cancel, {
+        Pthread::cancel(thread)?;
+        Ok(0)
+    })
+}
+
+/// Exits the current thread with `PTHREAD_CANCELED` if it has been
+/// cancelled.
+pub fn sys_pthread_testcancel() {
+    if axtask::current().is_cancelled() && Pthread::current().is_some() {
+        Pthread::exit_current(PTHREAD_CANCELED);
+    }
+}
+
 #[derive(Clone, Copy)]
 struct ForceSendSync<T>(T);
 
@@ -0,0 +1,136 @@
+//! Reader-writer locks, backed by [`axsync::RwLock`].
+
+use core::ffi::c_int;
+use core::mem::{forget, size_of};
+use core::sync::atomic::AtomicPtr;
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::RwLock;
+
+use super::{drop_lazy_object, lazy_object};
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+static_assertions::const_assert!(
+    size_of::<ctypes::pthread_rwlock_t>() >= size_of::<PthreadRwLock>()
+);
+
+/// The `pthread_rwlock_t` in C, whose first word points to the lock.
+#[repr(C)]
+pub struct PthreadRwLock(AtomicPtr<RwLock<()>>);
+
+impl PthreadRwLock {
+    fn lock(&self) -> &RwLock<()> {
+        lazy_object(&self.0)
+    }
+
+    fn rdlock(&self) -> LinuxResult {
+        forget(self.lock().read());
+        Ok(())
+    }
+
+    fn tryrdlock(&self) -> LinuxResult {
+        forget(self.lock().try_read().ok_or(LinuxError::EBUSY)?);
+        Ok(())
+    }
+
+    fn wrlock(&self) -> LinuxResult {
+        forget(self.lock().write());
+        Ok(())
+    }
+
+    fn trywrlock(&self) -> LinuxResult {
+        forget(self.lock().try_write().ok_or(LinuxError::EBUSY)?);
+        Ok(())
+    }
+
+    fn unlock(&self) -> LinuxResult {
+        let lock = self.lock();
+        if lock.is_write_locked() {
+            unsafe { lock.force_write_unlock() };
+        } else if lock.reader_count() > 0 {
+            unsafe { lock.force_read_unlock() };
+        } else {
+            return Err(LinuxError::EPERM);
+        }
+        Ok(())
+    }
+}
+
+unsafe fn rwlock_from<'a>(rwlock: *mut ctypes::pthread_rwlock_t) -> LinuxResult<&'a PthreadRwLock> {
+    check_null_mut_ptr(rwlock)?;
+    Ok(&*rwlock.cast::<PthreadRwLock>())
+}
+
+/// Initialize a reader-writer lock.
+pub fn sys_pthread_rwlock_init(
+    rwlock: *mut ctypes::pthread_rwlock_t,
+    _attr: *const ctypes::pthread_rwlockattr_t,
+) -> c_int {
+    debug!("sys_pthread_rwlock_init <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_init, {
+        check_null_mut_ptr(rwlock)?;
+        unsafe {
+            rwlock.write_bytes(0, 1);
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy a reader-writer lock.
+pub fn sys_pthread_rwlock_destroy(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_destroy <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_destroy, {
+        let rwlock = unsafe { rwlock_from(rwlock)? };
+        let lock = rwlock.lock();
+        if lock.is_write_locked() || lock.reader_count() > 0 {
+            return Err(LinuxError::EBUSY);
+        }
+        unsafe { drop_lazy_object(&rwlock.0) };
+        Ok(0)
+    })
+}
+
+/// Lock a reader-writer lock for reading.
+pub fn sys_pthread_rwlock_rdlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_rdlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_rdlock, {
+        unsafe { rwlock_from(rwlock)?.rdlock()? };
+        Ok(0)
+    })
+}
+
+/// Try to lock a reader-writer lock for reading without blocking.
+pub fn sys_pthread_rwlock_tryrdlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_tryrdlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_tryrdlock, {
+        unsafe { rwlock_from(rwlock)?.tryrdlock()? };
+        Ok(0)
+    })
+}
+
+/// Lock a reader-writer lock for writing.
+pub fn sys_pthread_rwlock_wrlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_wrlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_wrlock, {
+        unsafe { rwlock_from(rwlock)?.wrlock()? };
+        Ok(0)
+    })
+}
+
+/// Try to lock a reader-writer lock for writing without blocking.
+pub fn sys_pthread_rwlock_trywrlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_trywrlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_trywrlock, {
+        unsafe { rwlock_from(rwlock)?.trywrlock()? };
+        Ok(0)
+    })
+}
+
+/// Unlock a reader-writer lock.
+pub fn sys_pthread_rwlock_unlock(rwlock: *mut ctypes::pthread_rwlock_t) -> c_int {
+    debug!("sys_pthread_rwlock_unlock <= {:#x}", rwlock as usize);
+    syscall_body!(sys_pthread_rwlock_unlock, {
+        unsafe { rwlock_from(rwlock)?.unlock()? };
+        Ok(0)
+    })
+}
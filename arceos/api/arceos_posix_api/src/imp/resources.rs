@@ -1,6 +1,7 @@
 use crate::ctypes;
 use axerrno::LinuxError;
 use core::ffi::c_int;
+use core::time::Duration;
 
 /// Get resource limitations
 ///
@@ -49,3 +50,51 @@ pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit
         Ok(0)
     })
 }
+
+/// Get resource usage
+///
+/// Only the user and system CPU time are reported, other fields are zero.
+pub unsafe fn sys_getrusage(who: c_int, usage: *mut ctypes::rusage) -> c_int {
+    debug!("sys_getrusage <= {} {:#x}", who, usage as usize);
+    syscall_body!(sys_getrusage, {
+        if usage.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let (utime, stime) = if who == ctypes::RUSAGE_SELF as c_int {
+            process_cpu_times()
+        } else if who == ctypes::RUSAGE_THREAD as c_int {
+            thread_cpu_times()
+        } else if who == ctypes::RUSAGE_CHILDREN as c_int {
+            (Duration::ZERO, Duration::ZERO)
+        } else {
+            return Err(LinuxError::EINVAL);
+        };
+        unsafe {
+            *usage = core::mem::zeroed();
+            (*usage).ru_utime = utime.into();
+            (*usage).ru_stime = stime.into();
+        }
+        Ok(0)
+    })
+}
+
+/// Returns the user and system CPU time of all threads.
+fn process_cpu_times() -> (Duration, Duration) {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::total_cpu_times()
+    }
+    #[cfg(not(feature = "multitask"))]
+    (Duration::ZERO, super::time::process_cpu_time())
+}
+
+/// Returns the user and system CPU time of the calling thread.
+fn thread_cpu_times() -> (Duration, Duration) {
+    #[cfg(feature = "multitask")]
+    {
+        let curr = axtask::current();
+        (curr.user_time(), curr.kernel_time())
+    }
+    #[cfg(not(feature = "multitask"))]
+    (Duration::ZERO, super::time::thread_cpu_time())
+}
@@ -0,0 +1,123 @@
+//! Unnamed POSIX semaphores, backed by [`axsync::Semaphore`].
+
+use alloc::boxed::Box;
+use core::ffi::{c_int, c_uint};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Semaphore;
+
+use crate::{ctypes, utils::check_null_mut_ptr};
+
+/// The maximum value of a semaphore, `SEM_VALUE_MAX` in C.
+const SEM_VALUE_MAX: c_uint = i32::MAX as c_uint;
+
+static_assertions::const_assert!(size_of::<ctypes::sem_t>() >= size_of::<PosixSemaphore>());
+
+/// The `sem_t` in C, whose first word points to the semaphore.
+#[repr(C)]
+struct PosixSemaphore(AtomicPtr<Semaphore>);
+
+unsafe fn sem_from<'a>(sem: *mut ctypes::sem_t) -> LinuxResult<&'a Semaphore> {
+    check_null_mut_ptr(sem)?;
+    let ptr = (*sem.cast::<PosixSemaphore>()).0.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(&*ptr)
+}
+
+/// Initialize an unnamed semaphore with the given value.
+///
+/// Semaphores shared between processes are not supported, `pshared` is
+/// ignored.
+pub fn sys_sem_init(sem: *mut ctypes::sem_t, _pshared: c_int, value: c_uint) -> c_int {
+    debug!("sys_sem_init <= {:#x} {}", sem as usize, value);
+    syscall_body!(sys_sem_init, {
+        check_null_mut_ptr(sem)?;
+        if value > SEM_VALUE_MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        let ptr = Box::into_raw(Box::new(Semaphore::new(value as usize)));
+        unsafe {
+            sem.write_bytes(0, 1);
+            sem.cast::<PosixSemaphore>()
+                .write(PosixSemaphore(AtomicPtr::new(ptr)));
+        }
+        Ok(0)
+    })
+}
+
+/// Destroy an unnamed semaphore.
+pub fn sys_sem_destroy(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_destroy <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_destroy, {
+        check_null_mut_ptr(sem)?;
+        let ptr = unsafe { &(*sem.cast::<PosixSemaphore>()).0 }
+            .swap(core::ptr::null_mut(), Ordering::AcqRel);
+        if ptr.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        drop(unsafe { Box::from_raw(ptr) });
+        Ok(0)
+    })
+}
+
+/// Decrement a semaphore, blocking until its value is positive.
+pub fn sys_sem_wait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_wait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_wait, {
+        unsafe { sem_from(sem)? }.acquire();
+        Ok(0)
+    })
+}
+
+/// Decrement a semaphore if its value is positive, without blocking.
+pub fn sys_sem_trywait(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_trywait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_trywait, {
+        if !unsafe { sem_from(sem)? }.try_acquire() {
+            return Err(LinuxError::EAGAIN);
+        }
+        Ok(0)
+    })
+}
+
+/// Decrement a semaphore, blocking until its value is positive or the
+/// absolute `CLOCK_REALTIME` deadline has passed.
+#[cfg(feature = "irq")]
+pub fn sys_sem_timedwait(sem: *mut ctypes::sem_t, abstime: *const ctypes::timespec) -> c_int {
+    debug!("sys_sem_timedwait <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_timedwait, {
+        let sem = unsafe { sem_from(sem)? };
+        let timeout = super::pthread::timeout_until(abstime)?;
+        if !sem.acquire_timeout(timeout) {
+            return Err(LinuxError::ETIMEDOUT);
+        }
+        Ok(0)
+    })
+}
+
+/// Increment a semaphore, and wake up a task waiting on it.
+pub fn sys_sem_post(sem: *mut ctypes::sem_t) -> c_int {
+    debug!("sys_sem_post <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_post, {
+        let sem = unsafe { sem_from(sem)? };
+        if !sem.release_bounded(SEM_VALUE_MAX as usize) {
+            return Err(LinuxError::EOVERFLOW);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the value of a semaphore.
+pub fn sys_sem_getvalue(sem: *mut ctypes::sem_t, sval: *mut c_int) -> c_int {
+    debug!("sys_sem_getvalue <= {:#x}", sem as usize);
+    syscall_body!(sys_sem_getvalue, {
+        check_null_mut_ptr(sval)?;
+        let value = unsafe { sem_from(sem)? }.count();
+        unsafe { *sval = value as c_int };
+        Ok(0)
+    })
+}
@@ -1,5 +1,13 @@
 use core::ffi::c_int;
 
+#[cfg(feature = "multitask")]
+use axerrno::{LinuxError, LinuxResult};
+#[cfg(feature = "multitask")]
+use core::mem::size_of;
+
+#[cfg(feature = "multitask")]
+use crate::ctypes;
+
 /// Relinquish the CPU, and switches to another task.
 ///
 /// For single-threaded configuration (`multitask` feature is disabled), we just
@@ -38,3 +46,83 @@ pub fn sys_exit(exit_code: c_int) -> ! {
     #[cfg(not(feature = "multitask"))]
     axhal::misc::terminate();
 }
+
+/// Set the CPU affinity mask of the thread whose ID is `pid`.
+///
+/// Only the calling thread (`pid` is 0 or its own ID) is supported.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_sched_setaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    syscall_body!(sys_sched_setaffinity, {
+        check_affinity_target(pid)?;
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                mask as *const u8,
+                cpusetsize.min(size_of::<ctypes::cpu_set_t>()),
+            )
+        };
+        let mut cpumask = axtask::AxCpuMask::new();
+        for cpu_id in 0..axconfig::SMP.min(bytes.len() * 8) {
+            cpumask.set(cpu_id, bytes[cpu_id / 8] & (1 << (cpu_id % 8)) != 0);
+        }
+        if axtask::set_affinity(cpumask) {
+            Ok(0)
+        } else {
+            Err(LinuxError::EINVAL)
+        }
+    })
+}
+
+/// Get the CPU affinity mask of the thread whose ID is `pid`.
+///
+/// Only the calling thread (`pid` is 0 or its own ID) is supported.
+#[cfg(feature = "multitask")]
+pub unsafe fn sys_sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_sched_getaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    syscall_body!(sys_sched_getaffinity, {
+        check_affinity_target(pid)?;
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if cpusetsize * 8 < axconfig::SMP {
+            return Err(LinuxError::EINVAL);
+        }
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                mask as *mut u8,
+                cpusetsize.min(size_of::<ctypes::cpu_set_t>()),
+            )
+        };
+        bytes.fill(0);
+        for cpu_id in axtask::current().cpumask().iter() {
+            bytes[cpu_id / 8] |= 1 << (cpu_id % 8);
+        }
+        Ok(0)
+    })
+}
+
+#[cfg(feature = "multitask")]
+fn check_affinity_target(pid: c_int) -> LinuxResult {
+    if pid == 0 || pid as u64 == axtask::current().id().as_u64() {
+        Ok(())
+    } else {
+        Err(LinuxError::ESRCH)
+    }
+}
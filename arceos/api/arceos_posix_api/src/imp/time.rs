@@ -3,7 +3,9 @@ use core::ffi::{c_int, c_long};
 use core::time::Duration;
 
 use crate::ctypes;
-use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::ctypes::{
+    CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID,
+};
 
 impl From<ctypes::timespec> for Duration {
     fn from(ts: ctypes::timespec) -> Self {
@@ -35,6 +37,28 @@ impl From<Duration> for ctypes::timeval {
     }
 }
 
+/// Returns the CPU time consumed by all threads.
+pub(crate) fn process_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        let (user, kernel) = axtask::total_cpu_times();
+        user + kernel
+    }
+    // The only thread is always running.
+    #[cfg(not(feature = "multitask"))]
+    axhal::time::monotonic_time()
+}
+
+/// Returns the CPU time consumed by the calling thread.
+pub(crate) fn thread_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().run_time()
+    }
+    #[cfg(not(feature = "multitask"))]
+    axhal::time::monotonic_time()
+}
+
 /// Get clock time since booting
 pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_clock_gettime, {
@@ -44,6 +68,8 @@ pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespe
         let now = match clk as u32 {
             CLOCK_REALTIME => axhal::time::wall_time().into(),
             CLOCK_MONOTONIC => axhal::time::monotonic_time().into(),
+            CLOCK_PROCESS_CPUTIME_ID => process_cpu_time().into(),
+            CLOCK_THREAD_CPUTIME_ID => thread_cpu_time().into(),
             _ => {
                 warn!("Called sys_clock_gettime for unsupported clock {}", clk);
                 return Err(LinuxError::EINVAL);
@@ -74,7 +100,10 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
         let now = axhal::time::monotonic_time();
 
         #[cfg(feature = "multitask")]
-        axtask::sleep(dur);
+        if axtask::sleep_interruptible(dur).is_err() {
+            // `nanosleep` is a cancellation point.
+            crate::imp::pthread::sys_pthread_testcancel();
+        }
         #[cfg(not(feature = "multitask"))]
         axhal::time::busy_wait(dur);
 
@@ -14,11 +14,19 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `swap`: Enable swapping of anonymous pages to a block device, or to
+//!       a swap file if `fs` is enabled.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_edf`: Use the Earliest Deadline First (EDF) real-time scheduler.
+//!     - `async`: Enable the async executor, and asynchronous sleep and socket I/O.
+//!     - `watchdog`: Enable the soft-lockup and hung-task watchdog.
+//!     - `task_group`: Enable task groups with CPU bandwidth quotas.
+//!     - `lockdep`: Enable the lock dependency validator.
+//!     - `lock-stat`: Collect lock contention statistics.
 //! - Upperlayer stacks (fs, net, display)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
@@ -5,6 +5,12 @@ use std::{string::String, vec::Vec};
 #[cfg(all(not(feature = "axstd"), unix))]
 use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 
+#[cfg(all(feature = "axstd", feature = "multitask"))]
+use std::os::arceos::api::task::{ax_task_snapshot, AxTaskSnapshot};
+
+#[cfg(all(feature = "axstd", feature = "lock-stat"))]
+use std::os::arceos::api::sys::{ax_lock_stat_reset, ax_lock_stat_top, AxLockStatEntry};
+
 macro_rules! print_err {
     ($cmd: literal, $msg: expr) => {
         println!("{}: {}", $cmd, $msg);
@@ -22,10 +28,16 @@ const CMD_TABLE: &[(&str, CmdHandler)] = &[
     ("echo", do_echo),
     ("exit", do_exit),
     ("help", do_help),
+    #[cfg(all(feature = "axstd", feature = "lock-stat"))]
+    ("lockstat", do_lockstat),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    #[cfg(all(feature = "axstd", feature = "multitask"))]
+    ("ps", do_ps),
     ("pwd", do_pwd),
     ("rm", do_rm),
+    #[cfg(all(feature = "axstd", feature = "multitask"))]
+    ("top", do_top),
     ("uname", do_uname),
 ];
 
@@ -260,6 +272,108 @@ fn do_uname(_args: &str) {
     );
 }
 
+#[cfg(all(feature = "axstd", feature = "multitask"))]
+fn do_ps(_args: &str) {
+    println!(
+        "{:>5} {:>3} {:<8} {:>4} {:>12} {:>8} {:>13} NAME",
+        "ID", "CPU", "STATE", "PRIO", "TIME(ms)", "CSW", "STACK"
+    );
+    for t in ax_task_snapshot() {
+        let stack = if t.stack_size > 0 {
+            format!("{}/{}", t.stack_high_water, t.stack_size)
+        } else {
+            String::from("-")
+        };
+        println!(
+            "{:>5} {:>3} {:<8} {:>4} {:>12} {:>8} {:>13} {}",
+            t.id,
+            t.cpu_id,
+            format!("{:?}", t.state),
+            t.priority,
+            t.run_time.as_millis(),
+            t.ctx_switches,
+            stack,
+            t.name,
+        );
+    }
+}
+
+#[cfg(all(feature = "axstd", feature = "multitask"))]
+fn do_top(args: &str) {
+    use std::time::{Duration, Instant};
+
+    let rounds = match args {
+        "" => 1,
+        n => match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                print_err!("top", args, "invalid number of rounds");
+                return;
+            }
+        },
+    };
+
+    let find = |prev: &[AxTaskSnapshot], id| prev.iter().find(|t| t.id == id).map(|t| t.run_time);
+    let mut prev = ax_task_snapshot();
+    let mut prev_time = Instant::now();
+    for _ in 0..rounds {
+        std::thread::sleep(Duration::from_secs(1));
+        let now = Instant::now();
+        let interval = (now - prev_time).as_nanos().max(1);
+        let curr = ax_task_snapshot();
+
+        let mut usage = curr
+            .iter()
+            .map(|t| {
+                let delta = t
+                    .run_time
+                    .saturating_sub(find(&prev, t.id).unwrap_or_default());
+                (delta.as_nanos() * 1000 / interval, t)
+            })
+            .collect::<Vec<_>>();
+        usage.sort_by(|a, b| b.0.cmp(&a.0));
+
+        println!("{} tasks", curr.len());
+        println!("{:>5} {:>3} {:<8} {:>6} NAME", "ID", "CPU", "STATE", "%CPU");
+        for (permille, t) in usage {
+            println!(
+                "{:>5} {:>3} {:<8} {:>4}.{} {}",
+                t.id,
+                t.cpu_id,
+                format!("{:?}", t.state),
+                permille / 10,
+                permille % 10,
+                t.name,
+            );
+        }
+        println!();
+        prev = curr;
+        prev_time = now;
+    }
+}
+
+#[cfg(all(feature = "axstd", feature = "lock-stat"))]
+fn do_lockstat(args: &str) {
+    let n = match args {
+        "" => 10,
+        "reset" => {
+            ax_lock_stat_reset();
+            return;
+        }
+        n => match n.parse::<usize>() {
+            Ok(n) => n,
+            _ => {
+                print_err!("lockstat", args, "invalid number of locks");
+                return;
+            }
+        },
+    };
+    println!("{}", AxLockStatEntry::HEADER);
+    for entry in ax_lock_stat_top(n) {
+        println!("{}", entry);
+    }
+}
+
 fn do_help(_args: &str) {
     println!("Available commands:");
     for (name, _) in CMD_TABLE {
@@ -0,0 +1,94 @@
+//! Swaps pages of user address spaces out to the RAM disk and back, with
+//! more pages mapped than the memory can hold.
+//!
+//! Run with `make run A=exercises/swap_pages`.
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+extern crate alloc;
+
+use alloc::sync::Arc;
+
+use axalloc::global_allocator;
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use axsync::Mutex;
+
+const PAGE_SIZE: usize = 0x1000;
+const FLAGS: MappingFlags = MappingFlags::READ
+    .union(MappingFlags::WRITE)
+    .union(MappingFlags::USER);
+
+/// Creates a user address space with a lazy mapping of `num_pages` pages,
+/// whose pages can be swapped out for others.
+fn new_aspace(num_pages: usize) -> Arc<Mutex<AddrSpace>> {
+    let mut aspace = axmm::new_user_aspace().unwrap();
+    aspace
+        .map_alloc(aspace.base(), num_pages * PAGE_SIZE, FLAGS, false)
+        .unwrap();
+    let aspace = Arc::new(Mutex::new(aspace));
+    axmm::register_swappable(&aspace, |aspace, n| {
+        aspace
+            .try_lock()
+            .map_or(0, |mut aspace| aspace.swap_out_pages(n))
+    });
+    aspace
+}
+
+/// Writes `tag + i` to the `i`-th page, faulting it in like a user task does.
+///
+/// The address space is only locked for each page, so that its pages can be
+/// swapped out for the others in the meantime.
+fn fill(aspace: &Mutex<AddrSpace>, num_pages: usize, tag: usize) {
+    for i in 0..num_pages {
+        let mut aspace = aspace.lock();
+        let vaddr = aspace.base() + i * PAGE_SIZE;
+        assert!(aspace.handle_page_fault(vaddr, MappingFlags::WRITE));
+        aspace.write(vaddr, &(tag + i).to_ne_bytes()).unwrap();
+    }
+}
+
+/// Checks the pages written by [`fill`], swapping them in if needed.
+fn check(aspace: &Mutex<AddrSpace>, num_pages: usize, tag: usize) {
+    for i in 0..num_pages {
+        let mut aspace = aspace.lock();
+        let vaddr = aspace.base() + i * PAGE_SIZE;
+        let mut buf = [0; 8];
+        if aspace.read(vaddr, &mut buf).is_err() {
+            assert!(aspace.handle_page_fault(vaddr, MappingFlags::READ));
+            aspace.read(vaddr, &mut buf).unwrap();
+        }
+        assert_eq!(usize::from_ne_bytes(buf), tag + i);
+    }
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Running swap tests...");
+    let available = global_allocator().available_pages();
+
+    // The first address space takes almost all the memory.
+    let num_pages_a = available - 256;
+    let a = new_aspace(num_pages_a);
+    fill(&a, num_pages_a, 0);
+    println!("filled {} pages", num_pages_a);
+
+    // The second one swaps out pages of the first, or its own.
+    let num_pages_b = 1024;
+    let b = new_aspace(num_pages_b);
+    fill(&b, num_pages_b, num_pages_a);
+    println!("filled another {} pages", num_pages_b);
+
+    // Populated mappings swap out pages of the others as well.
+    let mut c = axmm::new_user_aspace().unwrap();
+    c.map_alloc(c.base(), 512 * PAGE_SIZE, FLAGS, true).unwrap();
+    drop(c);
+
+    check(&a, num_pages_a, 0);
+    check(&b, num_pages_b, num_pages_a);
+    println!("Swap tests run OK!");
+}
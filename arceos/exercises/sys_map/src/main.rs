@@ -11,6 +11,8 @@ extern crate axlog;
 mod task;
 mod syscall;
 mod loader;
+mod futex;
+mod shm;
 
 use axstd::io;
 use axhal::paging::MappingFlags;
@@ -21,6 +23,8 @@ use alloc::sync::Arc;
 use alloc::string::String;
 use alloc::collections::BTreeMap;
 use axmm::AddrSpace;
+use axhal::trap::{register_trap_handler, PAGE_FAULT};
+use axtask::TaskExtRef;
 use loader::load_user_app;
 
 const USER_STACK_SIZE: usize = 0x10000;
@@ -31,8 +35,10 @@ fn main() {
     // A new address space for user app.
     let mut uspace = axmm::new_user_aspace().unwrap();
 
-    // Load user app binary file into address space.
-    let entry = match load_user_app("/sbin/mapfile", &mut uspace) {
+    // Load user app binary file into address space. Another app can be
+    // selected by `USER_APP` at build time, e.g., `/sbin/shm`.
+    let app = option_env!("USER_APP").unwrap_or("/sbin/mapfile");
+    let entry = match load_user_app(app, &mut uspace) {
         Ok(e) => e,
         Err(err) => panic!("Cannot load app! {:?}", err),
     };
@@ -53,6 +59,24 @@ fn main() {
     ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
 }
 
+#[register_trap_handler(PAGE_FAULT)]
+fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+    let curr = axtask::current();
+    // Kernel tasks have no user address space.
+    if unsafe { curr.task_ext_ptr() }.is_null() {
+        return false;
+    }
+    // Faults in the kernel come from syscalls accessing lazy `mmap` pages.
+    if !curr.task_ext().aspace.lock().handle_page_fault(vaddr, access_flags) {
+        if !is_user {
+            return false;
+        }
+        ax_println!("{}: segmentation fault, exit!", curr.id_name());
+        axtask::exit(-1);
+    }
+    true
+}
+
 fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
     let ustack_top = uspace.end();
     let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
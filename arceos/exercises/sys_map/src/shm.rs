@@ -0,0 +1,230 @@
+//! Shared memory: POSIX named objects opened by `shm_open`, and System V
+//! shared memory segments.
+//!
+//! Both of them are backed by [`SharedPages`], which are freed when the last
+//! mapping and the last name of them go away.
+
+use core::ffi::{c_char, c_int, CStr};
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use arceos_posix_api::{ctypes, SharedMemory};
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::SharedPages;
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+use memory_addr::VirtAddrRange;
+
+/// The directory of named shared memory objects, as on Linux, where
+/// `shm_open` opens `/dev/shm/<name>`.
+const SHM_DIR: &str = "/dev/shm/";
+
+const IPC_PRIVATE: c_int = 0;
+const IPC_CREAT: c_int = 0o1000;
+const IPC_EXCL: c_int = 0o2000;
+const IPC_RMID: c_int = 0;
+
+const SHM_RDONLY: c_int = 0o10000;
+const SHM_RND: c_int = 0o20000;
+const SHM_EXEC: c_int = 0o100000;
+
+/// Named shared memory objects, which are kept until unlinked.
+static SHM_OBJECTS: Mutex<BTreeMap<String, Arc<SharedMemory>>> = Mutex::new(BTreeMap::new());
+
+/// A System V shared memory segment.
+struct Segment {
+    key: c_int,
+    pages: Arc<SharedPages>,
+}
+
+/// System V shared memory segments by their IDs, which are kept until
+/// removed by `shmctl(IPC_RMID)`.
+static SEGMENTS: Mutex<BTreeMap<c_int, Segment>> = Mutex::new(BTreeMap::new());
+static NEXT_SEGMENT_ID: AtomicI32 = AtomicI32::new(1);
+
+fn syscall_ret(name: &str, res: LinuxResult<isize>) -> isize {
+    match res {
+        Ok(ret) => ret,
+        Err(e) => {
+            debug!("{} => {:?}", name, e);
+            -e.code() as isize
+        }
+    }
+}
+
+/// Returns the name of the shared memory object at `path`, or `None` if the
+/// path is not in [`SHM_DIR`].
+pub(crate) fn shm_name(path: *const c_char) -> Option<&'static str> {
+    if path.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(path) }.to_str().ok()?;
+    path.strip_prefix(SHM_DIR)
+}
+
+fn shm_open(name: &str, flags: c_int) -> LinuxResult<isize> {
+    if name.is_empty() || name.contains('/') {
+        return Err(LinuxError::EINVAL);
+    }
+    let flags = flags as u32;
+    let mut objects = SHM_OBJECTS.lock();
+    let shm = match objects.get(name) {
+        Some(_) if flags & ctypes::O_CREAT != 0 && flags & ctypes::O_EXCL != 0 => {
+            return Err(LinuxError::EEXIST);
+        }
+        Some(shm) => shm.clone(),
+        None if flags & ctypes::O_CREAT != 0 => {
+            let shm = SharedMemory::new();
+            objects.insert(name.into(), shm.clone());
+            shm
+        }
+        None => return Err(LinuxError::ENOENT),
+    };
+    Ok(shm.add_to_fd_table()? as isize)
+}
+
+/// Opens the shared memory object of `name`, like `shm_open(3)`, which is
+/// the `openat` syscall on a path in [`SHM_DIR`].
+///
+/// The object is empty when created, and its size has to be set by
+/// `ftruncate` before it is mapped. As the size cannot be changed then,
+/// `O_TRUNC` is ignored.
+pub(crate) fn sys_shm_open(name: &str, flags: c_int) -> isize {
+    syscall_ret("sys_shm_open", shm_open(name, flags))
+}
+
+/// Removes the name of the shared memory object, like `shm_unlink(3)`, which
+/// is the `unlinkat` syscall on a path in [`SHM_DIR`].
+///
+/// The object is still usable through the opened file descriptors and the
+/// mappings of it.
+pub(crate) fn sys_shm_unlink(name: &str) -> isize {
+    let res = SHM_OBJECTS.lock().remove(name).ok_or(LinuxError::ENOENT);
+    syscall_ret("sys_shm_unlink", res.map(|_| 0))
+}
+
+fn ftruncate(fd: c_int, length: isize) -> LinuxResult<isize> {
+    if length < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    SharedMemory::from_fd(fd)?.set_size(length as usize)?;
+    Ok(0)
+}
+
+/// Sets the size of the shared memory object opened as `fd`. Other files are
+/// not supported.
+pub(crate) fn sys_ftruncate(fd: c_int, length: isize) -> isize {
+    syscall_ret("sys_ftruncate", ftruncate(fd, length))
+}
+
+fn shmget(key: c_int, size: usize, shmflg: c_int) -> LinuxResult<isize> {
+    let mut segments = SEGMENTS.lock();
+    if key != IPC_PRIVATE {
+        if let Some((&id, seg)) = segments.iter().find(|(_, seg)| seg.key == key) {
+            if shmflg & IPC_CREAT != 0 && shmflg & IPC_EXCL != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            if size > seg.pages.size() {
+                return Err(LinuxError::EINVAL);
+            }
+            return Ok(id as isize);
+        }
+        if shmflg & IPC_CREAT == 0 {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+    if size == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let pages = SharedPages::new(size)?;
+    let id = NEXT_SEGMENT_ID.fetch_add(1, Ordering::Relaxed);
+    segments.insert(id, Segment { key, pages });
+    Ok(id as isize)
+}
+
+fn shmat(shmid: c_int, addr: usize, shmflg: c_int) -> LinuxResult<isize> {
+    let pages = match SEGMENTS.lock().get(&shmid) {
+        Some(seg) => seg.pages.clone(),
+        None => return Err(LinuxError::EINVAL),
+    };
+    let mut flags = MappingFlags::READ | MappingFlags::USER;
+    if shmflg & SHM_RDONLY == 0 {
+        flags |= MappingFlags::WRITE;
+    }
+    if shmflg & SHM_EXEC != 0 {
+        flags |= MappingFlags::EXECUTE;
+    }
+
+    let curr = current();
+    let mut aspace = curr.task_ext().aspace.lock();
+    let size = pages.size();
+    let start = if addr == 0 {
+        let hint = aspace.base() + aspace.size() / 2;
+        let limit = VirtAddrRange::from_start_size(aspace.base(), aspace.size());
+        aspace
+            .find_free_area(hint, size, limit)
+            .ok_or(LinuxError::ENOMEM)?
+    } else if shmflg & SHM_RND != 0 {
+        VirtAddr::from(addr).align_down_4k()
+    } else if addr % PAGE_SIZE_4K == 0 {
+        VirtAddr::from(addr)
+    } else {
+        return Err(LinuxError::EINVAL);
+    };
+    aspace
+        .map_shared(start, size, flags, pages, 0)
+        .map_err(|_| LinuxError::EINVAL)?;
+    Ok(start.as_usize() as isize)
+}
+
+fn shmdt(addr: usize) -> LinuxResult<isize> {
+    // The attachment is found in the address space, so it also works in the
+    // copy-on-write clones of it.
+    current()
+        .task_ext()
+        .aspace
+        .lock()
+        .unmap_shared(addr.into())
+        .map_err(|_| LinuxError::EINVAL)?;
+    Ok(0)
+}
+
+fn shmctl(shmid: c_int, cmd: c_int) -> LinuxResult<isize> {
+    match cmd {
+        // The segment is freed after all the attachments are detached.
+        IPC_RMID => match SEGMENTS.lock().remove(&shmid) {
+            Some(_) => Ok(0),
+            None => Err(LinuxError::EINVAL),
+        },
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Gets the System V shared memory segment of `key`, or creates a new one
+/// of `size` bytes, like `shmget(2)`.
+pub(crate) fn sys_shmget(key: c_int, size: usize, shmflg: c_int) -> isize {
+    syscall_ret("sys_shmget", shmget(key, size, shmflg))
+}
+
+/// Attaches the System V shared memory segment to the address space of the
+/// current process, like `shmat(2)`.
+pub(crate) fn sys_shmat(shmid: c_int, addr: usize, shmflg: c_int) -> isize {
+    syscall_ret("sys_shmat", shmat(shmid, addr, shmflg))
+}
+
+/// Detaches the System V shared memory segment attached at `addr`, like
+/// `shmdt(2)`.
+pub(crate) fn sys_shmdt(addr: usize) -> isize {
+    syscall_ret("sys_shmdt", shmdt(addr))
+}
+
+/// Controls the System V shared memory segment, like `shmctl(2)`. Only
+/// `IPC_RMID` is supported.
+pub(crate) fn sys_shmctl(shmid: c_int, cmd: c_int) -> isize {
+    syscall_ret("sys_shmctl", shmctl(shmid, cmd))
+}
@@ -6,13 +6,11 @@ use axhal::trap::{register_trap_handler, SYSCALL};
 use axerrno::LinuxError;
 use axtask::current;
 use axtask::TaskExtRef;
-use axhal::paging::MappingFlags;
-use axhal::mem::{PAGE_SIZE_4K, VirtAddr, MemoryAddr};
 use arceos_posix_api as api;
-use alloc::vec::Vec;
-use alloc::vec;
 
 const SYS_IOCTL: usize = 29;
+const SYS_UNLINKAT: usize = 35;
+const SYS_FTRUNCATE: usize = 46;
 const SYS_OPENAT: usize = 56;
 const SYS_CLOSE: usize = 57;
 const SYS_READ: usize = 63;
@@ -21,10 +19,16 @@ const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_FUTEX: usize = 98;
+const SYS_SHMGET: usize = 194;
+const SYS_SHMCTL: usize = 195;
+const SYS_SHMAT: usize = 196;
+const SYS_SHMDT: usize = 197;
+const SYS_MUNMAP: usize = 215;
 const SYS_MMAP: usize = 222;
+const SYS_MSYNC: usize = 227;
 
 const AT_FDCWD: i32 = -100;
-const USER_STACK_SIZE: usize = 0x10000; // 64 KB, same as in main.rs
 
 /// Macro to generate syscall body
 ///
@@ -48,58 +52,6 @@ macro_rules! syscall_body {
     }};
 }
 
-bitflags::bitflags! {
-    #[derive(Debug)]
-    /// permissions for sys_mmap
-    ///
-    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
-    struct MmapProt: i32 {
-        /// Page can be read.
-        const PROT_READ = 1 << 0;
-        /// Page can be written.
-        const PROT_WRITE = 1 << 1;
-        /// Page can be executed.
-        const PROT_EXEC = 1 << 2;
-    }
-}
-
-impl From<MmapProt> for MappingFlags {
-    fn from(value: MmapProt) -> Self {
-        let mut flags = MappingFlags::USER;
-        if value.contains(MmapProt::PROT_READ) {
-            flags |= MappingFlags::READ;
-        }
-        if value.contains(MmapProt::PROT_WRITE) {
-            flags |= MappingFlags::WRITE;
-        }
-        if value.contains(MmapProt::PROT_EXEC) {
-            flags |= MappingFlags::EXECUTE;
-        }
-        flags
-    }
-}
-
-bitflags::bitflags! {
-    #[derive(Debug)]
-    /// flags for sys_mmap
-    ///
-    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
-    struct MmapFlags: i32 {
-        /// Share changes
-        const MAP_SHARED = 1 << 0;
-        /// Changes private; copy pages on write.
-        const MAP_PRIVATE = 1 << 1;
-        /// Map address must be exactly as requested, no matter whether it is available.
-        const MAP_FIXED = 1 << 4;
-        /// Don't use a file.
-        const MAP_ANONYMOUS = 1 << 5;
-        /// Don't check for reservations.
-        const MAP_NORESERVE = 1 << 14;
-        /// Allocation is for a stack.
-        const MAP_STACK = 0x20000;
-    }
-}
-
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ax_println!("handle_syscall [{}] ...", syscall_num);
@@ -107,18 +59,31 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
          SYS_IOCTL => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
         SYS_SET_TID_ADDRESS => sys_set_tid_address(tf.arg0() as _),
         SYS_OPENAT => sys_openat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _, tf.arg3() as _),
+        SYS_UNLINKAT => sys_unlinkat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_FTRUNCATE => crate::shm::sys_ftruncate(tf.arg0() as _, tf.arg1() as _),
         SYS_CLOSE => sys_close(tf.arg0() as _),
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITE => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_FUTEX => crate::futex::sys_futex(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
         SYS_EXIT_GROUP => {
             ax_println!("[SYS_EXIT_GROUP]: system is exiting ..");
+            crate::futex::clear_child_tid();
             axtask::exit(tf.arg0() as _)
         },
         SYS_EXIT => {
             ax_println!("[SYS_EXIT]: system is exiting ..");
+            crate::futex::clear_child_tid();
             axtask::exit(tf.arg0() as _)
         },
+        SYS_MUNMAP => sys_munmap(tf.arg0() as _, tf.arg1() as _),
         SYS_MMAP => sys_mmap(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -127,6 +92,11 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        SYS_MSYNC => sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_SHMGET => crate::shm::sys_shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_SHMCTL => crate::shm::sys_shmctl(tf.arg0() as _, tf.arg1() as _),
+        SYS_SHMAT => crate::shm::sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_SHMDT => crate::shm::sys_shmdt(tf.arg0() as _),
         _ => {
             ax_println!("Unimplemented syscall: {}", syscall_num);
             -LinuxError::ENOSYS.code() as _
@@ -135,69 +105,54 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     ret
 }
 
-#[allow(unused_variables)]
-fn sys_mmap(
-    addr: *mut usize,
-    length: usize,
-    prot: i32,
-    flags: i32,
-    fd: i32,
-    _offset: isize,
-) -> isize {
+fn sys_mmap(addr: usize, length: usize, prot: i32, flags: i32, fd: i32, offset: isize) -> isize {
     syscall_body!(sys_mmap, {
-        // Get the user space address space
         let curr = current();
-        let task_ext = curr.task_ext();
-        let mut aspace = task_ext.aspace.lock();
-        
-        // Parse flags
-        let mmap_flags = MmapFlags::from_bits_truncate(flags);
-        let mmap_prot = MmapProt::from_bits_truncate(prot);
-        
-        // Align address and size to page boundaries first
-        let aligned_length = (length + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        
-        // Allocate virtual address if addr is NULL
-        let aligned_vaddr = if !addr.is_null() {
-            VirtAddr::from(addr as usize).align_down_4k()
-        } else {
-            // Find a suitable virtual address below stack
-            // Stack is at [aspace.end() - USER_STACK_SIZE, aspace.end())
-            // So we place mmap at aspace.end() - USER_STACK_SIZE - aligned_length
-            (aspace.end() - USER_STACK_SIZE - aligned_length).align_down_4k()
-        };
-        
-        // Map the memory
-        let mapping_flags = MappingFlags::from(mmap_prot) | MappingFlags::USER;
-        aspace.map_alloc(aligned_vaddr, aligned_length, mapping_flags, true)?;
-        
-        // If it's a file mapping, read the file and write to the mapped memory
-        if !mmap_flags.contains(MmapFlags::MAP_ANONYMOUS) && fd >= 0 {
-            // Read from file
-            let mut data = vec![0u8; aligned_length];
-            let mut read_count = 0;
-            while read_count < length {
-                let buf = &mut data[read_count..];
-                let n = api::sys_read(fd, buf.as_mut_ptr() as *mut c_void, buf.len());
-                if n <= 0 {
-                    break;
-                }
-                read_count += n as usize;
-            }
-            
-            // Write to mapped memory
-            aspace.write(aligned_vaddr, &data[..read_count])?;
-        }
-        
-        Ok(aligned_vaddr.as_usize())
+        let mut aspace = curr.task_ext().aspace.lock();
+        api::aspace_mmap(&mut aspace, addr, length, prot, flags, fd, offset as _)
+    })
+}
+
+fn sys_munmap(addr: usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        api::aspace_munmap(&mut aspace, addr, length)?;
+        Ok(0)
+    })
+}
+
+fn sys_msync(addr: usize, length: usize, flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        api::aspace_msync(&mut aspace, addr, length, flags)?;
+        Ok(0)
     })
 }
 
 fn sys_openat(dfd: c_int, fname: *const c_char, flags: c_int, mode: api::ctypes::mode_t) -> isize {
-    assert_eq!(dfd, AT_FDCWD);
+    // Only paths relative to the current directory are supported now.
+    if dfd != AT_FDCWD {
+        return -LinuxError::EINVAL.code() as _;
+    }
+    if let Some(name) = crate::shm::shm_name(fname) {
+        return crate::shm::sys_shm_open(name, flags);
+    }
     api::sys_open(fname, flags, mode) as isize
 }
 
+fn sys_unlinkat(dfd: c_int, fname: *const c_char, _flags: c_int) -> isize {
+    if dfd != AT_FDCWD {
+        return -LinuxError::EINVAL.code() as _;
+    }
+    match crate::shm::shm_name(fname) {
+        Some(name) => crate::shm::sys_shm_unlink(name),
+        // Only shared memory objects can be removed now.
+        None => -LinuxError::ENOSYS.code() as _,
+    }
+}
+
 fn sys_close(fd: i32) -> isize {
     api::sys_close(fd) as isize
 }
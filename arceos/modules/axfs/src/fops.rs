@@ -169,6 +169,25 @@ impl File {
         Self::_open_at(None, path, opts)
     }
 
+    /// Creates a new handle of the same opened file, with the same access
+    /// permissions. The new handle has a separate cursor.
+    pub fn try_clone(&self) -> AxResult<Self> {
+        let node = unsafe { self.node.access_unchecked() }.clone();
+        node.open()?;
+        Ok(Self {
+            node: WithCap::new(node, self.node.cap()),
+            is_append: self.is_append,
+            offset: self.offset,
+        })
+    }
+
+    /// Returns a key that identifies the opened file node, which is the same
+    /// for all handles created by [`try_clone`](Self::try_clone).
+    pub fn node_id(&self) -> usize {
+        let node = unsafe { self.node.access_unchecked() };
+        alloc::sync::Arc::as_ptr(node) as *const () as usize
+    }
+
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
         self.access_node(Cap::WRITE)?.truncate(size)?;
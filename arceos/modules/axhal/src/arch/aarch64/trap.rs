@@ -37,8 +37,9 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 }
 
 #[no_mangle]
-fn handle_irq_exception(_tf: &TrapFrame) {
-    handle_trap!(IRQ, 0);
+fn handle_irq_exception(tf: &TrapFrame) {
+    // SPSR_EL1.M[3:0] is 0 (EL0t) if the IRQ is taken from EL0.
+    crate::trap::handle_irq(0, tf.spsr & 0b1111 == 0);
 }
 
 fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
@@ -48,9 +49,9 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
-    // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
-        || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+    // Only handle Translation fault, Access flag fault and Permission fault
+    if !matches!(iss & 0b111100, 0b0100 | 0b1000 | 0b1100) // IFSC or DFSC bits
+        || !crate::trap::handle_page_fault(vaddr, access_flags, is_user)
     {
         panic!(
             "Unhandled {} Instruction Abort @ {:#x}, fault_vaddr={:#x}, ISS={:#x} ({:?}):\n{:#x?}",
@@ -77,9 +78,9 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
-    // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
-        || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+    // Only handle Translation fault, Access flag fault and Permission fault
+    if !matches!(iss & 0b111100, 0b0100 | 0b1000 | 0b1100) // IFSC or DFSC bits
+        || !crate::trap::handle_page_fault(vaddr, access_flags, is_user)
     {
         panic!(
             "Unhandled {} Data Abort @ {:#x}, fault_vaddr={:#x}, ISS=0b{:08b} ({:?}):\n{:#x?}",
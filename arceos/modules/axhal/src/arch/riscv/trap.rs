@@ -21,7 +21,7 @@ fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bo
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(stval::read());
-    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+    if !crate::trap::handle_page_fault(vaddr, access_flags, is_user) {
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "User" } else { "Supervisor" },
@@ -49,7 +49,7 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         }
         Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
         Trap::Interrupt(_) => {
-            handle_trap!(IRQ, scause.bits());
+            crate::trap::handle_irq(scause.bits(), from_user);
         }
         _ => {
             panic!(
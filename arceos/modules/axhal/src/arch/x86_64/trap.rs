@@ -13,7 +13,7 @@ fn handle_page_fault(tf: &TrapFrame) {
     let access_flags = err_code_to_flags(tf.error_code)
         .unwrap_or_else(|e| panic!("Invalid #PF error code: {:#x}", e));
     let vaddr = va!(unsafe { cr2() });
-    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, tf.is_user()) {
+    if !crate::trap::handle_page_fault(vaddr, access_flags, tf.is_user()) {
         panic!(
             "Unhandled {} #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x} ({:?}):\n{:#x?}",
             if tf.is_user() { "user" } else { "kernel" },
@@ -26,11 +26,26 @@ fn handle_page_fault(tf: &TrapFrame) {
     }
 }
 
+/// Double faults are handled on a separate stack (see [`super::idt`]). They
+/// are most likely caused by kernel stack overflows, so the page fault that
+/// caused it (whose address is still in CR2) is reported as unhandled.
+fn handle_double_fault(tf: &TrapFrame) {
+    let vaddr = va!(unsafe { cr2() });
+    for func in crate::trap::UNHANDLED_PAGE_FAULT.iter() {
+        func(vaddr, MappingFlags::WRITE, false);
+    }
+    panic!(
+        "#DF @ {:#x}, last fault_vaddr={:#x}:\n{:#x?}",
+        tf.rip, vaddr, tf
+    );
+}
+
 #[no_mangle]
 fn x86_trap_handler(tf: &TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        DOUBLE_FAULT_VECTOR => handle_double_fault(tf),
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -38,7 +53,7 @@ fn x86_trap_handler(tf: &TrapFrame) {
             );
         }
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
-            handle_trap!(IRQ, tf.vector as _);
+            crate::trap::handle_irq(tf.vector as _, tf.is_user());
         }
         _ => {
             panic!(
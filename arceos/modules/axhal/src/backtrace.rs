@@ -0,0 +1,67 @@
+//! Stack backtraces by walking the chain of frame pointers.
+//!
+//! The kernel must be built with frame pointers (`-C force-frame-pointers=yes`),
+//! otherwise the walk stops early or yields meaningless addresses.
+
+use core::ops::Range;
+
+/// The maximum number of frames to walk.
+const MAX_DEPTH: usize = 32;
+
+/// Returns the frame pointer of the caller.
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let fp: usize;
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp) };
+        } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe { core::arch::asm!("mv {}, s0", out(reg) fp) };
+        } else if #[cfg(target_arch = "aarch64")] {
+            unsafe { core::arch::asm!("mov {}, x29", out(reg) fp) };
+        } else {
+            fp = 0;
+        }
+    }
+    fp
+}
+
+/// Returns the address of the frame record (the saved frame pointer followed
+/// by the return address) of the frame pointed to by `fp`.
+const fn frame_record(fp: usize) -> usize {
+    if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        // The frame pointer points to the top of the frame, below which are
+        // the return address and the saved frame pointer.
+        fp.wrapping_sub(2 * core::mem::size_of::<usize>())
+    } else {
+        fp
+    }
+}
+
+/// Walks the stack from the frame pointer `fp`, and calls `f` with the return
+/// address of each frame, from the innermost one.
+///
+/// Frame records must lie in `stack`, and the frame pointers must increase,
+/// otherwise the walk stops. At most 32 frames are walked.
+pub fn unwind<F: FnMut(usize)>(mut fp: usize, stack: Range<usize>, mut f: F) {
+    const WORD: usize = core::mem::size_of::<usize>();
+    for _ in 0..MAX_DEPTH {
+        let record = frame_record(fp);
+        if record % WORD != 0 || record < stack.start || record + 2 * WORD > stack.end {
+            break;
+        }
+        // SAFETY: the frame record is in the stack range.
+        let (prev_fp, ra) = unsafe {
+            let record = record as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if ra == 0 {
+            break;
+        }
+        f(ra);
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+}
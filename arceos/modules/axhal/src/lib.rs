@@ -45,6 +45,7 @@ mod platform;
 pub mod trap;
 
 pub mod arch;
+pub mod backtrace;
 pub mod cpu;
 pub mod mem;
 pub mod time;
@@ -2,6 +2,7 @@
 
 use axalloc::global_allocator;
 use lazyinit::LazyInit;
+use page_table_entry::GenericPTE;
 use page_table_multiarch::PagingHandler;
 
 use crate::mem::{phys_to_virt, virt_to_phys, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
@@ -57,15 +58,64 @@ cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::x86_64::X64PageTable<PagingHandlerImpl>;
+        type PTE = page_table_entry::x86_64::X64PTE;
+        const PAGE_LEVELS: usize = 4;
+        const PTE_ACCESSED: u64 = 1 << 5;
     } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::riscv::Sv39PageTable<PagingHandlerImpl>;
+        type PTE = page_table_entry::riscv::Rv64PTE;
+        const PAGE_LEVELS: usize = 3;
+        const PTE_ACCESSED: u64 = 1 << 6;
     } else if #[cfg(target_arch = "aarch64")]{
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::aarch64::A64PageTable<PagingHandlerImpl>;
+        type PTE = page_table_entry::aarch64::A64PTE;
+        const PAGE_LEVELS: usize = 4;
+        const PTE_ACCESSED: u64 = 1 << 10; // the access flag (AF)
     }
 }
 
+/// Tests and clears the accessed bit of the 4K page mapped at `vaddr`.
+///
+/// The accessed bit is set by the hardware on x86_64, and by the page fault
+/// handler on the other architectures, where accessing a page with the bit
+/// cleared raises a page fault (see `AddrSpace::handle_page_fault` of axmm).
+/// The TLB entry is flushed if the bit is cleared.
+///
+/// Returns `None` if `vaddr` is not mapped to a present 4K page.
+pub fn test_and_clear_accessed(pt: &mut PageTable, vaddr: VirtAddr) -> Option<bool> {
+    let mut table_paddr = pt.root_paddr();
+    for level in (0..PAGE_LEVELS).rev() {
+        let index = (vaddr.as_usize() >> (12 + level * 9)) & 0x1ff;
+        let entry_ptr = unsafe {
+            phys_to_virt(table_paddr)
+                .as_mut_ptr()
+                .cast::<PTE>()
+                .add(index)
+        };
+        let entry = unsafe { &*entry_ptr };
+        if !entry.is_present() || (level > 0 && entry.is_huge()) {
+            return None;
+        }
+        if level == 0 {
+            let bits = entry.bits() as u64;
+            if bits & PTE_ACCESSED == 0 {
+                return Some(false);
+            }
+            // All the PTE types are 64-bit.
+            unsafe { entry_ptr.cast::<u64>().write_volatile(bits & !PTE_ACCESSED) };
+            if cfg!(target_os = "none") {
+                // Not on the host, e.g., in unit tests.
+                crate::arch::flush_tlb(Some(vaddr));
+            }
+            return Some(true);
+        }
+        table_paddr = entry.paddr();
+    }
+    None
+}
+
 static KERNEL_PAGE_TABLE_ROOT: LazyInit<PhysAddr> = LazyInit::new();
 
 /// Saves the root physical address of the kernel page table, which may be used
@@ -12,6 +12,12 @@ pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(axconfig::UART_IRQ, InterruptType::SPI).unwrap();
 
+/// The IRQ number of inter-processor interrupts (SGI 1).
+pub const IPI_IRQ_NUM: usize = translate_irq(1, InterruptType::SGI).unwrap();
+
+/// The offset of the Software Generated Interrupt Register (GICD_SGIR).
+const GICD_SGIR_OFFSET: usize = 0xf00;
+
 const GICD_BASE: PhysAddr = pa!(axconfig::GICD_PADDR);
 const GICC_BASE: PhysAddr = pa!(axconfig::GICC_PADDR);
 
@@ -36,6 +42,16 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Sends an inter-processor interrupt to the given CPU.
+pub fn send_ipi(cpu_id: usize) {
+    // Hold the distributor lock, as the other registers are accessed under it.
+    let _gicd = GICD.lock();
+    let sgir = phys_to_virt(GICD_BASE + GICD_SGIR_OFFSET).as_mut_ptr() as *mut u32;
+    // The target list filter is 0b00, i.e., to the CPUs in the target list.
+    let value = (1 << (16 + cpu_id)) | IPI_IRQ_NUM as u32;
+    unsafe { sgir.write_volatile(value) };
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -1,7 +1,18 @@
 //! Description tables (per-CPU GDT, per-CPU ISS, IDT)
 
-use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment};
+use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment, DOUBLE_FAULT_IST_INDEX};
 use lazyinit::LazyInit;
+use x86_64::VirtAddr;
+
+/// The size of the stack for double faults on each CPU.
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+/// The stacks for double faults, see [`DOUBLE_FAULT_IST_INDEX`].
+#[repr(align(16))]
+struct DoubleFaultStacks([[u8; DOUBLE_FAULT_STACK_SIZE]; axconfig::SMP]);
+
+static mut DOUBLE_FAULT_STACKS: DoubleFaultStacks =
+    DoubleFaultStacks([[0; DOUBLE_FAULT_STACK_SIZE]; axconfig::SMP]);
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
@@ -16,7 +27,11 @@ fn init_percpu() {
         IDT.load();
         let tss = TSS.current_ref_mut_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.init_once(TaskStateSegment::new());
+        let mut new_tss = TaskStateSegment::new();
+        let stack = core::ptr::addr_of!(DOUBLE_FAULT_STACKS.0[crate::cpu::this_cpu_id()]);
+        new_tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::from_ptr(stack) + DOUBLE_FAULT_STACK_SIZE as u64;
+        tss.init_once(new_tss);
         gdt.init_once(GdtStruct::new(tss));
         gdt.load();
         gdt.load_tss();
@@ -9,6 +9,10 @@ use crate::arch::TrapFrame;
 
 pub use linkme::distributed_slice as register_trap_handler;
 
+/// Whether the last IRQ on each CPU was taken from user mode.
+#[percpu::def_percpu]
+static IRQ_FROM_USER: bool = false;
+
 /// A slice of IRQ handler functions.
 #[def_trap_handler]
 pub static IRQ: [fn(usize) -> bool];
@@ -17,6 +21,14 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// A slice of functions called on page faults that are not handled by the
+/// [`PAGE_FAULT`] handler, before panicking.
+///
+/// They can be used to report the cause of the fault (e.g, a stack
+/// overflow). Unlike other traps, all registered functions are called.
+#[def_trap_handler]
+pub static UNHANDLED_PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool)];
+
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
@@ -38,6 +50,38 @@ macro_rules! handle_trap {
     }}
 }
 
+/// Returns whether the last IRQ on the current CPU was taken from user mode.
+///
+/// It is only meaningful in IRQ handlers, e.g, to account the CPU time of the
+/// interrupted task on timer ticks.
+pub fn irq_from_user() -> bool {
+    unsafe { IRQ_FROM_USER.read_current_raw() }
+}
+
+/// Call the external IRQ handler.
+#[cfg(any(target_os = "none", not(target_arch = "x86_64")))]
+pub(crate) fn handle_irq(irq: usize, from_user: bool) -> bool {
+    unsafe { IRQ_FROM_USER.write_current_raw(from_user) };
+    handle_trap!(IRQ, irq)
+}
+
+/// Call the external page fault handler, and notify the functions in
+/// [`UNHANDLED_PAGE_FAULT`] if it is not handled.
+#[cfg(any(target_os = "none", not(target_arch = "x86_64")))]
+pub(crate) fn handle_page_fault(
+    vaddr: VirtAddr,
+    access_flags: MappingFlags,
+    is_user: bool,
+) -> bool {
+    if handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+        return true;
+    }
+    for func in UNHANDLED_PAGE_FAULT.iter() {
+        func(vaddr, access_flags, is_user);
+    }
+    false
+}
+
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
 pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
@@ -1,24 +1,31 @@
 use core::fmt;
 
+use crate::backend::{
+    protect_region, split_huge_pages_at_bounds, Backend, MappedFile, SharedPages,
+};
+use crate::mapping_err_to_ax_err;
+use crate::paging_err_to_ax_err;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use axerrno::{ax_err, AxError, AxResult};
 use axhal::{
     mem::phys_to_virt,
     paging::{MappingFlags, PageTable},
 };
 use memory_addr::{
-    is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
+    is_aligned_4k, pa, va, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
 };
 use memory_set::{MemoryArea, MemorySet};
-use crate::backend::Backend;
-use crate::paging_err_to_ax_err;
-use crate::mapping_err_to_ax_err;
-use alloc::vec::Vec;
 
 /// The virtual memory address space.
 pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// The clock hand of swapping, where the next scan for victim pages
+    /// starts.
+    #[cfg(feature = "swap")]
+    swap_hand: VirtAddr,
 }
 
 impl AddrSpace {
@@ -59,6 +66,8 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            #[cfg(feature = "swap")]
+            swap_hand: base,
         })
     }
 
@@ -77,6 +86,52 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Creates a copy-on-write clone of the address space, e.g., for `fork`.
+    ///
+    /// The pages of allocation mappings are shared read-only by the two
+    /// address spaces, until either of them writes to a page and gets a copy
+    /// of it in [`AddrSpace::handle_page_fault`]. Pages not populated yet
+    /// are populated independently. Linear mappings and shared memory
+    /// mappings are shared as is.
+    ///
+    /// The kernel mappings are also copied if the address space does not
+    /// overlap the kernel address space.
+    ///
+    /// Note that [`AddrSpace::write`] bypasses the page permissions, so a
+    /// shared page must be unshared by a write fault before written by it.
+    pub fn clone_cow(&mut self) -> AxResult<Self> {
+        let mut new = Self::new_empty(self.base(), self.size())?;
+        let kernel_range = VirtAddrRange::from_start_size(
+            va!(axconfig::KERNEL_ASPACE_BASE),
+            axconfig::KERNEL_ASPACE_SIZE,
+        );
+        if !self.va_range.overlaps(kernel_range) && crate::KERNEL_ASPACE.is_inited() {
+            new.copy_mappings_from(&crate::kernel_aspace().lock())?;
+        }
+
+        for area in self.areas.iter() {
+            let backend = area.backend();
+            let new_area = MemoryArea::new(
+                area.start(),
+                area.size(),
+                area.flags(),
+                backend.cow_backend(),
+            );
+            new.areas
+                .map(new_area, &mut new.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+            if !backend.share_cow(area.start(), area.size(), &mut self.pt, &mut new.pt) {
+                return ax_err!(NoMemory, "failed to share pages");
+            }
+        }
+        Ok(new)
+    }
+
+    /// Removes all mappings in the address space.
+    pub fn clear(&mut self) {
+        self.areas.clear(&mut self.pt).unwrap();
+    }
+
     /// Finds a free area that can accommodate the given size.
     ///
     /// The search starts from the given hint address, and the area should be within the given limit range.
@@ -94,7 +149,8 @@ impl AddrSpace {
     /// Add a new linear mapping.
     ///
     /// The mapping is linear, i.e., `start_vaddr` is mapped to `start_paddr`,
-    /// and `start_vaddr + size` is mapped to `start_paddr + size`.
+    /// and `start_vaddr + size` is mapped to `start_paddr + size`. Huge pages
+    /// are used if the addresses and the size are aligned.
     ///
     /// The `flags` parameter indicates the mapping permissions and attributes.
     ///
@@ -121,7 +177,7 @@ impl AddrSpace {
                 |va| pa!(va.as_usize() - offset),
                 size,
                 flags,
-                false, // allow_huge
+                true,  // allow_huge
                 false, // flush_tlb_by_page
             )
             .map_err(paging_err_to_ax_err)?
@@ -151,6 +207,10 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
+        #[cfg(feature = "swap")]
+        if populate {
+            self.reclaim_pages(size / PAGE_SIZE_4K);
+        }
         let area = MemoryArea::new(start, size, flags, Backend::new_alloc(populate));
         self.areas
             .map(area, &mut self.pt, false)
@@ -158,6 +218,138 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Add a new file mapping.
+    ///
+    /// The file is mapped from `offset` at `start`, and the pages are loaded
+    /// on demand. If `shared` is `true`, changes to the mapping are written
+    /// back to the file by [`AddrSpace::msync`] or when unmapped. Otherwise,
+    /// they are private to the mapping.
+    ///
+    /// The `flags` parameter indicates the mapping permissions and attributes.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        file: Arc<dyn MappedFile>,
+        offset: u64,
+        shared: bool,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) || offset % PAGE_SIZE_4K as u64 != 0 {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let backend = Backend::new_file(file, start, offset, shared);
+        let area = MemoryArea::new(start, size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Add a new shared memory mapping.
+    ///
+    /// The shared pages are mapped from `offset` at `start`. They can be
+    /// mapped into other address spaces, or elsewhere in this one, and
+    /// changes through any mapping are visible to all of them.
+    ///
+    /// The `flags` parameter indicates the mapping permissions and attributes.
+    ///
+    /// Returns an error if the address range is out of the address space or
+    /// the shared pages, or not aligned.
+    pub fn map_shared(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pages: Arc<SharedPages>,
+        offset: usize,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if offset
+            .checked_add(size)
+            .map_or(true, |end| end > pages.size())
+        {
+            return ax_err!(InvalidInput, "offset out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) || !is_aligned_4k(offset) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let backend = Backend::new_shared(pages, start, offset);
+        let area = MemoryArea::new(start, size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Removes the shared memory mapped at `start` from the beginning of the
+    /// pages by [`AddrSpace::map_shared`], including the parts of it split
+    /// by other operations, like `shmdt(2)`.
+    ///
+    /// Returns an error if there is no such mapping.
+    pub fn unmap_shared(&mut self, start: VirtAddr) -> AxResult {
+        let parts: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                matches!(area.backend(), Backend::Shared { start: s, offset: 0, .. } if *s == start)
+            })
+            .map(|area| (area.start(), area.size()))
+            .collect();
+        if parts.is_empty() {
+            return ax_err!(InvalidInput, "no shared memory mapped at the address");
+        }
+        for (start, size) in parts {
+            self.areas
+                .unmap(start, size, &mut self.pt)
+                .map_err(mapping_err_to_ax_err)?;
+        }
+        Ok(())
+    }
+
+    /// Populates the pages within the specified virtual address range for
+    /// the access of `access_flags`, as if they have been accessed.
+    ///
+    /// It is used where page faults are not handled, e.g., in the kernel
+    /// address space.
+    ///
+    /// Returns an error if any page in the range cannot be populated.
+    pub fn populate(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+    ) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            match self.pt.query(vaddr) {
+                Ok((_, flags, _)) if !flags.is_empty() && flags.contains(access_flags) => {}
+                _ => {
+                    if !self.handle_page_fault(vaddr, access_flags) {
+                        return ax_err!(BadAddress, "failed to populate page");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Removes mappings within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
@@ -170,10 +362,34 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .unmap_region(start, size, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        self.areas
+            .unmap(start, size, &mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Writes the changes within the specified virtual address range back
+    /// to the mapped files, for shared file mappings.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned, or writing back fails.
+    pub fn msync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let end = start + size;
+        for area in self.areas.iter() {
+            let area_start = area.start().max(start);
+            let area_end = area.end().min(end);
+            if area_start < area_end {
+                area.backend()
+                    .sync(area_start, area_end - area_start, &mut self.pt)?;
+            }
+        }
         Ok(())
     }
 
@@ -193,7 +409,12 @@ impl AddrSpace {
         for vaddr in PageIter4K::new(start.align_down_4k(), end_align_up)
             .expect("Failed to create page iterator")
         {
-            let (mut paddr, _, _) = self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
+            let (mut paddr, flags, _) = self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
+            if flags.is_empty() {
+                // Not populated yet, swapped out, or protected with
+                // `PROT_NONE`.
+                return ax_err!(BadAddress);
+            }
 
             let mut copy_size = (size - cnt).min(PAGE_SIZE_4K);
 
@@ -247,10 +468,22 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .protect_region(start, size, flags, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        split_huge_pages_at_bounds(&mut self.pt, start, size).map_err(paging_err_to_ax_err)?;
+        protect_region(&mut self.pt, start, size, flags).map_err(paging_err_to_ax_err)?;
+        if flags.contains(MappingFlags::WRITE) {
+            // Pages shared by copy-on-write must stay read-only.
+            let end = start + size;
+            for area in self.areas.iter() {
+                let (area_start, area_end) = (area.start().max(start), area.end().min(end));
+                if area_start >= area_end {
+                    continue;
+                }
+                let backend = area.backend();
+                if !backend.write_protect_cow(area_start, area_end - area_start, &mut self.pt) {
+                    return ax_err!(BadState, "failed to write-protect shared pages");
+                }
+            }
+        }
         Ok(())
     }
 
@@ -258,23 +491,103 @@ impl AddrSpace {
     ///
     /// `access_flags` indicates the access type that caused the page fault.
     ///
+    /// With the `swap` feature, pages are swapped out first if free memory
+    /// runs low (see [`swap_out_pages`](Self::swap_out_pages)), and
+    /// swapped-out pages are swapped in.
+    ///
     /// Returns `true` if the page fault is handled successfully (not a real
     /// fault).
     pub fn handle_page_fault(&mut self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        #[cfg(feature = "swap")]
+        self.reclaim_pages(1);
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
-                return area
-                    .backend()
-                    .handle_page_fault(vaddr, orig_flags, &mut self.pt);
+                return area.backend().handle_page_fault(
+                    vaddr,
+                    orig_flags,
+                    access_flags,
+                    &mut self.pt,
+                );
             }
         }
         false
     }
 
+    /// Swaps out at most `num_pages` pages of the allocation mappings in the
+    /// user space, and returns the number of pages swapped out.
+    ///
+    /// The victim pages are chosen by the clock (second-chance) algorithm.
+    /// The pages are scanned from where the last scan stopped, and those
+    /// accessed since the last scan are skipped this time, with their
+    /// accessed bits cleared. Shared pages and huge pages are not swapped
+    /// out.
+    ///
+    /// Pages cannot be swapped out if [`swap_on`](crate::swap_on) has not been
+    /// called.
+    #[cfg(feature = "swap")]
+    pub fn swap_out_pages(&mut self, num_pages: usize) -> usize {
+        if !crate::swap::is_enabled() {
+            return 0;
+        }
+        let ranges: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                matches!(area.backend(), Backend::Alloc { .. })
+                    && area.flags().contains(MappingFlags::USER)
+            })
+            .map(|area| (area.start(), area.end()))
+            .collect();
+        let hand = self.swap_hand;
+        let pages = || {
+            ranges
+                .iter()
+                .flat_map(|&(start, end)| PageIter4K::new(start, end).unwrap())
+        };
+        let mut count = 0;
+        // Two rounds, as each page is skipped at most once.
+        for _ in 0..2 {
+            let clock = pages()
+                .filter(|&vaddr| vaddr >= hand)
+                .chain(pages().filter(|&vaddr| vaddr < hand));
+            for vaddr in clock {
+                if count == num_pages {
+                    return count;
+                }
+                if crate::backend::swap_out_page(vaddr, &mut self.pt) {
+                    self.swap_hand = vaddr + PAGE_SIZE_4K;
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Swaps out pages, if any, until `num_pages` pages can be allocated and
+    /// there are still [`MIN_FREE_PAGES`](crate::swap::MIN_FREE_PAGES) free
+    /// pages left.
+    ///
+    /// Victim pages are chosen from this address space first, then from the
+    /// others registered with [`register_swappable`] that are not locked.
+    ///
+    /// [`register_swappable`]: crate::register_swappable
+    #[cfg(feature = "swap")]
+    fn reclaim_pages(&mut self, num_pages: usize) {
+        let wanted = num_pages + crate::swap::MIN_FREE_PAGES;
+        let available = axalloc::global_allocator().available_pages();
+        if available < wanted {
+            let mut swapped = self.swap_out_pages(wanted - available);
+            if swapped < wanted - available {
+                swapped += crate::swap::reclaim(wanted - available - swapped);
+            }
+            debug!("reclaimed {} pages by swapping", swapped);
+        }
+    }
+
     pub fn translated_byte_buffer(
         &self,
         vaddr: VirtAddr,
@@ -324,6 +637,12 @@ impl AddrSpace {
     }
 }
 
+impl Drop for AddrSpace {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
 impl fmt::Debug for AddrSpace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AddrSpace")
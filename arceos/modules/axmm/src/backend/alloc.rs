@@ -1,22 +1,276 @@
+use alloc::collections::BTreeMap;
+
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 
+use super::huge::{
+    max_page_size, smaller_page_size, split_huge_pages, split_huge_pages_at_bounds, split_to_4k,
+};
 use super::Backend;
+#[cfg(feature = "swap")]
+use crate::swap;
+
+/// Reference counts of frames shared by copy-on-write or shared mappings.
+///
+/// Frames not in the map are owned by a single mapping.
+static SHARED_FRAMES: SpinNoIrq<BTreeMap<PhysAddr, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+pub(super) fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
+    alloc_frames(PageSize::Size4K, zeroed)
+}
 
-fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
-    let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
+pub(super) fn dealloc_frame(frame: PhysAddr) {
+    dealloc_frames(frame, PageSize::Size4K);
+}
+
+/// Allocates contiguous frames for a page of `page_size`, which are aligned
+/// to the page size.
+///
+/// The frames of a huge page can be deallocated separately after the page is
+/// split, as the global page allocator keeps track of each frame.
+fn alloc_frames(page_size: PageSize, zeroed: bool) -> Option<PhysAddr> {
+    let size = usize::from(page_size);
+    let num_pages = size / PAGE_SIZE_4K;
+    let vaddr = VirtAddr::from(global_allocator().alloc_pages(num_pages, size).ok()?);
     if zeroed {
-        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
+        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, size) };
     }
     let paddr = virt_to_phys(vaddr);
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
+fn dealloc_frames(frame: PhysAddr, page_size: PageSize) {
     let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+    global_allocator().dealloc_pages(vaddr.as_usize(), usize::from(page_size) / PAGE_SIZE_4K);
+}
+
+/// Adds a mapping to the frame.
+pub(super) fn share_frame(frame: PhysAddr) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+}
+
+/// Removes a mapping to the frame, and deallocates it if there are no other
+/// mappings.
+pub(super) fn release_frame(frame: PhysAddr) {
+    let mut shared = SHARED_FRAMES.lock();
+    match shared.get_mut(&frame) {
+        Some(count) if *count > 2 => *count -= 1,
+        Some(_) => {
+            shared.remove(&frame);
+        }
+        None => {
+            drop(shared);
+            dealloc_frame(frame);
+        }
+    }
+}
+
+/// Returns `true` if the frame is mapped more than once.
+pub(super) fn is_frame_shared(frame: PhysAddr) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
+
+/// Returns the frame (at the offset of `vaddr`), the flags and the size of
+/// the page at `vaddr`, or `None` if it is not populated yet or swapped out.
+///
+/// Lazy pages (and swapped-out pages) have empty entries with the address 0,
+/// while populated pages have empty flags if protected with `PROT_NONE`.
+pub(super) fn query_page(
+    pt: &PageTable,
+    vaddr: VirtAddr,
+) -> Option<(PhysAddr, MappingFlags, PageSize)> {
+    match pt.query(vaddr) {
+        Ok((frame, flags, page_size))
+            if !flags.is_empty() || frame.align_down(usize::from(page_size)).as_usize() != 0 =>
+        {
+            Some((frame, flags, page_size))
+        }
+        _ => None,
+    }
+}
+
+/// Breaks the sharing of the frame at `vaddr` on a write fault, by copying
+/// it to a new frame, or by restoring the write permission if the other
+/// mappings have gone.
+pub(super) fn handle_cow_fault(
+    vaddr: VirtAddr,
+    frame: PhysAddr,
+    orig_flags: MappingFlags,
+    pt: &mut PageTable,
+) -> bool {
+    // The frame is queried at the fault address, which may be unaligned.
+    let frame = frame.align_down_4k();
+    let frame = if is_frame_shared(frame) {
+        let Some(new_frame) = alloc_frame(false) else {
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(frame).as_ptr(),
+                phys_to_virt(new_frame).as_mut_ptr(),
+                PAGE_SIZE_4K,
+            )
+        };
+        release_frame(frame);
+        new_frame
+    } else {
+        frame
+    };
+    pt.remap(vaddr, frame, orig_flags)
+        .map(|(_, tlb)| tlb.flush())
+        .is_ok()
+}
+
+/// Removes the write permission of the pages in `[start, start + size)`
+/// whose frames are shared, so that the first write to them still copies the
+/// page after the range is protected with new flags.
+pub(super) fn write_protect_shared(start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+    let end = start + size;
+    let mut addr = start;
+    while addr < end {
+        let (frame, flags, page_size) = match pt.query(addr) {
+            Ok(res) => res,
+            Err(_) => {
+                addr += PAGE_SIZE_4K;
+                continue;
+            }
+        };
+        // Shared frames are never mapped by huge pages.
+        if page_size == PageSize::Size4K
+            && flags.contains(MappingFlags::WRITE)
+            && is_frame_shared(frame)
+        {
+            match pt.protect(addr, flags - MappingFlags::WRITE) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+        }
+        addr = addr.align_down(usize::from(page_size)) + usize::from(page_size);
+    }
+    true
+}
+
+/// Maps the populated pages in `[start, start + size)` of `src_pt` to the
+/// same frames in `dst_pt`.
+///
+/// If `cow` is `true`, the pages are write-protected in both page tables, so
+/// that the first write in either of them copies the page.
+///
+/// Huge pages in `src_pt` are split first, as frames are shared page by page.
+pub(super) fn share_frames(
+    start: VirtAddr,
+    size: usize,
+    src_pt: &mut PageTable,
+    dst_pt: &mut PageTable,
+    cow: bool,
+) -> bool {
+    debug!(
+        "share_frames: [{:#x}, {:#x}) (cow={})",
+        start,
+        start + size,
+        cow
+    );
+    if split_huge_pages(src_pt, start, size).is_err() {
+        return false;
+    }
+    for addr in PageIter4K::new(start, start + size).unwrap() {
+        #[cfg(feature = "swap")]
+        if let Some(entry) = swap::swap_entry(src_pt, addr) {
+            // Swapped-out pages share the slot, and are swapped in separately.
+            swap::dup_slot(entry.slot);
+            swap::set_swap_entry(dst_pt, addr, entry);
+            continue;
+        }
+        let Some((frame, mut flags, _)) = query_page(src_pt, addr) else {
+            continue; // not populated yet
+        };
+        if cow && flags.contains(MappingFlags::WRITE) {
+            flags -= MappingFlags::WRITE;
+            match src_pt.protect(addr, flags) {
+                Ok((_, tlb)) => tlb.flush(),
+                Err(_) => return false,
+            }
+        }
+        match dst_pt.remap(addr, frame, flags) {
+            Ok((_, tlb)) => tlb.ignore(),
+            Err(_) => return false,
+        }
+        share_frame(frame);
+    }
+    true
+}
+
+/// Swaps out the page at `vaddr` to a free slot of the swap area, unless it
+/// has been accessed since the last call, in which case its accessed bit is
+/// cleared to give it a second chance.
+///
+/// Only present 4K pages that are not shared can be swapped out.
+///
+/// Returns `true` if the page is swapped out.
+#[cfg(feature = "swap")]
+pub(crate) fn swap_out_page(vaddr: VirtAddr, pt: &mut PageTable) -> bool {
+    let (frame, flags) = match pt.query(vaddr) {
+        Ok((frame, flags, PageSize::Size4K)) if !flags.is_empty() && !is_frame_shared(frame) => {
+            (frame, flags)
+        }
+        _ => return false,
+    };
+    if axhal::paging::test_and_clear_accessed(pt, vaddr) != Some(false) {
+        return false;
+    }
+    let Some(slot) = swap::alloc_slot() else {
+        return false;
+    };
+    // The page is unmapped before written out, so that it is not changed
+    // during the write. Its entry is left empty like a lazy page.
+    match pt.remap(vaddr, PhysAddr::from(0), MappingFlags::empty()) {
+        Ok((_, tlb)) => tlb.flush(),
+        Err(_) => {
+            swap::free_slot(slot);
+            return false;
+        }
+    }
+    swap::set_swap_entry(pt, vaddr, swap::SwapEntry { slot, flags });
+    if let Err(e) = swap::write_slot(slot, frame) {
+        warn!("failed to swap out page {:#x}: {:?}", vaddr, e);
+        swap::take_swap_entry(pt, vaddr);
+        swap::free_slot(slot);
+        if let Ok((_, tlb)) = pt.remap(vaddr, frame, flags) {
+            tlb.flush();
+        }
+        return false;
+    }
+    dealloc_frame(frame);
+    true
+}
+
+/// Swaps in the page at `vaddr` from its slot to a new frame, with the flags
+/// it had when swapped out (or was protected with later).
+#[cfg(feature = "swap")]
+fn swap_in_page(vaddr: VirtAddr, entry: swap::SwapEntry, pt: &mut PageTable) -> bool {
+    let Some(frame) = alloc_frame(false) else {
+        return false;
+    };
+    if let Err(e) = swap::read_slot(entry.slot, frame) {
+        warn!("failed to swap in page {:#x}: {:?}", vaddr, e);
+        dealloc_frame(frame);
+        return false;
+    }
+    match pt.remap(vaddr, frame, entry.flags) {
+        Ok((_, tlb)) => {
+            tlb.flush();
+            swap::take_swap_entry(pt, vaddr);
+            swap::free_slot(entry.slot);
+            true
+        }
+        Err(_) => {
+            dealloc_frame(frame);
+            false
+        }
+    }
 }
 
 impl Backend {
@@ -41,15 +295,26 @@ impl Backend {
             populate
         );
         if populate {
-            // allocate all possible physical frames for populated mapping.
-            for addr in PageIter4K::new(start, start + size).unwrap() {
-                if let Some(frame) = alloc_frame(true) {
-                    if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
+            // allocate all possible physical frames for populated mapping,
+            // using huge pages where possible.
+            let end = start + size;
+            let mut addr = start;
+            while addr < end {
+                let mut page_size = max_page_size(addr, end - addr);
+                let frame = loop {
+                    match alloc_frames(page_size, true) {
+                        None if page_size.is_huge() => page_size = smaller_page_size(page_size),
+                        frame => break frame,
+                    }
+                };
+                if let Some(frame) = frame {
+                    if let Ok(tlb) = pt.map(addr, frame, page_size, flags) {
                         tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
                     } else {
                         return false;
                     }
                 }
+                addr += usize::from(page_size);
             }
             true
         } else {
@@ -69,17 +334,35 @@ impl Backend {
         _populate: bool,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
-        for addr in PageIter4K::new(start, start + size).unwrap() {
-            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+        if split_huge_pages_at_bounds(pt, start, size).is_err() {
+            return false;
+        }
+        let end = start + size;
+        let mut addr = start;
+        while addr < end {
+            #[cfg(feature = "swap")]
+            if let Some(entry) = swap::take_swap_entry(pt, addr) {
+                swap::free_slot(entry.slot);
+            }
+            // The page is queried first, as populated pages protected with
+            // `PROT_NONE` may be not present.
+            let page = query_page(pt, addr);
+            if let Ok((_, _, tlb)) = pt.unmap(addr) {
+                tlb.flush();
+            }
+            if let Some((frame, _, page_size)) = page {
                 // Deallocate the physical frame if there is a mapping in the
                 // page table.
                 if page_size.is_huge() {
-                    return false;
+                    // Huge pages are never shared, see `share_frames`.
+                    dealloc_frames(frame, page_size);
+                } else {
+                    release_frame(frame);
                 }
-                tlb.flush();
-                dealloc_frame(frame);
+                addr += usize::from(page_size);
             } else {
                 // Deallocation is needn't if the page is not mapped.
+                addr += PAGE_SIZE_4K;
             }
         }
         true
@@ -92,6 +375,33 @@ impl Backend {
         pt: &mut PageTable,
         populate: bool,
     ) -> bool {
+        if let Some((frame, flags, page_size)) = query_page(pt, vaddr) {
+            if flags.is_empty() {
+                return false; // protected with `PROT_NONE`
+            }
+            // The page is mapped but not writable, which must be a write
+            // fault on a copy-on-write page, or on a write-protected huge
+            // page, which is never shared.
+            if orig_flags.contains(MappingFlags::WRITE) && !flags.contains(MappingFlags::WRITE) {
+                let frame = if page_size.is_huge() {
+                    match split_to_4k(pt, vaddr).and_then(|_| pt.query(vaddr)) {
+                        Ok((frame, ..)) => frame,
+                        Err(_) => return false,
+                    }
+                } else {
+                    frame
+                };
+                return handle_cow_fault(vaddr, frame, orig_flags, pt);
+            }
+            // The page is present and permitted, so the fault is spurious,
+            // e.g., caused by a stale TLB entry, or by the accessed bit
+            // cleared by `swap_out_page`, which is set on remapping.
+            return pt.protect(vaddr, flags).map(|(_, tlb)| tlb.flush()).is_ok();
+        }
+        #[cfg(feature = "swap")]
+        if let Some(entry) = swap::swap_entry(pt, vaddr) {
+            return swap_in_page(vaddr, entry, pt);
+        }
         if populate {
             false // Populated mappings should not trigger page faults.
         } else if let Some(frame) = alloc_frame(true) {
@@ -0,0 +1,285 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use axerrno::{AxError, AxResult};
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, handle_cow_fault, is_frame_shared, release_frame, share_frame};
+use super::Backend;
+
+/// Frames of the file pages mapped by shared mappings, indexed by the file
+/// (see [`MappedFile::identity`]) and the page offset.
+///
+/// All shared mappings of a file page map the same frame, so that changes
+/// are visible to each other. A page is removed when it is no longer mapped.
+/// Private mappings and `read`/`write` of the file do not go through the
+/// cache, so they only see the changes that have been written back.
+static PAGE_CACHE: SpinNoIrq<BTreeMap<(usize, u64), PhysAddr>> = SpinNoIrq::new(BTreeMap::new());
+
+fn cache_key(file: &dyn MappedFile, offset: u64) -> (usize, u64) {
+    (file.identity(), offset)
+}
+
+/// A file that can be mapped into an address space.
+///
+/// See [`AddrSpace::map_file`](crate::AddrSpace::map_file).
+pub trait MappedFile: Send + Sync {
+    /// Reads the file at the given offset. Returns the number of bytes read,
+    /// which is less than `buf.len()` only at the end of the file.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+
+    /// Writes the file at the given offset. Returns the number of bytes
+    /// written.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize>;
+
+    /// Returns the size of the file.
+    fn size(&self) -> AxResult<u64>;
+
+    /// Returns a key that identifies the underlying file, e.g., the address of
+    /// its inode. Shared mappings of objects with the same key share the
+    /// frames of the same pages.
+    ///
+    /// By default, it is the address of the object, so that mappings of
+    /// different objects never share frames.
+    fn identity(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
+/// Returns the file offset of the page at `vaddr`, in the mapping of `offset`
+/// at `start`.
+fn page_offset(vaddr: VirtAddr, start: VirtAddr, offset: u64) -> u64 {
+    offset + (vaddr.align_down_4k() - start) as u64
+}
+
+fn page_data(frame: PhysAddr) -> &'static mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) }
+}
+
+/// Loads the page at `offset` of the file to the frame. The part beyond the
+/// end of the file is left untouched.
+fn read_page(file: &dyn MappedFile, offset: u64, frame: PhysAddr) -> AxResult {
+    let buf = page_data(frame);
+    let mut pos = 0;
+    while pos < buf.len() {
+        match file.read_at(offset + pos as u64, &mut buf[pos..])? {
+            0 => break,
+            n => pos += n,
+        }
+    }
+    Ok(())
+}
+
+/// Allocates a frame and loads the page at `offset` of the file to it.
+fn load_page(file: &dyn MappedFile, offset: u64) -> Option<PhysAddr> {
+    let frame = alloc_frame(true)?;
+    if let Err(e) = read_page(file, offset, frame) {
+        warn!("failed to load page at offset {:#x}: {:?}", offset, e);
+        release_frame(frame);
+        return None;
+    }
+    Some(frame)
+}
+
+/// Writes the frame back to the page at `offset` of the file. The part beyond
+/// the end of the file is discarded, i.e., the file is never extended.
+fn write_page(file: &dyn MappedFile, offset: u64, frame: PhysAddr) -> AxResult {
+    let size = file.size()?;
+    if offset >= size {
+        return Ok(());
+    }
+    let buf = &page_data(frame)[..(size - offset).min(PAGE_SIZE_4K as u64) as usize];
+    let mut pos = 0;
+    while pos < buf.len() {
+        match file.write_at(offset + pos as u64, &buf[pos..])? {
+            0 => return Err(AxError::WriteZero),
+            n => pos += n,
+        }
+    }
+    Ok(())
+}
+
+impl Backend {
+    /// Creates a new file mapping backend, which maps the file from `offset`
+    /// at the virtual address `start`.
+    pub fn new_file(file: Arc<dyn MappedFile>, start: VirtAddr, offset: u64, shared: bool) -> Self {
+        Self::File {
+            file,
+            start,
+            offset,
+            shared,
+        }
+    }
+
+    pub(crate) fn map_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!("map_file: [{:#x}, {:#x}) {:?}", start, start + size, flags);
+        // Map to a empty entry for on-demand mapping.
+        let flags = MappingFlags::empty();
+        pt.map_region(start, |_| 0.into(), size, flags, false, false)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
+
+    /// Returns the file, the start address, the file offset and whether the
+    /// mapping is shared.
+    fn file_mapping(&self) -> (&dyn MappedFile, VirtAddr, u64, bool) {
+        match self {
+            Self::File {
+                file,
+                start,
+                offset,
+                shared,
+            } => (file.as_ref(), *start, *offset, *shared),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn unmap_file(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        let (file, map_start, offset, shared) = self.file_mapping();
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            let (frame, flags) = match pt.query(addr) {
+                Ok((frame, flags, _)) if !flags.is_empty() => (frame, flags),
+                _ => continue, // not populated yet
+            };
+            // Writable pages of shared mappings are dirty.
+            if shared && flags.contains(MappingFlags::WRITE) {
+                if let Err(e) = write_page(file, page_offset(addr, map_start, offset), frame) {
+                    warn!("failed to write back page {:#x}: {:?}", addr, e);
+                }
+            }
+            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
+                if page_size.is_huge() {
+                    return false;
+                }
+                tlb.flush();
+                // The last mapping of a shared page removes it from the
+                // cache, which is locked so that the page is not mapped again
+                // in the meantime.
+                let mut cache = PAGE_CACHE.lock();
+                if shared && !is_frame_shared(frame) {
+                    cache.remove(&cache_key(file, page_offset(addr, map_start, offset)));
+                }
+                release_frame(frame);
+            }
+        }
+        true
+    }
+
+    /// Writes the dirty pages in `[start, start + size)` of the shared mapping
+    /// back to the file, and marks them clean.
+    pub(crate) fn sync_file(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> AxResult {
+        debug!("sync_file: [{:#x}, {:#x})", start, start + size);
+        let (file, map_start, offset, shared) = self.file_mapping();
+        if !shared {
+            return Ok(()); // Changes of private mappings are never written back.
+        }
+        for addr in PageIter4K::new(start, start + size).unwrap() {
+            match pt.query(addr) {
+                Ok((frame, flags, _)) if flags.contains(MappingFlags::WRITE) => {
+                    write_page(file, page_offset(addr, map_start, offset), frame)?;
+                    // Write-protect the page again to catch the next write.
+                    if let Ok((_, tlb)) = pt.protect(addr, flags - MappingFlags::WRITE) {
+                        tlb.flush();
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        &self,
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        access_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let (file, map_start, offset, shared) = self.file_mapping();
+        let write = access_flags.contains(MappingFlags::WRITE);
+        match pt.query(vaddr) {
+            // The page is mapped (lazy mappings have empty flags) but not
+            // writable, which must be the first write to a clean page of a
+            // shared mapping, or to a copy-on-write page.
+            Ok((frame, flags, _)) if !flags.is_empty() => {
+                if !write || flags.contains(MappingFlags::WRITE) {
+                    false
+                } else if shared {
+                    // Mark the page dirty by making it writable.
+                    pt.protect(vaddr, orig_flags)
+                        .map(|(_, tlb)| tlb.flush())
+                        .is_ok()
+                } else {
+                    handle_cow_fault(vaddr, frame, orig_flags, pt)
+                }
+            }
+            _ if shared => {
+                // Pages of shared mappings are mapped read-only until they are
+                // written, to track the dirty pages.
+                let flags = if write {
+                    orig_flags
+                } else {
+                    orig_flags - MappingFlags::WRITE
+                };
+                let key = cache_key(file, page_offset(vaddr, map_start, offset));
+                let cached = PAGE_CACHE.lock().get(&key).map(|&frame| {
+                    share_frame(frame);
+                    frame
+                });
+                let frame = match cached {
+                    Some(frame) => frame,
+                    None => {
+                        // The file is read without the cache locked, so the
+                        // page may be loaded by another fault in the meantime.
+                        let Some(loaded) = load_page(file, key.1) else {
+                            return false;
+                        };
+                        let mut cache = PAGE_CACHE.lock();
+                        match cache.get(&key) {
+                            Some(&frame) => {
+                                share_frame(frame);
+                                drop(cache);
+                                release_frame(loaded);
+                                frame
+                            }
+                            None => {
+                                cache.insert(key, loaded);
+                                loaded
+                            }
+                        }
+                    }
+                };
+                match pt.remap(vaddr, frame, flags) {
+                    Ok((_, tlb)) => tlb.flush(),
+                    Err(_) => {
+                        let mut cache = PAGE_CACHE.lock();
+                        if !is_frame_shared(frame) {
+                            cache.remove(&key);
+                        }
+                        release_frame(frame);
+                        return false;
+                    }
+                }
+                true
+            }
+            _ => {
+                let Some(frame) = load_page(file, page_offset(vaddr, map_start, offset)) else {
+                    return false;
+                };
+                pt.remap(vaddr, frame, orig_flags)
+                    .map(|(_, tlb)| tlb.flush())
+                    .is_ok()
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Helpers for huge pages (2M and 1G) in the page table.
+
+use axhal::paging::{PageSize, PageTable, PagingResult};
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// Returns the largest page size that `vaddr` is aligned to, and that fits
+/// in `size`.
+pub(super) fn max_page_size(vaddr: VirtAddr, size: usize) -> PageSize {
+    for page_size in [PageSize::Size1G, PageSize::Size2M] {
+        if page_size.is_aligned(vaddr.as_usize()) && size >= usize::from(page_size) {
+            return page_size;
+        }
+    }
+    PageSize::Size4K
+}
+
+/// Returns the next smaller page size of a huge page.
+pub(super) fn smaller_page_size(page_size: PageSize) -> PageSize {
+    match page_size {
+        PageSize::Size1G => PageSize::Size2M,
+        _ => PageSize::Size4K,
+    }
+}
+
+/// Splits the huge page containing `vaddr` into pages of the next smaller
+/// size, which map the same frames with the same flags.
+fn split_huge_page(pt: &mut PageTable, vaddr: VirtAddr) -> PagingResult {
+    let (paddr, flags, page_size) = pt.query(vaddr)?;
+    let start = vaddr.align_down(page_size);
+    let paddr = paddr.align_down(page_size);
+    let small_size = smaller_page_size(page_size);
+    // The translation is not changed, so the TLB is flushed after the new
+    // pages are mapped, in case the page is being accessed.
+    let (_, _, tlb) = pt.unmap(start)?;
+    for offset in (0..usize::from(page_size)).step_by(small_size.into()) {
+        if let Err(e) = pt.map(start + offset, paddr + offset, small_size, flags) {
+            // Only the first mapping can fail, when allocating the page table
+            // for the small pages, so the huge page can be mapped back.
+            debug_assert_eq!(offset, 0);
+            pt.map(start, paddr, page_size, flags)?.ignore();
+            tlb.ignore();
+            return Err(e);
+        }
+    }
+    tlb.flush();
+    Ok(())
+}
+
+/// Splits the huge page containing `vaddr`, if any, until `vaddr` is mapped
+/// by a 4K page.
+///
+/// Operations on a single 4K page have to call it first, as the page table
+/// fails them with [`PagingError::MappedToHugePage`](axhal::paging::PagingError)
+/// or applies them to the whole huge page.
+pub(super) fn split_to_4k(pt: &mut PageTable, vaddr: VirtAddr) -> PagingResult {
+    split_huge_pages(pt, vaddr.align_down_4k(), PAGE_SIZE_4K)
+}
+
+/// Splits the huge pages crossing the boundaries of `[start, start + size)`,
+/// so that the range can be unmapped or protected page by page.
+pub(crate) fn split_huge_pages_at_bounds(
+    pt: &mut PageTable,
+    start: VirtAddr,
+    size: usize,
+) -> PagingResult {
+    for vaddr in [start, start + size] {
+        while let Ok((_, _, page_size)) = pt.query(vaddr) {
+            if page_size.is_aligned(vaddr.as_usize()) {
+                break;
+            }
+            split_huge_page(pt, vaddr)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits all the huge pages in `[start, start + size)` into 4K pages.
+pub(super) fn split_huge_pages(pt: &mut PageTable, start: VirtAddr, size: usize) -> PagingResult {
+    let mut vaddr = start;
+    while vaddr < start + size {
+        match pt.query(vaddr) {
+            Ok((_, _, page_size)) if page_size.is_huge() => split_huge_page(pt, vaddr)?,
+            _ => vaddr += PAGE_SIZE_4K,
+        }
+    }
+    Ok(())
+}
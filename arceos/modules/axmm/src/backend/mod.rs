@@ -1,21 +1,47 @@
 //! Memory mapping backends.
 #![allow(dead_code)]
 
-use axhal::paging::{MappingFlags, PageTable};
-use memory_addr::VirtAddr;
+use ::alloc::sync::Arc;
+use axerrno::AxResult;
+use axhal::paging::{MappingFlags, PageTable, PagingResult};
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
 use memory_set::MappingBackend;
 
 mod alloc;
+mod file;
+mod huge;
 mod linear;
+mod shared;
+
+pub use self::file::MappedFile;
+pub use self::shared::SharedPages;
+
+#[cfg(feature = "swap")]
+pub(crate) use self::alloc::swap_out_page;
+pub(crate) use self::huge::split_huge_pages_at_bounds;
 
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, four backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
+///   Huge pages are used if the addresses and the size are aligned.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
-///   frames are obtained from the global allocator.
+///   frames are obtained from the global allocator. Populated mappings use
+///   huge pages where possible. The frames can be shared by copy-on-write
+///   clones of the address space (see
+///   [`AddrSpace::clone_cow`](crate::AddrSpace::clone_cow)). With the `swap`
+///   feature, the pages of user mappings can be swapped out (see
+///   [`AddrSpace::swap_out_pages`](crate::AddrSpace::swap_out_pages)).
+/// - **File**: used for memory-mapped files. The target physical frames are
+///   obtained from the global allocator, and filled with the file contents on
+///   demand.
+/// - **Shared**: used for shared memory. The target physical frames are
+///   allocated when the shared memory object is created, and can be mapped
+///   into several address spaces at different addresses.
+///
+/// Huge pages are split when a part of them is unmapped or protected.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -37,6 +63,38 @@ pub enum Backend {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
     },
+    /// File mapping backend.
+    ///
+    /// The pages are loaded from the file on demand (by handling page faults).
+    /// Changes to a shared mapping are written back to the file when the
+    /// mapping is synchronized or unmapped, while a private mapping gets
+    /// copy-on-write pages, whose changes are never written back. All shared
+    /// mappings of a file page map the same frame, so they see the changes of
+    /// each other immediately.
+    File {
+        /// The mapped file.
+        file: Arc<dyn MappedFile>,
+        /// The start address of the whole mapping, which is kept when the
+        /// mapping is split.
+        start: VirtAddr,
+        /// The file offset mapped at `start`.
+        offset: u64,
+        /// Whether the mapping is shared with the file (`MAP_SHARED`).
+        shared: bool,
+    },
+    /// Shared memory backend.
+    ///
+    /// All the pages are mapped when the mapping is created. The frames are
+    /// not freed on unmap, but when the last reference to them is dropped.
+    Shared {
+        /// The mapped pages.
+        pages: Arc<SharedPages>,
+        /// The start address of the whole mapping, which is kept when the
+        /// mapping is split.
+        start: VirtAddr,
+        /// The offset in the pages mapped at `start`.
+        offset: usize,
+    },
 }
 
 impl MappingBackend for Backend {
@@ -47,6 +105,8 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, pa_va_offset),
             Self::Alloc { populate } => self.map_alloc(start, size, flags, pt, populate),
+            Self::File { .. } => self.map_file(start, size, flags, pt),
+            Self::Shared { .. } => self.map_shared(start, size, flags, pt),
         }
     }
 
@@ -54,6 +114,8 @@ impl MappingBackend for Backend {
         match *self {
             Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, pa_va_offset),
             Self::Alloc { populate } => self.unmap_alloc(start, size, pt, populate),
+            Self::File { .. } => self.unmap_file(start, size, pt),
+            Self::Shared { .. } => self.unmap_shared(start, size, pt),
         }
     }
 
@@ -64,25 +126,119 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        if split_huge_pages_at_bounds(page_table, start, size).is_err() {
+            return false;
+        }
+        protect_region(page_table, start, size, new_flags).is_ok()
+            && self.write_protect_cow(start, size, page_table)
+    }
+}
+
+/// Protects the pages in `[start, start + size)` with `flags`, after the huge
+/// pages at the bounds are split.
+///
+/// Swapped-out pages are not present, so only the flags to restore when they
+/// are swapped in are changed.
+pub(crate) fn protect_region(
+    pt: &mut PageTable,
+    start: VirtAddr,
+    size: usize,
+    flags: MappingFlags,
+) -> PagingResult {
+    let end = start + size;
+    #[cfg(feature = "swap")]
+    let swapped = crate::swap::protect_swap_entries(pt, start, end, flags);
+    #[cfg(not(feature = "swap"))]
+    let swapped = [];
+    let mut addr = start;
+    for hole in swapped.into_iter().chain([end]) {
+        if addr < hole {
+            pt.protect_region(addr, hole - addr, flags, true)?.ignore();
+        }
+        addr = hole + PAGE_SIZE_4K;
     }
+    Ok(())
 }
 
 impl Backend {
+    /// Returns the backend for the copy-on-write clone of a mapping of this
+    /// backend.
+    ///
+    /// Allocation mappings are cloned as lazy ones, whose populated pages are
+    /// then shared by [`Backend::share_cow`].
+    pub(crate) fn cow_backend(&self) -> Self {
+        match *self {
+            Self::Linear { .. } | Self::File { .. } | Self::Shared { .. } => self.clone(),
+            Self::Alloc { .. } => Self::new_alloc(false),
+        }
+    }
+
+    /// Shares the populated pages in `[start, start + size)` of `src_pt` with
+    /// `dst_pt` as copy-on-write pages. Pages of shared file mappings and
+    /// shared memory mappings are shared as is.
+    ///
+    /// The range must have been mapped in `dst_pt` by the backend returned
+    /// by [`Backend::cow_backend`].
+    pub(crate) fn share_cow(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        src_pt: &mut PageTable,
+        dst_pt: &mut PageTable,
+    ) -> bool {
+        match *self {
+            Self::Linear { .. } | Self::Shared { .. } => true, // The same frames have been mapped.
+            Self::Alloc { .. } => alloc::share_frames(start, size, src_pt, dst_pt, true),
+            Self::File { shared, .. } => alloc::share_frames(start, size, src_pt, dst_pt, !shared),
+        }
+    }
+
+    /// Write-protects the copy-on-write pages in `[start, start + size)`
+    /// again, after the range is protected with new flags.
+    pub(crate) fn write_protect_cow(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        page_table: &mut PageTable,
+    ) -> bool {
+        match *self {
+            Self::Alloc { .. } | Self::File { shared: false, .. } => {
+                alloc::write_protect_shared(start, size, page_table)
+            }
+            _ => true,
+        }
+    }
+
+    /// Writes the changes in `[start, start + size)` back to the backing
+    /// store, if any.
+    pub(crate) fn sync(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        page_table: &mut PageTable,
+    ) -> AxResult {
+        match *self {
+            Self::File { .. } => self.sync_file(start, size, page_table),
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) fn handle_page_fault(
         &self,
         vaddr: VirtAddr,
         orig_flags: MappingFlags,
+        access_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
         match *self {
-            Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
+            // Linear and shared memory mappings should not trigger page faults.
+            Self::Linear { .. } | Self::Shared { .. } => false,
             Self::Alloc { populate } => {
                 self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
             }
+            Self::File { .. } => {
+                self.handle_page_fault_file(vaddr, orig_flags, access_flags, page_table)
+            }
         }
     }
 }
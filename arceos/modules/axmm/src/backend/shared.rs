@@ -0,0 +1,143 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxResult};
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame};
+use super::{Backend, MappedFile};
+
+/// Physical frames that can be mapped into several address spaces, e.g., for
+/// shared memory objects.
+///
+/// The frames are zeroed when allocated, and freed when the last reference
+/// is dropped, i.e., when all the mappings and the owner of the object have
+/// gone.
+///
+/// See [`AddrSpace::map_shared`](crate::AddrSpace::map_shared).
+pub struct SharedPages {
+    frames: Vec<PhysAddr>,
+}
+
+impl SharedPages {
+    /// Allocates the frames of `size` bytes, which is rounded up to pages.
+    pub fn new(size: usize) -> AxResult<Arc<Self>> {
+        let num_pages = size.div_ceil(PAGE_SIZE_4K);
+        let mut frames = Vec::with_capacity(num_pages);
+        for _ in 0..num_pages {
+            match alloc_frame(true) {
+                Some(frame) => frames.push(frame),
+                None => {
+                    // The allocated frames are freed on drop.
+                    drop(Self { frames });
+                    return ax_err!(NoMemory);
+                }
+            }
+        }
+        Ok(Arc::new(Self { frames }))
+    }
+
+    /// Returns the size of the pages in bytes.
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE_4K
+    }
+
+    /// Calls `f` with the part of each page in `[offset, offset + len)`,
+    /// and its position in the range.
+    fn for_each_chunk(&self, offset: usize, len: usize, mut f: impl FnMut(usize, &mut [u8])) {
+        let end = (offset + len).min(self.size());
+        let mut pos = offset;
+        while pos < end {
+            let page_off = pos % PAGE_SIZE_4K;
+            let chunk_len = (PAGE_SIZE_4K - page_off).min(end - pos);
+            let page = phys_to_virt(self.frames[pos / PAGE_SIZE_4K]);
+            let chunk = unsafe {
+                core::slice::from_raw_parts_mut(page.as_mut_ptr().add(page_off), chunk_len)
+            };
+            f(pos - offset, chunk);
+            pos += chunk_len;
+        }
+    }
+}
+
+impl Drop for SharedPages {
+    fn drop(&mut self) {
+        for &frame in &self.frames {
+            dealloc_frame(frame);
+        }
+    }
+}
+
+/// The pages can also be mapped as a file, for private mappings of them.
+impl MappedFile for SharedPages {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let mut read_len = 0;
+        self.for_each_chunk(offset as usize, buf.len(), |pos, chunk| {
+            buf[pos..pos + chunk.len()].copy_from_slice(chunk);
+            read_len += chunk.len();
+        });
+        Ok(read_len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let mut write_len = 0;
+        self.for_each_chunk(offset as usize, buf.len(), |pos, chunk| {
+            chunk.copy_from_slice(&buf[pos..pos + chunk.len()]);
+            write_len += chunk.len();
+        });
+        Ok(write_len)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.size() as u64)
+    }
+}
+
+impl Backend {
+    /// Creates a new shared memory backend, which maps the pages from
+    /// `offset` at the virtual address `start`.
+    pub fn new_shared(pages: Arc<SharedPages>, start: VirtAddr, offset: usize) -> Self {
+        Self::Shared {
+            pages,
+            start,
+            offset,
+        }
+    }
+
+    pub(crate) fn map_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Self::Shared {
+            pages,
+            start: map_start,
+            offset,
+        } = self
+        else {
+            unreachable!()
+        };
+        debug!(
+            "map_shared: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            flags
+        );
+        let va_to_pa = |va: VirtAddr| pages.frames[(offset + (va - *map_start)) / PAGE_SIZE_4K];
+        pt.map_region(start, va_to_pa, size, flags, false, false)
+            .map(|tlb| tlb.ignore()) // TLB flush on map is unnecessary, as there are no outdated mappings.
+            .is_ok()
+    }
+
+    pub(crate) fn unmap_shared(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_shared: [{:#x}, {:#x})", start, start + size);
+        // The frames are freed with the last reference to the pages.
+        pt.unmap_region(start, size, true)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
+}
@@ -1,6 +1,6 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate log;
@@ -8,8 +8,16 @@ extern crate alloc;
 
 mod aspace;
 mod backend;
+#[cfg(feature = "swap")]
+mod swap;
+
+#[cfg(test)]
+mod tests;
 
 pub use self::aspace::AddrSpace;
+pub use self::backend::{MappedFile, SharedPages};
+#[cfg(feature = "swap")]
+pub use self::swap::{register_swappable, swap_on, SwapDevice};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -40,7 +48,9 @@ fn paging_err_to_ax_err(err: PagingError) -> AxError {
         PagingError::NotAligned => AxError::InvalidInput,
         PagingError::NotMapped => AxError::NotFound,
         PagingError::AlreadyMapped => AxError::AlreadyExists,
-        PagingError::MappedToHugePage => AxError::InvalidInput,
+        // Operations on 4K pages split the huge pages first, so it is only
+        // returned when mapping an address already mapped by a huge page.
+        PagingError::MappedToHugePage => AxError::AlreadyExists,
     }
 }
 
@@ -0,0 +1,234 @@
+//! Swapping of anonymous pages to a swap device.
+//!
+//! Pages of allocation mappings in user address spaces are swapped out when
+//! free memory runs low (see [`AddrSpace::swap_out_pages`]), first from the
+//! faulting address space, then from the others registered with
+//! [`register_swappable`]. Pages are only reclaimed on page faults and
+//! populated mappings of user address spaces, never from inside the global
+//! allocator, where the locks of the page tables and the swap entries may be
+//! held and the swap device cannot be accessed.
+//!
+//! A swapped-out page is left as an empty entry in the page table, like a
+//! lazy page, and its slot in the swap area is recorded in a swap entry
+//! beside the page table. It is swapped in on the next page fault.
+//!
+//! [`AddrSpace::swap_out_pages`]: crate::AddrSpace::swap_out_pages
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// The number of free pages in the global allocator below which pages are
+/// swapped out on page faults, to keep some memory for the kernel.
+pub(crate) const MIN_FREE_PAGES: usize = 64;
+
+/// A device that holds a swap area, which is an array of page-sized slots.
+pub trait SwapDevice: Send + Sync {
+    /// Returns the number of pages the swap area can hold.
+    fn num_pages(&self) -> usize;
+
+    /// Reads the page in the slot of `index` into `buf`, which is of the
+    /// page size.
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult;
+
+    /// Writes the page in `buf`, which is of the page size, to the slot of
+    /// `index`.
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult;
+}
+
+struct SwapArea {
+    dev: Box<dyn SwapDevice>,
+    /// Reference counts of the slots, where free slots have 0. A slot is
+    /// shared by copy-on-write clones of an address space.
+    slots: SpinNoIrq<Vec<usize>>,
+}
+
+static SWAP_AREA: LazyInit<SwapArea> = LazyInit::new();
+
+/// A swapped-out page.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SwapEntry {
+    /// The slot of the page in the swap area.
+    pub slot: usize,
+    /// The flags of the page, which are restored when it is swapped in.
+    pub flags: MappingFlags,
+}
+
+/// The swap entries of all page tables, indexed by the root of the page
+/// table and the virtual address of the page.
+static SWAP_ENTRIES: SpinNoIrq<BTreeMap<(PhysAddr, VirtAddr), SwapEntry>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// Swaps out pages of a registered address space, or returns `None` if it
+/// has been dropped.
+type SwapOutFn = dyn Fn(usize) -> Option<usize> + Send + Sync;
+
+/// The address spaces whose pages can be swapped out for others.
+static SWAPPABLE: SpinNoIrq<Vec<Arc<SwapOutFn>>> = SpinNoIrq::new(Vec::new());
+
+/// The registered address space where the next reclaim starts, so that the
+/// victim pages are taken from them in turn.
+static RECLAIM_HAND: AtomicUsize = AtomicUsize::new(0);
+
+/// Enables swapping to `dev`.
+///
+/// Only one swap area is supported, and it cannot be turned off.
+pub fn swap_on(dev: Box<dyn SwapDevice>) -> AxResult {
+    if SWAP_AREA.is_inited() {
+        return ax_err!(AlreadyExists, "swap area already exists");
+    }
+    let num_pages = dev.num_pages();
+    info!("swap on: {} pages", num_pages);
+    SWAP_AREA.init_once(SwapArea {
+        dev,
+        slots: SpinNoIrq::new(vec![0; num_pages]),
+    });
+    Ok(())
+}
+
+/// Registers an address space behind a lock, e.g., an
+/// `Arc<Mutex<AddrSpace>>`, whose pages can then be swapped out when other
+/// address spaces run out of memory.
+///
+/// `swap_out` swaps out at most the given number of pages of the address
+/// space with [`AddrSpace::swap_out_pages`], and returns the number of pages
+/// swapped out. It must not wait for the lock, which may be held by the
+/// task reclaiming the pages, e.g.:
+///
+/// ```ignore
+/// axmm::register_swappable(&aspace, |aspace, n| {
+///     aspace.try_lock().map_or(0, |mut aspace| aspace.swap_out_pages(n))
+/// });
+/// ```
+///
+/// The address space is unregistered after it is dropped.
+///
+/// [`AddrSpace::swap_out_pages`]: crate::AddrSpace::swap_out_pages
+pub fn register_swappable<T>(space: &Arc<T>, swap_out: fn(&T, usize) -> usize)
+where
+    T: Send + Sync + 'static,
+{
+    let space = Arc::downgrade(space);
+    SWAPPABLE.lock().push(Arc::new(move |num_pages| {
+        space.upgrade().map(|space| swap_out(&space, num_pages))
+    }));
+}
+
+/// Swaps out at most `num_pages` pages of the registered address spaces, and
+/// returns the number of pages swapped out.
+///
+/// Address spaces that are locked are skipped. It is only called when an
+/// address space reclaims pages for a page fault or a populated mapping, with
+/// no locks of the swap entries, the shared frames or the page cache held.
+pub(crate) fn reclaim(num_pages: usize) -> usize {
+    if !is_enabled() {
+        return 0;
+    }
+    let mut count = 0;
+    let mut tried = 0;
+    while count < num_pages {
+        // The registry is not locked while swapping out, which may allocate
+        // memory or sleep on the swap device.
+        let swap_out = {
+            let spaces = SWAPPABLE.lock();
+            if tried >= spaces.len() {
+                break;
+            }
+            let hand = RECLAIM_HAND.fetch_add(1, Ordering::Relaxed) % spaces.len();
+            spaces[hand].clone()
+        };
+        tried += 1;
+        match swap_out(num_pages - count) {
+            Some(swapped) => count += swapped,
+            None => SWAPPABLE.lock().retain(|f| !Arc::ptr_eq(f, &swap_out)),
+        }
+    }
+    count
+}
+
+/// Returns `true` if a swap area is enabled.
+pub(crate) fn is_enabled() -> bool {
+    SWAP_AREA.is_inited()
+}
+
+fn entry_key(pt: &PageTable, vaddr: VirtAddr) -> (PhysAddr, VirtAddr) {
+    (pt.root_paddr(), vaddr.align_down_4k())
+}
+
+/// Returns the swap entry of the page at `vaddr`, or `None` if it is not
+/// swapped out.
+pub(crate) fn swap_entry(pt: &PageTable, vaddr: VirtAddr) -> Option<SwapEntry> {
+    SWAP_ENTRIES.lock().get(&entry_key(pt, vaddr)).copied()
+}
+
+/// Records the page at `vaddr`, whose entry must have been cleared, as
+/// swapped out.
+pub(crate) fn set_swap_entry(pt: &PageTable, vaddr: VirtAddr, entry: SwapEntry) {
+    SWAP_ENTRIES.lock().insert(entry_key(pt, vaddr), entry);
+}
+
+/// Removes the swap entry of the page at `vaddr`, if any.
+pub(crate) fn take_swap_entry(pt: &PageTable, vaddr: VirtAddr) -> Option<SwapEntry> {
+    SWAP_ENTRIES.lock().remove(&entry_key(pt, vaddr))
+}
+
+/// Sets the flags of the swapped-out pages in `[start, end)` to `flags`, and
+/// returns their addresses in ascending order.
+pub(crate) fn protect_swap_entries(
+    pt: &PageTable,
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: MappingFlags,
+) -> Vec<VirtAddr> {
+    let root = pt.root_paddr();
+    let mut entries = SWAP_ENTRIES.lock();
+    entries
+        .range_mut((root, start)..(root, end))
+        .map(|(&(_, vaddr), entry)| {
+            entry.flags = flags;
+            vaddr
+        })
+        .collect()
+}
+
+/// Allocates a free slot, or returns `None` if the swap area is full or not
+/// enabled.
+pub(crate) fn alloc_slot() -> Option<usize> {
+    let mut slots = SWAP_AREA.get()?.slots.lock();
+    let slot = slots.iter().position(|&count| count == 0)?;
+    slots[slot] = 1;
+    Some(slot)
+}
+
+/// Adds a reference to the slot.
+pub(crate) fn dup_slot(slot: usize) {
+    SWAP_AREA.slots.lock()[slot] += 1;
+}
+
+/// Removes a reference to the slot, and frees it if there are no others.
+pub(crate) fn free_slot(slot: usize) {
+    SWAP_AREA.slots.lock()[slot] -= 1;
+}
+
+/// Writes the page in `frame` to the slot.
+pub(crate) fn write_slot(slot: usize, frame: PhysAddr) -> AxResult {
+    let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), PAGE_SIZE_4K) };
+    SWAP_AREA.dev.write_page(slot, buf)
+}
+
+/// Reads the page in the slot into `frame`.
+pub(crate) fn read_slot(slot: usize, frame: PhysAddr) -> AxResult {
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    SWAP_AREA.dev.read_page(slot, buf)
+}
@@ -0,0 +1,684 @@
+use std::sync::{Arc, Mutex, Once};
+
+use axalloc::global_allocator;
+use axerrno::AxResult;
+use axhal::paging::{MappingFlags, PageSize};
+use memory_addr::{va, VirtAddr, PAGE_SIZE_4K};
+
+use crate::{AddrSpace, MappedFile, SharedPages};
+
+static INIT: Once = Once::new();
+static SERIAL: Mutex<()> = Mutex::new(());
+
+const HEAP_SIZE: usize = 0x100_0000; // 16M
+
+#[repr(align(4096))]
+struct Heap([u8; HEAP_SIZE]);
+
+static mut HEAP: Heap = Heap([0; HEAP_SIZE]);
+
+const BASE: usize = 0x1000_0000;
+const SIZE: usize = 0x10_0000;
+const FLAGS: MappingFlags = MappingFlags::READ
+    .union(MappingFlags::WRITE)
+    .union(MappingFlags::USER);
+
+fn init() {
+    INIT.call_once(|| {
+        let start = unsafe { core::ptr::addr_of_mut!(HEAP) as usize };
+        axalloc::global_init(start, HEAP_SIZE);
+    });
+}
+
+/// Writes to the address space like a user task does, i.e., triggers page
+/// faults first if the pages are not mapped or not writable.
+fn user_write(aspace: &mut AddrSpace, start: VirtAddr, buf: &[u8]) {
+    let mut vaddr = start.align_down_4k();
+    while vaddr < start + buf.len() {
+        match aspace.page_table().query(vaddr) {
+            Ok((_, flags, _)) if flags.contains(MappingFlags::WRITE) => {}
+            _ => assert!(aspace.handle_page_fault(vaddr, MappingFlags::WRITE)),
+        }
+        vaddr += PAGE_SIZE_4K;
+    }
+    aspace.write(start, buf).unwrap();
+}
+
+fn user_read(aspace: &mut AddrSpace, start: VirtAddr, len: usize) -> Vec<u8> {
+    let mut vaddr = start.align_down_4k();
+    while vaddr < start + len {
+        if !matches!(aspace.page_table().query(vaddr), Ok((_, flags, _)) if !flags.is_empty()) {
+            assert!(aspace.handle_page_fault(vaddr, MappingFlags::READ));
+        }
+        vaddr += PAGE_SIZE_4K;
+    }
+    let mut buf = vec![0; len];
+    aspace.read(start, &mut buf).unwrap();
+    buf
+}
+
+fn paddr_of(aspace: &AddrSpace, vaddr: VirtAddr) -> usize {
+    aspace.page_table().query(vaddr).unwrap().0.as_usize()
+}
+
+#[test]
+fn test_clone_cow_isolation() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let mut parent = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let populated = va!(BASE);
+    let lazy = va!(BASE + 0x8000);
+    parent.map_alloc(populated, 0x4000, FLAGS, true).unwrap();
+    parent.map_alloc(lazy, 0x4000, FLAGS, false).unwrap();
+    user_write(&mut parent, populated + 0x10, b"parent data");
+    user_write(&mut parent, lazy + 0x1010, b"lazy data");
+
+    let mut child = parent.clone_cow().unwrap();
+    assert_eq!(child.areas.len(), 2);
+
+    // Populated pages are shared read-only, and the others are left unmapped.
+    assert_eq!(paddr_of(&parent, populated), paddr_of(&child, populated));
+    assert_eq!(
+        paddr_of(&parent, lazy + 0x1000),
+        paddr_of(&child, lazy + 0x1000)
+    );
+    for aspace in [&parent, &child] {
+        let (_, flags, _) = aspace.page_table().query(populated).unwrap();
+        assert!(!flags.contains(MappingFlags::WRITE));
+    }
+    assert_eq!(user_read(&mut child, populated + 0x10, 11), b"parent data");
+    assert_eq!(user_read(&mut child, lazy + 0x1010, 9), b"lazy data");
+
+    // Writes in either address space are not visible to the other, also
+    // when faulting at unaligned addresses.
+    assert!(child.handle_page_fault(populated + 0x10, MappingFlags::WRITE));
+    user_write(&mut child, populated + 0x10, b"child data!");
+    user_write(&mut parent, lazy + 0x1010, b"LAZY DATA");
+    user_write(&mut child, lazy + 0x2000, b"child only");
+    assert_ne!(paddr_of(&parent, populated), paddr_of(&child, populated));
+    assert_ne!(
+        paddr_of(&parent, lazy + 0x1000),
+        paddr_of(&child, lazy + 0x1000)
+    );
+
+    assert_eq!(user_read(&mut parent, populated + 0x10, 11), b"parent data");
+    assert_eq!(user_read(&mut child, populated + 0x10, 11), b"child data!");
+    assert_eq!(user_read(&mut parent, lazy + 0x1010, 9), b"LAZY DATA");
+    assert_eq!(user_read(&mut child, lazy + 0x1010, 9), b"lazy data");
+    assert_eq!(user_read(&mut parent, lazy + 0x2000, 10), [0; 10]);
+    assert_eq!(user_read(&mut child, lazy + 0x2000, 10), b"child only");
+
+    // The last owner of a shared page writes to it in place.
+    let paddr = paddr_of(&parent, populated + 0x1000);
+    drop(child);
+    user_write(&mut parent, populated + 0x1000, b"no copy");
+    assert_eq!(paddr_of(&parent, populated + 0x1000), paddr);
+}
+
+#[test]
+fn test_fault_on_populated_page() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let mut aspace = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let lazy = va!(BASE);
+    aspace.map_alloc(lazy, 0x2000, FLAGS, false).unwrap();
+    user_write(&mut aspace, lazy + 0x10, b"first fault");
+    let paddr = paddr_of(&aspace, lazy);
+
+    // Spurious faults on present pages keep the frame and its contents.
+    let used = global_allocator().used_pages();
+    for _ in 0..2 {
+        assert!(aspace.handle_page_fault(lazy + 0x10, MappingFlags::WRITE));
+        assert!(aspace.handle_page_fault(lazy + 0x10, MappingFlags::READ));
+    }
+    assert_eq!(paddr_of(&aspace, lazy), paddr);
+    assert_eq!(global_allocator().used_pages(), used);
+    assert_eq!(user_read(&mut aspace, lazy + 0x10, 11), b"first fault");
+}
+
+#[test]
+fn test_clone_cow_reclaim() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let mut parent = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    parent.map_alloc(va!(BASE), 0x10000, FLAGS, true).unwrap();
+
+    // Fork twice, and write to some pages in each address space.
+    let mut child1 = parent.clone_cow().unwrap();
+    let mut child2 = child1.clone_cow().unwrap();
+    user_write(&mut parent, va!(BASE), &[1; 0x3000]);
+    user_write(&mut child1, va!(BASE + 0x2000), &[2; 0x3000]);
+    user_write(&mut child2, va!(BASE + 0x4000), &[3; 0x3000]);
+    assert_eq!(user_read(&mut parent, va!(BASE + 0x4000), 1), [0]);
+    assert_eq!(user_read(&mut child1, va!(BASE), 1), [0]);
+    assert_eq!(user_read(&mut child2, va!(BASE + 0x2000), 1), [0]);
+
+    // All frames are freed whatever the order the address spaces go away.
+    drop(child1);
+    drop(parent);
+    user_write(&mut child2, va!(BASE), &[4; 0x10000]);
+    drop(child2);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+#[test]
+fn test_clone_cow_protect() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let file = Arc::new(MemFile(Mutex::new(vec![7; 0x2000])));
+    let mut parent = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let anon = va!(BASE);
+    let private = va!(BASE + 0x8000);
+    parent.map_alloc(anon, 0x2000, FLAGS, true).unwrap();
+    parent
+        .map_file(private, 0x2000, FLAGS, file.clone(), 0, false)
+        .unwrap();
+    user_write(&mut parent, anon, b"parent");
+    assert_eq!(user_read(&mut parent, private, 1), [7]);
+
+    let mut child = parent.clone_cow().unwrap();
+
+    // Protecting the pages read-write again keeps the shared pages
+    // write-protected in both address spaces.
+    let ro = MappingFlags::READ | MappingFlags::USER;
+    for aspace in [&mut parent, &mut child] {
+        for (vaddr, size) in [(anon, 0x2000), (private, 0x1000)] {
+            aspace.protect(vaddr, size, ro).unwrap();
+            aspace.protect(vaddr, size, FLAGS).unwrap();
+            let (_, flags, _) = aspace.page_table().query(vaddr).unwrap();
+            assert!(!flags.contains(MappingFlags::WRITE));
+        }
+    }
+
+    // So writes after that are still not visible to the other.
+    user_write(&mut child, anon, b"child!");
+    user_write(&mut child, private, b"child!");
+    assert_eq!(user_read(&mut parent, anon, 6), b"parent");
+    assert_eq!(user_read(&mut parent, private, 6), [7; 6]);
+    user_write(&mut parent, private, b"parent");
+    assert_eq!(user_read(&mut child, private, 6), b"child!");
+    assert_eq!(&file.0.lock().unwrap()[..6], [7; 6]);
+}
+
+struct MemFile(Mutex<Vec<u8>>);
+
+impl MappedFile for MemFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let data = self.0.lock().unwrap();
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let mut data = self.0.lock().unwrap();
+        let end = offset as usize + buf.len();
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.0.lock().unwrap().len() as u64)
+    }
+}
+
+#[test]
+fn test_map_file() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let content: Vec<u8> = (0..0x2800).map(|i| (i % 251) as u8).collect();
+    let file = Arc::new(MemFile(Mutex::new(content.clone())));
+    let mut aspace = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let shared = va!(BASE);
+    let private = va!(BASE + 0x8000);
+    aspace
+        .map_file(shared, 0x3000, FLAGS, file.clone(), 0x1000, true)
+        .unwrap();
+    aspace
+        .map_file(private, 0x4000, FLAGS, file.clone(), 0, false)
+        .unwrap();
+
+    // Pages are loaded on demand, and zero-filled beyond the end of the file.
+    assert!(!matches!(aspace.page_table().query(shared), Ok((_, flags, _)) if !flags.is_empty()));
+    assert_eq!(user_read(&mut aspace, shared, 0x1800), content[0x1000..]);
+    assert_eq!(user_read(&mut aspace, shared + 0x1800, 0x10), [0; 0x10]);
+    assert_eq!(user_read(&mut aspace, private, 0x2800), content);
+
+    // Only changes to the shared mapping are written back, and the file is
+    // not extended.
+    user_write(&mut aspace, shared + 0x10, b"shared");
+    user_write(&mut aspace, shared + 0x17fb, b"overflow");
+    user_write(&mut aspace, private + 0x1020, b"private");
+    aspace.msync(shared, 0x3000).unwrap();
+    let data = file.0.lock().unwrap().clone();
+    assert_eq!(data.len(), 0x2800);
+    assert_eq!(&data[0x1010..0x1016], b"shared");
+    assert_eq!(&data[0x27fb..], b"overf");
+    assert_eq!(&data[0x1020..0x1027], &content[0x1020..0x1027]);
+    assert_eq!(
+        user_read(&mut aspace, private + 0x1010, 6),
+        content[0x1010..0x1016]
+    );
+
+    // Pages are clean after synchronized, and dirty pages are written back
+    // on unmap.
+    let (_, flags, _) = aspace.page_table().query(shared).unwrap();
+    assert!(!flags.contains(MappingFlags::WRITE));
+    user_write(&mut aspace, shared + 0x1000, b"unmapped");
+    aspace.unmap(shared, 0x3000).unwrap();
+    assert_eq!(&file.0.lock().unwrap()[0x2000..0x2008], b"unmapped");
+
+    drop(aspace);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+#[test]
+fn test_map_file_shared_coherent() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let file = Arc::new(MemFile(Mutex::new(vec![0; 0x3000])));
+    let mut aspace1 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let mut aspace2 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let (start1, start2) = (va!(BASE), va!(BASE + 0x8000));
+    aspace1
+        .map_file(start1, 0x3000, FLAGS, file.clone(), 0, true)
+        .unwrap();
+    aspace2
+        .map_file(start2, 0x2000, FLAGS, file.clone(), 0x1000, true)
+        .unwrap();
+
+    // Shared mappings of the same file page see the changes of each other
+    // before they are written back.
+    user_write(&mut aspace1, start1 + 0x1010, b"from 1");
+    assert_eq!(user_read(&mut aspace2, start2 + 0x10, 6), b"from 1");
+    user_write(&mut aspace2, start2 + 0x1020, b"from 2");
+    assert_eq!(user_read(&mut aspace1, start1 + 0x2020, 6), b"from 2");
+    assert_eq!(
+        paddr_of(&aspace1, start1 + 0x1000),
+        paddr_of(&aspace2, start2)
+    );
+    assert_eq!(&file.0.lock().unwrap()[0x1010..0x1016], [0; 6]);
+
+    // The page is dropped from the cache with its last mapping, and loaded
+    // from the file again.
+    aspace1.unmap(start1, 0x3000).unwrap();
+    assert_eq!(user_read(&mut aspace2, start2 + 0x10, 6), b"from 1");
+    aspace2.unmap(start2, 0x2000).unwrap();
+    aspace1
+        .map_file(start1, 0x3000, FLAGS, file.clone(), 0, true)
+        .unwrap();
+    assert_eq!(user_read(&mut aspace1, start1 + 0x1010, 6), b"from 1");
+    assert_eq!(user_read(&mut aspace1, start1 + 0x2020, 6), b"from 2");
+
+    drop(aspace1);
+    drop(aspace2);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+/// A handle of a [`MemFile`], like an opened file of an inode.
+struct MemFileHandle(Arc<MemFile>);
+
+impl MappedFile for MemFileHandle {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.0.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.0.write_at(offset, buf)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        self.0.size()
+    }
+
+    fn identity(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+}
+
+#[test]
+fn test_map_file_shared_handles() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let file = Arc::new(MemFile(Mutex::new(vec![0; 0x2000])));
+    let mut aspace1 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let mut aspace2 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let start = va!(BASE);
+    // Each mapping has its own handle of the same file.
+    for aspace in [&mut aspace1, &mut aspace2] {
+        let handle = Arc::new(MemFileHandle(file.clone()));
+        aspace
+            .map_file(start, 0x2000, FLAGS, handle, 0, true)
+            .unwrap();
+    }
+
+    user_write(&mut aspace1, start + 0x1010, b"handle 1");
+    assert_eq!(user_read(&mut aspace2, start + 0x1010, 8), b"handle 1");
+    assert_eq!(
+        paddr_of(&aspace1, start + 0x1000),
+        paddr_of(&aspace2, start + 0x1000)
+    );
+
+    drop(aspace1);
+    drop(aspace2);
+    assert_eq!(&file.0.lock().unwrap()[0x1010..0x1018], b"handle 1");
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+#[test]
+fn test_map_shared() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let pages = SharedPages::new(0x3800).unwrap();
+    assert_eq!(pages.size(), 0x4000);
+    let mut aspace1 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let mut aspace2 = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    aspace1
+        .map_shared(va!(BASE), 0x4000, FLAGS, pages.clone(), 0)
+        .unwrap();
+    aspace2
+        .map_shared(va!(BASE + 0x8000), 0x2000, FLAGS, pages.clone(), 0x2000)
+        .unwrap();
+    assert!(aspace2
+        .map_shared(va!(BASE), 0x2000, FLAGS, pages.clone(), 0x3000)
+        .is_err());
+
+    // Changes through either mapping are visible to the other, and to the
+    // copy-on-write clones.
+    user_write(&mut aspace1, va!(BASE + 0x2ff8), b"shared memory");
+    assert_eq!(
+        user_read(&mut aspace2, va!(BASE + 0x8ff8), 13),
+        b"shared memory"
+    );
+    let mut child = aspace2.clone_cow().unwrap();
+    user_write(&mut child, va!(BASE + 0x9000), b"SHARED");
+    assert_eq!(
+        user_read(&mut aspace1, va!(BASE + 0x2ff8), 14),
+        b"shared mSHARED"
+    );
+
+    // Private mappings of the pages get copies of them.
+    aspace1
+        .map_file(
+            va!(BASE + 0x10000),
+            0x1000,
+            FLAGS,
+            pages.clone(),
+            0x3000,
+            false,
+        )
+        .unwrap();
+    assert_eq!(user_read(&mut aspace1, va!(BASE + 0x10000), 6), b"SHARED");
+    user_write(&mut aspace1, va!(BASE + 0x10000), b"copied");
+    assert_eq!(user_read(&mut aspace2, va!(BASE + 0x9000), 6), b"SHARED");
+
+    // The frames are freed after all the mappings and references have gone.
+    aspace1.unmap(va!(BASE), 0x4000).unwrap();
+    drop(child);
+    drop(pages);
+    assert_eq!(user_read(&mut aspace2, va!(BASE + 0x8ff8), 8), b"shared m");
+    drop(aspace2);
+    drop(aspace1);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+#[test]
+fn test_unmap_shared() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let pages = SharedPages::new(0x4000).unwrap();
+    let mut parent = AddrSpace::new_empty(va!(BASE), SIZE).unwrap();
+    let attached = va!(BASE);
+    let mapped = va!(BASE + 0x8000);
+    parent
+        .map_shared(attached, 0x4000, FLAGS, pages.clone(), 0)
+        .unwrap();
+    parent
+        .map_shared(mapped, 0x2000, FLAGS, pages.clone(), 0x1000)
+        .unwrap();
+    // Split the mapping into two parts.
+    parent.unmap(attached + 0x1000, 0x1000).unwrap();
+    assert_eq!(parent.areas.len(), 3);
+
+    // Only mappings from the beginning of the pages can be removed, as a
+    // whole, and clones can remove their own.
+    let mut child = parent.clone_cow().unwrap();
+    assert!(parent.unmap_shared(attached + 0x2000).is_err());
+    assert!(parent.unmap_shared(mapped).is_err());
+    child.unmap_shared(attached).unwrap();
+    assert_eq!(child.areas.len(), 1);
+    assert!(child.unmap_shared(attached).is_err());
+    assert_eq!(parent.areas.len(), 3);
+    parent.unmap_shared(attached).unwrap();
+    assert_eq!(parent.areas.len(), 1);
+    assert!(parent.page_table().query(attached + 0x2000).is_err());
+}
+
+#[test]
+fn test_huge_pages() {
+    let _lock = SERIAL.lock();
+    init();
+
+    let used_pages = global_allocator().used_pages();
+    let huge = va!(BASE);
+    let mut aspace = AddrSpace::new_empty(huge, 0x100_0000).unwrap();
+    aspace.map_alloc(huge, 0x40_2000, FLAGS, true).unwrap();
+    let page_size = |aspace: &AddrSpace, vaddr| aspace.page_table().query(vaddr).unwrap().2;
+    assert_eq!(page_size(&aspace, huge), PageSize::Size2M);
+    assert_eq!(page_size(&aspace, huge + 0x20_0000), PageSize::Size2M);
+    assert_eq!(page_size(&aspace, huge + 0x40_0000), PageSize::Size4K);
+    user_write(&mut aspace, huge + 0x1ff8, b"huge page");
+    user_write(&mut aspace, huge + 0x20_1000, b"another");
+
+    // Huge pages are split on partial unmap and protect, keeping the data.
+    aspace.unmap(huge + 0x1000, 0x1000).unwrap();
+    assert_eq!(page_size(&aspace, huge), PageSize::Size4K);
+    assert!(aspace.page_table().query(huge + 0x1000).is_err());
+    assert_eq!(user_read(&mut aspace, huge + 0x2000, 1), b"e");
+    aspace
+        .protect(huge + 0x20_0000, 0x1000, MappingFlags::READ)
+        .unwrap();
+    assert_eq!(page_size(&aspace, huge + 0x20_1000), PageSize::Size4K);
+    let (_, flags, _) = aspace.page_table().query(huge + 0x20_1000).unwrap();
+    assert!(flags.contains(MappingFlags::WRITE));
+    assert_eq!(user_read(&mut aspace, huge + 0x20_1000, 7), b"another");
+
+    // Writes to a write-protected huge page split it, and only the written
+    // page gets the write permission back.
+    let ro = huge + 0x60_0000;
+    aspace.map_alloc(ro, 0x20_0000, FLAGS, true).unwrap();
+    aspace.protect(ro, 0x20_0000, MappingFlags::READ).unwrap();
+    assert_eq!(page_size(&aspace, ro), PageSize::Size2M);
+    user_write(&mut aspace, ro + 0x1010, b"written");
+    assert_eq!(page_size(&aspace, ro), PageSize::Size4K);
+    assert_eq!(user_read(&mut aspace, ro + 0x1010, 7), b"written");
+    let (_, flags, _) = aspace.page_table().query(ro).unwrap();
+    assert!(!flags.contains(MappingFlags::WRITE));
+
+    // Frames of split huge pages are freed separately.
+    aspace.unmap(ro, 0x20_0000).unwrap();
+    aspace.unmap(huge, 0x40_2000).unwrap();
+    drop(aspace);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+}
+
+#[cfg(feature = "swap")]
+struct MemSwap(Mutex<Vec<u8>>);
+
+#[cfg(feature = "swap")]
+impl crate::SwapDevice for MemSwap {
+    fn num_pages(&self) -> usize {
+        self.0.lock().unwrap().len() / PAGE_SIZE_4K
+    }
+
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult {
+        let offset = index * PAGE_SIZE_4K;
+        buf.copy_from_slice(&self.0.lock().unwrap()[offset..offset + PAGE_SIZE_4K]);
+        Ok(())
+    }
+
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult {
+        let offset = index * PAGE_SIZE_4K;
+        self.0.lock().unwrap()[offset..offset + PAGE_SIZE_4K].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "swap")]
+fn swap_on() {
+    static SWAP_ON: Once = Once::new();
+    SWAP_ON.call_once(|| {
+        crate::swap_on(Box::new(MemSwap(Mutex::new(vec![0; 0x10000])))).unwrap();
+    });
+}
+
+#[cfg(feature = "swap")]
+fn is_swapped(aspace: &AddrSpace, vaddr: VirtAddr) -> bool {
+    crate::swap::swap_entry(aspace.page_table(), vaddr).is_some()
+}
+
+#[cfg(feature = "swap")]
+#[test]
+fn test_swap() {
+    let _lock = SERIAL.lock();
+    init();
+    swap_on();
+
+    let used_pages = global_allocator().used_pages();
+    let start = va!(BASE);
+    let mut aspace = AddrSpace::new_empty(start, SIZE).unwrap();
+    aspace.map_alloc(start, 0x4000, FLAGS, false).unwrap();
+    for i in 0..4 {
+        user_write(&mut aspace, start + i * PAGE_SIZE_4K, &[i as u8 + 1; 16]);
+    }
+    // The frames of swapped-out pages are freed, and the clock hand moves on.
+    assert_eq!(aspace.swap_out_pages(2), 2);
+    assert_eq!(aspace.swap_out_pages(1), 1);
+    assert!(is_swapped(&aspace, start + 0x2000));
+    assert!(!is_swapped(&aspace, start + 0x3000));
+    assert!(aspace.read(start, &mut [0; 16]).is_err());
+
+    // Swapped-out pages are shared by clones, and swapped in on faults.
+    let mut child = aspace.clone_cow().unwrap();
+    assert!(is_swapped(&child, start + 0x1000));
+    assert_eq!(user_read(&mut aspace, start, 16), [1; 16]);
+    assert_eq!(user_read(&mut child, start, 16), [1; 16]);
+    user_write(&mut child, start + 0x1000, b"child");
+    assert_eq!(user_read(&mut aspace, start + 0x1000, 5), [2; 5]);
+    assert_eq!(user_read(&mut child, start + 0x1000, 5), b"child");
+
+    // Protecting swapped-out pages changes the flags they are swapped in with.
+    let ro = MappingFlags::READ | MappingFlags::USER;
+    aspace.protect(start + 0x2000, PAGE_SIZE_4K, ro).unwrap();
+    assert!(is_swapped(&aspace, start + 0x2000));
+    assert_eq!(user_read(&mut aspace, start + 0x2000, 16), [3; 16]);
+    assert_eq!(aspace.page_table().query(start + 0x2000).unwrap().1, ro);
+    aspace.protect(start + 0x2000, PAGE_SIZE_4K, FLAGS).unwrap();
+
+    // Populated pages protected with `PROT_NONE` are neither swapped out nor
+    // taken as swapped out, and are still shared by clones.
+    let none = start + 0x8000;
+    aspace.map_alloc(none, 0x2000, FLAGS, true).unwrap();
+    user_write(&mut aspace, none, b"none");
+    let frame = paddr_of(&aspace, none);
+    aspace.protect(none, 0x2000, MappingFlags::empty()).unwrap();
+    aspace.swap_out_pages(16);
+    assert!(!is_swapped(&aspace, none));
+    assert!(!aspace.handle_page_fault(none, MappingFlags::READ));
+    assert!(aspace.read(none, &mut [0; 4]).is_err());
+    assert_eq!(paddr_of(&aspace, none), frame);
+    let mut clone = aspace.clone_cow().unwrap();
+    assert_eq!(paddr_of(&clone, none), frame);
+    clone.protect(none, 0x2000, FLAGS).unwrap();
+    user_write(&mut clone, none, b"NONE");
+    aspace.protect(none, 0x2000, FLAGS).unwrap();
+    assert_eq!(user_read(&mut aspace, none, 4), b"none");
+    drop(clone);
+
+    // All frames and slots are freed with the address spaces.
+    drop(child);
+    drop(aspace);
+    assert_eq!(global_allocator().used_pages(), used_pages);
+    assert_eq!(crate::swap::alloc_slot(), Some(0));
+    crate::swap::free_slot(0);
+}
+
+#[cfg(feature = "swap")]
+#[test]
+fn test_swap_reclaim() {
+    let _lock = SERIAL.lock();
+    init();
+    swap_on();
+
+    let start = va!(BASE);
+    let other = Arc::new(Mutex::new(AddrSpace::new_empty(start, SIZE).unwrap()));
+    crate::register_swappable(&other, |aspace, n| {
+        aspace
+            .try_lock()
+            .map_or(0, |mut aspace| aspace.swap_out_pages(n))
+    });
+    {
+        let mut aspace = other.lock().unwrap();
+        aspace.map_alloc(start, 0x4000, FLAGS, false).unwrap();
+        for i in 0..4 {
+            user_write(&mut aspace, start + i * PAGE_SIZE_4K, &[i as u8 + 1; 16]);
+        }
+    }
+
+    // Pages are reclaimed from the registered address spaces that are not
+    // locked.
+    let guard = other.lock().unwrap();
+    assert_eq!(crate::swap::reclaim(2), 0);
+    drop(guard);
+    assert_eq!(crate::swap::reclaim(2), 2);
+
+    // Page faults reclaim pages from the registered address spaces when free
+    // memory runs low, while allocations of the kernel never do.
+    let mut faulting = AddrSpace::new_empty(start, SIZE).unwrap();
+    faulting.map_alloc(start, 0x1000, FLAGS, false).unwrap();
+    let mut pages = Vec::new();
+    while let Ok(page) = global_allocator().alloc_pages(1, PAGE_SIZE_4K) {
+        pages.push(page);
+    }
+    for page in pages.drain(..crate::swap::MIN_FREE_PAGES) {
+        global_allocator().dealloc_pages(page, 1);
+    }
+    assert!(faulting.handle_page_fault(start, MappingFlags::WRITE));
+    for page in pages {
+        global_allocator().dealloc_pages(page, 1);
+    }
+    {
+        let mut aspace = other.lock().unwrap();
+        let swapped = (0..4)
+            .filter(|&i| is_swapped(&aspace, start + i * PAGE_SIZE_4K))
+            .count();
+        assert_eq!(swapped, 3);
+        for i in 0..4 {
+            let vaddr = start + i * PAGE_SIZE_4K;
+            assert_eq!(user_read(&mut aspace, vaddr, 16), [i as u8 + 1; 16]);
+        }
+    }
+    drop(faulting);
+
+    // Dropped address spaces are unregistered.
+    drop(other);
+    assert_eq!(crate::swap::reclaim(1), 0);
+}
@@ -14,6 +14,9 @@
 //!
 //! - `smoltcp`: Use [smoltcp] as the underlying network stack. This is enabled
 //!   by default.
+//! - `async`: Enable asynchronous socket operations, such as
+//!   [`TcpSocket::poll_recv`]. Their wakers are woken up by polling the network
+//!   stack, instead of busy-polling.
 //!
 //! [smoltcp]: https://github.com/smoltcp-rs/smoltcp
 
@@ -4,6 +4,8 @@ mod dns;
 mod listen_table;
 mod tcp;
 mod udp;
+#[cfg(feature = "async")]
+mod waker;
 
 use alloc::vec;
 use core::cell::RefCell;
@@ -120,7 +122,12 @@ impl<'a> SocketSetWrapper<'a> {
     }
 
     pub fn poll_interfaces(&self) {
-        ETH0.poll(&self.0);
+        let _changed = ETH0.poll(&self.0);
+        // Pending operations can only make progress if some sockets changed.
+        #[cfg(feature = "async")]
+        if _changed {
+            waker::wake_all();
+        }
     }
 
     pub fn remove(&self, handle: SocketHandle) {
@@ -170,12 +177,14 @@ impl InterfaceWrapper {
         };
     }
 
-    pub fn poll(&self, sockets: &Mutex<SocketSet>) {
+    /// Polls the interface, and returns whether the readiness of some
+    /// sockets may have changed.
+    pub fn poll(&self, sockets: &Mutex<SocketSet>) -> bool {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
         let mut sockets = sockets.lock();
         let timestamp = Self::current_time();
-        iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        iface.poll(timestamp, dev.deref_mut(), &mut sockets)
     }
 }
 
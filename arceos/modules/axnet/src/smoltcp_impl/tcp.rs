@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
@@ -229,11 +231,23 @@ impl TcpSocket {
 
         // SAFETY: `self.local_addr` should be initialized after `bind()`.
         let local_port = unsafe { self.local_addr.get().read().port };
-        self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
-            debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
-        })
+        self.block_on(|| Self::accept_once(local_port))
+    }
+
+    /// Polls for a new connection, the asynchronous version of
+    /// [`accept`](Self::accept).
+    ///
+    /// If there is no new connection, the waker of `cx` is woken up when
+    /// polling the network stack changes the readiness of sockets.
+    #[cfg(feature = "async")]
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<AxResult<TcpSocket>> {
+        if !self.is_listening() {
+            return Poll::Ready(ax_err!(InvalidInput, "socket accept() failed: not listen"));
+        }
+
+        // SAFETY: `self.local_addr` should be initialized after `bind()`.
+        let local_port = unsafe { self.local_addr.get().read().port };
+        super::waker::poll_on(cx, || Self::accept_once(local_port))
     }
 
     /// Close the connection.
@@ -279,26 +293,18 @@ impl TcpSocket {
 
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(|| Self::recv_once(handle, buf))
+    }
+
+    /// Polls to receive data, the asynchronous version of [`recv`](Self::recv).
+    ///
+    /// If there is no data, the waker of `cx` is woken up when polling the
+    /// network stack changes the readiness of sockets.
+    #[cfg(feature = "async")]
+    pub fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<AxResult<usize>> {
+        super::waker::poll_on(cx, || {
+            let handle = self.connected_handle("socket recv() failed")?;
+            Self::recv_once(handle, buf)
         })
     }
 
@@ -312,23 +318,18 @@ impl TcpSocket {
 
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
-                    // closed by remote
-                    ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(|| Self::send_once(handle, buf))
+    }
+
+    /// Polls to transmit data, the asynchronous version of [`send`](Self::send).
+    ///
+    /// If the transmit buffer is full, the waker of `cx` is woken up when
+    /// polling the network stack changes the readiness of sockets.
+    #[cfg(feature = "async")]
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<AxResult<usize>> {
+        super::waker::poll_on(cx, || {
+            let handle = self.connected_handle("socket send() failed")?;
+            Self::send_once(handle, buf)
         })
     }
 
@@ -469,6 +470,70 @@ impl TcpSocket {
         })
     }
 
+    fn accept_once(local_port: u16) -> AxResult<TcpSocket> {
+        let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+        debug!("TCP socket accepted a new connection {}", peer_addr);
+        Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+    }
+
+    fn recv_once(handle: SocketHandle, buf: &mut [u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() {
+                // not open
+                ax_err!(ConnectionRefused, "socket recv() failed")
+            } else if !socket.may_recv() {
+                // connection closed
+                Ok(0)
+            } else if socket.recv_queue() > 0 {
+                // data available
+                // TODO: use socket.recv(|buf| {...})
+                let len = socket
+                    .recv_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                Ok(len)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    fn send_once(handle: SocketHandle, buf: &[u8]) -> AxResult<usize> {
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            if !socket.is_active() || !socket.may_send() {
+                // closed by remote
+                ax_err!(ConnectionReset, "socket send() failed")
+            } else if socket.can_send() {
+                // connected, and the tx buffer is not full
+                // TODO: use socket.send(|buf| {...})
+                let len = socket
+                    .send_slice(buf)
+                    .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                Ok(len)
+            } else {
+                // tx buffer is full
+                Err(AxError::WouldBlock)
+            }
+        })
+    }
+
+    /// Returns the handle of a connected socket, or
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) if it is still connecting.
+    #[cfg(feature = "async")]
+    fn connected_handle(&self, err_msg: &str) -> AxResult<SocketHandle> {
+        if self.is_connecting() {
+            self.poll_connect()?;
+        }
+        if self.is_connecting() {
+            Err(AxError::WouldBlock)
+        } else if !self.is_connected() {
+            ax_err!(NotConnected, err_msg)
+        } else {
+            // SAFETY: `self.handle` should be initialized in a connected socket.
+            Ok(unsafe { self.handle.get().read().unwrap() })
+        }
+    }
+
     /// Block the current thread until the given function completes or fails.
     ///
     /// If the socket is non-blocking, it calls the function once and returns
@@ -1,10 +1,11 @@
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
 
 use axerrno::{ax_err, ax_err_type, AxError, AxResult};
 use axio::PollState;
-use axsync::Mutex;
-use spin::RwLock;
+use axsync::{Mutex, RwLock};
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::udp::{self, BindError, SendError};
@@ -118,6 +119,28 @@ impl UdpSocket {
         })
     }
 
+    /// Polls to receive a single datagram message, the asynchronous version
+    /// of [`recv_from`](Self::recv_from).
+    ///
+    /// If there is no message, the waker of `cx` is woken up when polling the
+    /// network stack changes the readiness of sockets.
+    #[cfg(feature = "async")]
+    pub fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<AxResult<(usize, SocketAddr)>> {
+        if self.local_addr.read().is_none() {
+            return Poll::Ready(ax_err!(NotConnected, "socket send() failed"));
+        }
+        super::waker::poll_on(cx, || {
+            self.recv_once(|socket| match socket.recv_slice(buf) {
+                Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
+                Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+            })
+        })
+    }
+
     /// Receives a single datagram message on the socket, without removing it from
     /// the queue. On success, returns the number of bytes read and the origin.
     pub fn peek_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
@@ -239,16 +262,21 @@ impl UdpSocket {
             return ax_err!(NotConnected, "socket send() failed");
         }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_recv() {
-                    // data available
-                    op(socket)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(|| self.recv_once(&mut op))
+    }
+
+    fn recv_once<F, T>(&self, op: F) -> AxResult<T>
+    where
+        F: FnOnce(&mut udp::Socket) -> AxResult<T>,
+    {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            if socket.can_recv() {
+                // data available
+                op(socket)
+            } else {
+                // no more data
+                Err(AxError::WouldBlock)
+            }
         })
     }
 
@@ -0,0 +1,84 @@
+//! Wakers of asynchronous socket operations.
+//!
+//! A pending operation registers its waker, which is woken up the next time
+//! polling the network stack changes the readiness of sockets, i.e., when
+//! the operation may make progress. While there are registered wakers, a
+//! poller task polls the network stack periodically, so that pending futures
+//! do not have to busy-poll.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult};
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use super::SOCKET_SET;
+
+/// The interval to poll the network stack while there are pending operations.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+static WAKERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+/// Whether `WAKERS` is not empty, checked by the poller with the run queue
+/// locked.
+static HAS_WAKERS: AtomicBool = AtomicBool::new(false);
+static POLLER_WQ: WaitQueue = WaitQueue::new();
+static POLLER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Wakes up all pending operations, called after polling the network stack
+/// changes the readiness of sockets.
+pub(crate) fn wake_all() {
+    HAS_WAKERS.store(false, Ordering::Release);
+    let wakers = core::mem::take(&mut *WAKERS.lock());
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+fn register(waker: &Waker) {
+    let mut wakers = WAKERS.lock();
+    if !wakers.iter().any(|w| w.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+    drop(wakers);
+    HAS_WAKERS.store(true, Ordering::Release);
+
+    if !POLLER_STARTED.swap(true, Ordering::AcqRel) {
+        axtask::TaskBuilder::new()
+            .name("net-poller".into())
+            .spawn(run_poller);
+    }
+    POLLER_WQ.notify_one(false);
+}
+
+fn run_poller() {
+    loop {
+        POLLER_WQ.wait_until(|| HAS_WAKERS.load(Ordering::Acquire));
+        SOCKET_SET.poll_interfaces();
+        axtask::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Polls the network stack and calls `f`.
+///
+/// If `f` returns [`Err(WouldBlock)`](AxError::WouldBlock), the waker of
+/// `cx` is registered and `f` is called again, as the sockets may have
+/// become ready before the registration, e.g., by the poller task. If it
+/// still would block, [`Poll::Pending`] is returned.
+pub(crate) fn poll_on<F, T>(cx: &mut Context<'_>, mut f: F) -> Poll<AxResult<T>>
+where
+    F: FnMut() -> AxResult<T>,
+{
+    SOCKET_SET.poll_interfaces();
+    match f() {
+        Err(AxError::WouldBlock) => {}
+        res => return Poll::Ready(res),
+    }
+    register(cx.waker());
+    match f() {
+        Err(AxError::WouldBlock) => Poll::Pending,
+        res => Poll::Ready(res),
+    }
+}
@@ -13,6 +13,8 @@
 //! - `fs`: Enable filesystem support.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
+//! - `swap`: Enable swapping of anonymous pages to a block device, or to a
+//!   swap file if `fs` is enabled.
 //!
 //! All the features are optional and disabled by default.
 
@@ -22,12 +24,18 @@
 #[macro_use]
 extern crate axlog;
 
+#[cfg(feature = "swap")]
+extern crate alloc;
+
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "swap")]
+mod swap;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -153,7 +161,7 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(feature = "fs", feature = "net", feature = "display", feature = "swap"))]
     {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
@@ -161,6 +169,11 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         #[cfg(feature = "fs")]
         axfs::init_filesystems(all_devices.block);
 
+        #[cfg(all(feature = "swap", feature = "fs"))]
+        self::swap::init_swap_file();
+        #[cfg(all(feature = "swap", not(feature = "fs")))]
+        self::swap::init_swap_device(all_devices.block);
+
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
 
@@ -264,12 +277,15 @@ fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
 
     // Setup timer interrupt handler
+    #[cfg(not(feature = "multitask"))]
     const PERIODIC_INTERVAL_NANOS: u64 =
         axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
 
+    #[cfg(not(feature = "multitask"))]
     #[percpu::def_percpu]
     static NEXT_DEADLINE: u64 = 0;
 
+    #[cfg(not(feature = "multitask"))]
     fn update_timer() {
         let now_ns = axhal::time::monotonic_time_nanos();
         // Safety: we have disabled preemption in IRQ handler.
@@ -282,11 +298,18 @@ fn init_interrupt() {
     }
 
     axhal::irq::register_handler(TIMER_IRQ_NUM, || {
-        update_timer();
+        // The scheduler reprograms the timer by itself, to stop the periodic
+        // tick on idle CPUs.
         #[cfg(feature = "multitask")]
         axtask::on_timer_tick();
+        #[cfg(not(feature = "multitask"))]
+        update_timer();
     });
 
+    // Setup the reschedule IPI handler
+    #[cfg(all(feature = "smp", feature = "multitask"))]
+    axhal::irq::register_handler(axhal::irq::IPI_IRQ_NUM, axtask::on_reschedule_ipi);
+
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
 }
@@ -0,0 +1,127 @@
+//! Swap areas for swapping out anonymous pages, see [`axmm::swap_on`].
+//!
+//! The swap area is on the first block device, e.g., the RAM disk, or on a
+//! swap file if the filesystem is enabled, which takes the block devices.
+
+use alloc::boxed::Box;
+
+use axerrno::AxResult;
+use axhal::mem::PAGE_SIZE_4K;
+use axmm::SwapDevice;
+
+#[cfg(not(feature = "fs"))]
+use {
+    axdriver::{prelude::*, AxDeviceContainer},
+    axerrno::AxError,
+    kspin::SpinNoIrq,
+};
+
+#[cfg(feature = "fs")]
+use {
+    axerrno::ax_err,
+    axfs::fops::{File, OpenOptions},
+};
+
+/// A swap area on a block device, where each page is stored in consecutive
+/// blocks.
+#[cfg(not(feature = "fs"))]
+struct BlockSwap(SpinNoIrq<AxBlockDevice>);
+
+#[cfg(not(feature = "fs"))]
+impl SwapDevice for BlockSwap {
+    fn num_pages(&self) -> usize {
+        let dev = self.0.lock();
+        dev.num_blocks() as usize * dev.block_size() / PAGE_SIZE_4K
+    }
+
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult {
+        let mut dev = self.0.lock();
+        let block_size = dev.block_size();
+        let block_id = (index * PAGE_SIZE_4K / block_size) as u64;
+        for (i, block) in buf.chunks_exact_mut(block_size).enumerate() {
+            dev.read_block(block_id + i as u64, block)
+                .map_err(|_| AxError::Io)?;
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult {
+        let mut dev = self.0.lock();
+        let block_size = dev.block_size();
+        let block_id = (index * PAGE_SIZE_4K / block_size) as u64;
+        for (i, block) in buf.chunks_exact(block_size).enumerate() {
+            dev.write_block(block_id + i as u64, block)
+                .map_err(|_| AxError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Enables swapping on the first block device.
+#[cfg(not(feature = "fs"))]
+pub(crate) fn init_swap_device(mut block_devs: AxDeviceContainer<AxBlockDevice>) {
+    info!("Initialize swap area...");
+    let Some(dev) = block_devs.take_one() else {
+        warn!("  no block device for swap");
+        return;
+    };
+    info!("  use block device {:?}.", dev.device_name());
+    if let Err(e) = axmm::swap_on(Box::new(BlockSwap(SpinNoIrq::new(dev)))) {
+        warn!("  failed to enable swap: {:?}", e);
+    }
+}
+
+/// The path of the swap file.
+#[cfg(feature = "fs")]
+const SWAP_FILE: &str = "/swapfile";
+
+/// The size of the swap file, which is created on boot.
+#[cfg(feature = "fs")]
+const SWAP_FILE_SIZE: usize = 0x100_0000; // 16M
+
+/// A swap area on a file.
+#[cfg(feature = "fs")]
+struct FileSwap(File);
+
+#[cfg(feature = "fs")]
+impl SwapDevice for FileSwap {
+    fn num_pages(&self) -> usize {
+        SWAP_FILE_SIZE / PAGE_SIZE_4K
+    }
+
+    fn read_page(&self, index: usize, buf: &mut [u8]) -> AxResult {
+        if self.0.read_at((index * PAGE_SIZE_4K) as u64, buf)? != buf.len() {
+            return ax_err!(Io, "swap file truncated");
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, index: usize, buf: &[u8]) -> AxResult {
+        if self.0.write_at((index * PAGE_SIZE_4K) as u64, buf)? != buf.len() {
+            return ax_err!(Io, "swap file truncated");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+fn open_swap_file() -> AxResult<File> {
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    opts.create(true);
+    let file = File::open(SWAP_FILE, &opts)?;
+    file.truncate(SWAP_FILE_SIZE as u64)?;
+    Ok(file)
+}
+
+/// Enables swapping on the swap file, which is created if it does not exist.
+#[cfg(feature = "fs")]
+pub(crate) fn init_swap_file() {
+    info!("Initialize swap area...");
+    info!("  use swap file {:?}.", SWAP_FILE);
+    let res = open_swap_file().and_then(|file| axmm::swap_on(Box::new(FileSwap(file))));
+    if let Err(e) = res {
+        warn!("  failed to enable swap: {:?}", e);
+    }
+}
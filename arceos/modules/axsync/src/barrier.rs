@@ -0,0 +1,127 @@
+//! A barrier.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "multitask")]
+use axtask::WaitQueue;
+
+#[cfg(not(feature = "multitask"))]
+use crate::wait::WaitQueue;
+
+/// A barrier enables multiple tasks to synchronize the beginning of some
+/// computation, similar to
+/// [`std::sync::Barrier`](https://doc.rust-lang.org/std/sync/struct.Barrier.html).
+///
+/// The barrier is reusable: after all tasks have rendezvoused, it starts
+/// over for the next round.
+pub struct Barrier {
+    num_tasks: usize,
+    count: AtomicUsize,
+    /// Incremented each time all tasks have rendezvoused.
+    generation: AtomicUsize,
+    wq: WaitQueue,
+}
+
+/// A `BarrierWaitResult` is returned by [`Barrier::wait`] when all tasks
+/// in the [`Barrier`] have rendezvoused.
+pub struct BarrierWaitResult(bool);
+
+impl Barrier {
+    /// Creates a new barrier that blocks `n` tasks.
+    ///
+    /// A barrier of 0 or 1 task does not block.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            num_tasks: n,
+            count: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Blocks the current task until all `n` tasks have called this method.
+    ///
+    /// A single (arbitrary) task receives a [`BarrierWaitResult`] that returns
+    /// `true` from [`BarrierWaitResult::is_leader`], and the others receive
+    /// one that returns `false`.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let generation = self.generation.load(Ordering::Acquire);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 >= self.num_tasks {
+            self.count.store(0, Ordering::Release);
+            self.generation.fetch_add(1, Ordering::Release);
+            self.wq.notify_all(true);
+            BarrierWaitResult(true)
+        } else {
+            self.wq
+                .wait_until(|| self.generation.load(Ordering::Acquire) != generation);
+            BarrierWaitResult(false)
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Barrier")
+            .field("num_tasks", &self.num_tasks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BarrierWaitResult {
+    /// Returns `true` if this task is the "leader task" for the call to
+    /// [`Barrier::wait`].
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl fmt::Debug for BarrierWaitResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BarrierWaitResult")
+            .field("is_leader", &self.is_leader())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Barrier;
+    use axtask as thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn barrier_rounds() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_ROUNDS: usize = 10;
+        static BARRIER: Barrier = Barrier::new(NUM_TASKS);
+        static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+        static LEADERS: AtomicUsize = AtomicUsize::new(0);
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|_| {
+                thread::spawn(|| {
+                    for round in 1..=NUM_ROUNDS {
+                        ARRIVED.fetch_add(1, Ordering::SeqCst);
+                        if BARRIER.wait().is_leader() {
+                            LEADERS.fetch_add(1, Ordering::SeqCst);
+                        }
+                        // Nobody passes the barrier before all tasks arrive.
+                        assert!(ARRIVED.load(Ordering::SeqCst) >= round * NUM_TASKS);
+                        BARRIER.wait();
+                    }
+                })
+            })
+            .collect();
+        for t in tasks {
+            t.join();
+        }
+
+        assert_eq!(ARRIVED.load(Ordering::SeqCst), NUM_ROUNDS * NUM_TASKS);
+        assert_eq!(LEADERS.load(Ordering::SeqCst), NUM_ROUNDS);
+        assert!(Barrier::new(1).wait().is_leader());
+        println!("Barrier test OK");
+    }
+}
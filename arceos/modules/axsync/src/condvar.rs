@@ -0,0 +1,208 @@
+//! A condition variable.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use crate::MutexGuard;
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+#[cfg(feature = "irq")]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+#[cfg(feature = "irq")]
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable, similar to
+/// [`std::sync::Condvar`](https://doc.rust-lang.org/std/sync/struct.Condvar.html).
+///
+/// Waiting tasks are blocked in a wait queue. Each notification bumps a
+/// sequence number, so that a notification between releasing the mutex and
+/// blocking is not lost.
+pub struct Condvar {
+    wq: WaitQueue,
+    seq: AtomicU64,
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Blocks the current task until this condition variable receives a
+    /// notification.
+    ///
+    /// The mutex of `guard` is released while waiting, and re-acquired before
+    /// returning. Spurious wakeups are possible.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        let seq = self.seq.load(Ordering::Acquire);
+        drop(guard);
+        self.wq
+            .wait_until(|| self.seq.load(Ordering::Acquire) != seq);
+        mutex.lock()
+    }
+
+    /// Blocks the current task until `condition` returns `false`.
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Waits on this condition variable for a notification, timing out after
+    /// the specified duration.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult) {
+        let mutex = guard.mutex();
+        let seq = self.seq.load(Ordering::Acquire);
+        drop(guard);
+        let timed_out = self
+            .wq
+            .wait_timeout_until(dur, || self.seq.load(Ordering::Acquire) != seq);
+        (mutex.lock(), WaitTimeoutResult(timed_out))
+    }
+
+    /// Waits until `condition` returns `false`, timing out after the
+    /// specified duration.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = axhal::time::monotonic_time() + dur;
+        while condition(&mut *guard) {
+            let now = axhal::time::monotonic_time();
+            if now >= deadline {
+                return (guard, WaitTimeoutResult(true));
+            }
+            guard = self.wait_timeout(guard, deadline - now).0;
+        }
+        (guard, WaitTimeoutResult(false))
+    }
+
+    /// Wakes up one blocked task on this condition variable.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Wakes up all blocked tasks on this condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(true);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Condvar, Mutex};
+    use axtask as thread;
+
+    #[test]
+    fn condvar_notify() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: u32 = 10;
+        static COUNT: Mutex<u32> = Mutex::new(0);
+        static CVAR: Condvar = Condvar::new();
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|_| {
+                thread::spawn(|| {
+                    let mut count = COUNT.lock();
+                    *count += 1;
+                    CVAR.notify_all();
+                    // Wait for the main task to release everyone.
+                    let count = CVAR.wait_while(count, |count| *count != 0);
+                    assert_eq!(*count, 0);
+                })
+            })
+            .collect();
+
+        let mut count = CVAR.wait_while(COUNT.lock(), |count| *count < NUM_TASKS);
+        assert_eq!(*count, NUM_TASKS);
+        *count = 0;
+        CVAR.notify_all();
+        drop(count);
+        for t in tasks {
+            t.join();
+        }
+        println!("Condvar test OK");
+    }
+
+    #[cfg(feature = "irq")]
+    #[test]
+    fn condvar_wait_timeout() {
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use core::time::Duration;
+
+        crate::init_test_scheduler();
+
+        static READY: Mutex<bool> = Mutex::new(false);
+        static CVAR: Condvar = Condvar::new();
+        static WAITING: AtomicBool = AtomicBool::new(false);
+
+        let (guard, res) = CVAR.wait_timeout(READY.lock(), Duration::ZERO);
+        assert!(res.timed_out());
+        assert!(!*guard);
+        drop(guard);
+
+        let (guard, res) = CVAR.wait_timeout_while(READY.lock(), Duration::ZERO, |ready| !*ready);
+        assert!(res.timed_out());
+        drop(guard);
+
+        let notifier = thread::spawn(|| {
+            while !WAITING.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            *READY.lock() = true;
+            CVAR.notify_one();
+        });
+        let guard = READY.lock();
+        WAITING.store(true, Ordering::Release);
+        let (guard, res) =
+            CVAR.wait_timeout_while(guard, Duration::from_secs(3600), |ready| !*ready);
+        assert!(!res.timed_out());
+        assert!(*guard);
+        drop(guard);
+        notifier.join();
+        println!("Condvar timeout test OK");
+    }
+}
@@ -2,22 +2,68 @@
 //!
 //! Currently supported primitives:
 //!
-//! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`Mutex`]: A mutual exclusion primitive, with optional priority
+//!   inheritance.
+//! - [`RwLock`]: A writer-preferring reader-writer lock, with upgradable
+//!   reads.
+//! - [`Condvar`]: A condition variable, used along with [`Mutex`].
+//! - [`Semaphore`]: A counting semaphore.
+//! - [`Barrier`]: A reusable barrier for a fixed number of tasks.
+//! - mod [`mpsc`]: Multi-producer, single-consumer channels.
 //! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
-//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
-//!   feature is enabled by default.
+//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`], the
+//!   other primitives spin instead of blocking, and [`Condvar`] is not
+//!   available. This feature is enabled by default.
+//! - `irq`: Enable timed waits, i.e., [`Condvar::wait_timeout`] and
+//!   [`Semaphore::acquire_timeout`].
+//! - `lockdep`: Track [`Mutex`]es, [`RwLock`]s and the spinlocks in [`spin`]
+//!   with the lock dependency validator of `axtask`, which reports potential
+//!   deadlocks caused by inconsistent lock orders.
+//! - `lock-stat`: Collect contention [statistics](stat) of [`Mutex`]es,
+//!   [`RwLock`]s and the spinlocks in [`spin`], per place where the locks are
+//!   created.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
+#[cfg(any(feature = "multitask", feature = "lock-stat"))]
+extern crate alloc;
+
+#[cfg(not(any(feature = "lock-stat", feature = "lockdep")))]
 pub use kspin as spin;
+#[cfg(any(feature = "lock-stat", feature = "lockdep"))]
+pub mod spin;
+#[cfg(feature = "lock-stat")]
+pub mod stat;
+
+mod barrier;
+mod rwlock;
+mod semaphore;
 
+#[cfg(feature = "multitask")]
+mod condvar;
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub mod mpsc;
 #[cfg(feature = "multitask")]
 mod mutex;
+#[cfg(not(feature = "multitask"))]
+mod wait;
+
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard};
+pub use self::semaphore::Semaphore;
+
+#[cfg(feature = "multitask")]
+#[doc(cfg(feature = "multitask"))]
+pub use self::condvar::Condvar;
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub use self::condvar::WaitTimeoutResult;
 
 #[cfg(feature = "multitask")]
 #[doc(cfg(feature = "multitask"))]
@@ -25,4 +71,11 @@ pub use self::mutex::{Mutex, MutexGuard};
 
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
-pub use kspin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+pub use self::spin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+
+/// Initializes the scheduler of `axtask` once for all tests.
+#[cfg(test)]
+fn init_test_scheduler() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(axtask::init_scheduler);
+}
@@ -0,0 +1,664 @@
+//! Multi-producer, single-consumer FIFO queue communication primitives,
+//! similar to [`std::sync::mpsc`](https://doc.rust-lang.org/std/sync/mpsc/index.html).
+//!
+//! - [`channel`] creates an asynchronous, infinitely buffered channel, whose
+//!   [`Sender::send`] never blocks.
+//! - [`sync_channel`] creates a synchronous, bounded channel, whose
+//!   [`SyncSender::send`] blocks when the buffer is full. A bound of 0 makes
+//!   a "rendezvous" channel, where each send blocks until the message is
+//!   received.
+//!
+//! A channel is disconnected when all senders or the receiver are dropped.
+//! Blocked tasks are put into wait queues, and woken up on each message or
+//! disconnection.
+//!
+//! Unlike `std`, the [`Receiver`] is [`Sync`], so it can also be shared by
+//! multiple consumers (e.g., in an [`Arc`]), each message being received by
+//! one of them.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axtask::WaitQueue;
+
+use crate::spin::SpinNoIrq;
+
+/// An error returned from [`Sender::send`] or [`SyncSender::send`], when the
+/// receiver has been dropped. It contains the message that was not sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+/// An error returned from [`Receiver::recv`], when all senders have been
+/// dropped and the channel is empty.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecvError;
+
+/// An error returned from [`Receiver::try_recv`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// The channel is currently empty, but may receive messages later.
+    Empty,
+    /// All senders have been dropped and the channel is empty.
+    Disconnected,
+}
+
+/// An error returned from [`Receiver::recv_timeout`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecvTimeoutError {
+    /// No message arrived before the timeout.
+    Timeout,
+    /// All senders have been dropped and the channel is empty.
+    Disconnected,
+}
+
+/// An error returned from [`SyncSender::try_send`]. It contains the message
+/// that was not sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The buffer is full, or no receiver is waiting on a rendezvous channel.
+    Full(T),
+    /// The receiver has been dropped.
+    Disconnected(T),
+}
+
+struct Channel<T> {
+    queue: SpinNoIrq<VecDeque<T>>,
+    /// The number of messages in `queue`, which can be read in wait
+    /// conditions without locking.
+    len: AtomicUsize,
+    /// The capacity of the buffer, `None` for unbounded channels, and 0 for
+    /// rendezvous channels.
+    cap: Option<usize>,
+    /// The number of messages sent and received, used by rendezvous senders
+    /// to wait for their messages to be received.
+    sent: AtomicU64,
+    received: AtomicU64,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    receiver_waiting: AtomicBool,
+    /// Tasks waiting to receive.
+    recv_wq: WaitQueue,
+    /// Tasks waiting to send.
+    send_wq: WaitQueue,
+}
+
+impl<T> Channel<T> {
+    fn new(cap: Option<usize>) -> Self {
+        Self {
+            queue: SpinNoIrq::new(VecDeque::new()),
+            len: AtomicUsize::new(0),
+            cap,
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            senders: AtomicUsize::new(1),
+            receiver_alive: AtomicBool::new(true),
+            receiver_waiting: AtomicBool::new(false),
+            recv_wq: WaitQueue::new(),
+            send_wq: WaitQueue::new(),
+        }
+    }
+
+    fn is_disconnected(&self) -> bool {
+        !self.receiver_alive.load(Ordering::Acquire)
+    }
+
+    fn has_room(&self) -> bool {
+        match self.cap {
+            None => true,
+            // A rendezvous channel holds at most the message being handed
+            // over.
+            Some(cap) => self.len.load(Ordering::Acquire) < cap.max(1),
+        }
+    }
+
+    /// Pushes a message if there is room, and returns its sequence number.
+    fn try_push(&self, msg: T) -> Result<u64, TrySendError<T>> {
+        let mut queue = self.queue.lock();
+        if self.is_disconnected() {
+            return Err(TrySendError::Disconnected(msg));
+        }
+        if !self.has_room() {
+            return Err(TrySendError::Full(msg));
+        }
+        queue.push_back(msg);
+        self.len.store(queue.len(), Ordering::Release);
+        let seq = self.sent.fetch_add(1, Ordering::AcqRel) + 1;
+        drop(queue);
+        self.recv_wq.notify_one(true);
+        Ok(seq)
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let mut queue = self.queue.lock();
+        let msg = queue.pop_front()?;
+        self.len.store(queue.len(), Ordering::Release);
+        self.received.fetch_add(1, Ordering::AcqRel);
+        drop(queue);
+        self.send_wq.notify_all(true);
+        Some(msg)
+    }
+
+    fn send(&self, mut msg: T) -> Result<(), SendError<T>> {
+        let seq = loop {
+            match self.try_push(msg) {
+                Ok(seq) => break seq,
+                Err(TrySendError::Disconnected(m)) => return Err(SendError(m)),
+                Err(TrySendError::Full(m)) => msg = m,
+            }
+            self.send_wq
+                .wait_until(|| self.has_room() || self.is_disconnected());
+        };
+        if self.cap == Some(0) {
+            // Wait for the message to be handed over.
+            self.send_wq.wait_until(|| {
+                self.received.load(Ordering::Acquire) >= seq || self.is_disconnected()
+            });
+            if self.received.load(Ordering::Acquire) < seq {
+                // Only our message can be in the buffer of a rendezvous
+                // channel.
+                let mut queue = self.queue.lock();
+                if let Some(msg) = queue.pop_front() {
+                    self.len.store(queue.len(), Ordering::Release);
+                    return Err(SendError(msg));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        if self.cap == Some(0) && !self.receiver_waiting.load(Ordering::Acquire) {
+            return Err(if self.is_disconnected() {
+                TrySendError::Disconnected(msg)
+            } else {
+                TrySendError::Full(msg)
+            });
+        }
+        self.try_push(msg).map(|_| ())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        // Check the senders before popping, so that a message sent by the
+        // last sender before it is dropped is not missed.
+        let disconnected = self.senders.load(Ordering::Acquire) == 0;
+        match self.try_pop() {
+            Some(msg) => Ok(msg),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Receives a message, blocking until one is available, all senders are
+    /// dropped, or the optional timeout has elapsed.
+    ///
+    /// The timeout is ignored if the `irq` feature is not enabled.
+    fn recv(&self, timeout: Option<Duration>) -> Result<T, RecvTimeoutError> {
+        #[cfg(feature = "irq")]
+        let deadline = timeout.map(|dur| axhal::time::wall_time() + dur);
+        #[cfg(not(feature = "irq"))]
+        let _ = timeout;
+        loop {
+            match self.try_recv() {
+                Ok(msg) => return Ok(msg),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {}
+            }
+            #[cfg(feature = "irq")]
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = axhal::time::wall_time();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            let ready = || {
+                self.len.load(Ordering::Acquire) > 0 || self.senders.load(Ordering::Acquire) == 0
+            };
+            self.receiver_waiting.store(true, Ordering::Release);
+            #[cfg(feature = "irq")]
+            match timeout {
+                Some(dur) => {
+                    self.recv_wq.wait_timeout_until(dur, ready);
+                }
+                None => self.recv_wq.wait_until(ready),
+            }
+            #[cfg(not(feature = "irq"))]
+            self.recv_wq.wait_until(ready);
+            self.receiver_waiting.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// The sending half of a channel created by [`channel`].
+///
+/// Messages can be sent with [`Sender::send`], which never blocks. It can be
+/// cloned to send to the same channel multiple times.
+pub struct Sender<T> {
+    inner: Arc<Channel<T>>,
+}
+
+/// The sending half of a channel created by [`sync_channel`].
+///
+/// Messages can be sent with [`SyncSender::send`], which blocks if the buffer
+/// is full. It can be cloned to send to the same channel multiple times.
+pub struct SyncSender<T> {
+    inner: Arc<Channel<T>>,
+}
+
+/// The receiving half of a channel created by [`channel`] or
+/// [`sync_channel`].
+pub struct Receiver<T> {
+    inner: Arc<Channel<T>>,
+}
+
+/// Creates a new asynchronous channel, returning the sender/receiver halves.
+///
+/// The buffer is unbounded, so [`Sender::send`] never blocks.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Channel::new(None));
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// Creates a new synchronous, bounded channel, returning the sender/receiver
+/// halves.
+///
+/// [`SyncSender::send`] blocks when there are `bound` messages in the buffer.
+/// If `bound` is 0, each send blocks until the message is received.
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    let inner = Arc::new(Channel::new(Some(bound)));
+    (
+        SyncSender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Sends a message to the receiver, without blocking.
+    ///
+    /// Returns the message back if the receiver has been dropped.
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.inner.send(msg)
+    }
+}
+
+impl<T> SyncSender<T> {
+    /// Sends a message to the receiver, blocking until there is room in the
+    /// buffer, or until the message is received for a rendezvous channel.
+    ///
+    /// Returns the message back if the receiver has been dropped.
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.inner.send(msg)
+    }
+
+    /// Tries to send a message without blocking.
+    ///
+    /// For a rendezvous channel, it succeeds only if the receiver is waiting.
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.inner.try_send(msg)
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message, blocking until one is available.
+    ///
+    /// Returns [`RecvError`] if all senders have been dropped and the channel
+    /// is empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv(None).map_err(|_| RecvError)
+    }
+
+    /// Tries to receive a message without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    /// Receives a message, blocking until one is available or the timeout
+    /// has elapsed.
+    ///
+    /// The timeout is ignored if the `irq` feature is not enabled.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.inner.recv(Some(timeout))
+    }
+
+    /// Returns an iterator that blocks waiting for messages, until all
+    /// senders have been dropped.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// Returns an iterator over the messages currently in the channel,
+    /// without blocking.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.recv_wq.notify_all(true);
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.recv_wq.notify_all(true);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Take the queue lock, so that no message is pushed after the
+        // receiver is gone.
+        let queue = self.inner.queue.lock();
+        self.inner.receiver_alive.store(false, Ordering::Release);
+        drop(queue);
+        self.inner.send_wq.notify_all(true);
+    }
+}
+
+/// A blocking iterator over the messages of a [`Receiver`], created by
+/// [`Receiver::iter`].
+pub struct Iter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// A non-blocking iterator over the messages of a [`Receiver`], created by
+/// [`Receiver::try_iter`].
+pub struct TryIter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// An owning blocking iterator over the messages of a [`Receiver`].
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "sending on a closed channel".fmt(f)
+    }
+}
+
+impl<T> core::error::Error for SendError<T> {}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TrySendError::Full(..) => "Full(..)".fmt(f),
+            TrySendError::Disconnected(..) => "Disconnected(..)".fmt(f),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TrySendError::Full(..) => "sending on a full channel".fmt(f),
+            TrySendError::Disconnected(..) => "sending on a closed channel".fmt(f),
+        }
+    }
+}
+
+impl<T> core::error::Error for TrySendError<T> {}
+
+impl<T> From<SendError<T>> for TrySendError<T> {
+    fn from(err: SendError<T>) -> TrySendError<T> {
+        TrySendError::Disconnected(err.0)
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "receiving on a closed channel".fmt(f)
+    }
+}
+
+impl core::error::Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TryRecvError::Empty => "receiving on an empty channel".fmt(f),
+            TryRecvError::Disconnected => "receiving on a closed channel".fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for TryRecvError {}
+
+impl From<RecvError> for TryRecvError {
+    fn from(err: RecvError) -> TryRecvError {
+        match err {
+            RecvError => TryRecvError::Disconnected,
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecvTimeoutError::Timeout => "timed out waiting on channel".fmt(f),
+            RecvTimeoutError::Disconnected => "channel is empty and sending half is closed".fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for RecvTimeoutError {}
+
+impl From<RecvError> for RecvTimeoutError {
+    fn from(err: RecvError) -> RecvTimeoutError {
+        match err {
+            RecvError => RecvTimeoutError::Disconnected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axtask as thread;
+
+    #[test]
+    fn smoke() {
+        crate::init_test_scheduler();
+
+        let (tx, rx) = channel::<i32>();
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn multiple_producers() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: usize = 8;
+        const NUM_MSGS: usize = 100;
+        let (tx, rx) = channel();
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|i| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for j in 0..NUM_MSGS {
+                        tx.send(i * NUM_MSGS + j).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let mut msgs: Vec<_> = rx.iter().collect();
+        for t in tasks {
+            t.join();
+        }
+        msgs.sort();
+        assert_eq!(msgs, (0..NUM_TASKS * NUM_MSGS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bounded() {
+        crate::init_test_scheduler();
+
+        let (tx, rx) = sync_channel(2);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        let t = thread::spawn(move || {
+            for i in 3..10 {
+                tx.send(i).unwrap();
+            }
+        });
+        assert_eq!(rx.iter().collect::<Vec<_>>(), (1..10).collect::<Vec<_>>());
+        t.join();
+    }
+
+    #[test]
+    fn rendezvous() {
+        crate::init_test_scheduler();
+
+        let (tx, rx) = sync_channel(0);
+        assert_eq!(tx.try_send(0), Err(TrySendError::Full(0)));
+        let t = thread::spawn(move || {
+            tx.send(1).unwrap();
+            tx.send(2).unwrap();
+        });
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(2));
+        assert_eq!(rx.recv(), Err(RecvError));
+        t.join();
+    }
+
+    #[test]
+    fn disconnected_receiver() {
+        crate::init_test_scheduler();
+
+        let (tx, rx) = sync_channel(0);
+        let t = thread::spawn(move || assert_eq!(tx.send(1), Err(SendError(1))));
+        // Let the sender block on the rendezvous, if it can run.
+        for _ in 0..10 {
+            thread::yield_now();
+        }
+        drop(rx);
+        t.join();
+
+        let (tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    #[cfg(feature = "irq")]
+    fn timeout() {
+        crate::init_test_scheduler();
+
+        let (tx, rx) = channel::<i32>();
+        assert_eq!(
+            rx.recv_timeout(Duration::ZERO),
+            Err(RecvTimeoutError::Timeout)
+        );
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::ZERO), Ok(1));
+    }
+}
@@ -5,17 +5,37 @@ use core::fmt;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicU64, Ordering};
 
+#[cfg(feature = "lockdep")]
+use axtask::lockdep::{self, LockClass, LockKind};
 use axtask::{current, WaitQueue};
 
+#[cfg(feature = "lock-stat")]
+use crate::stat::LockStat;
+
 /// A mutual exclusion primitive useful for protecting shared data, similar to
 /// [`std::sync::Mutex`](https://doc.rust-lang.org/std/sync/struct.Mutex.html).
 ///
 /// When the mutex is locked, the current task will block and be put into the
 /// wait queue. When the mutex is unlocked, all tasks waiting on the queue
 /// will be woken up.
+///
+/// A mutex created by [`Mutex::new_pi`] enables priority inheritance: the
+/// owner is temporarily raised to the highest priority of the tasks waiting
+/// for it (also through chains of nested mutexes), and restored on unlock.
+///
+/// The fields are kept in order, since `PTHREAD_MUTEX_INITIALIZER` in C is
+/// generated from the layout (see `arceos_posix_api/build.rs`). The lock
+/// class of `lockdep` and the statistics of `lock-stat` come after the common
+/// fields, and are valid when zero-initialized.
+#[repr(C)]
 pub struct Mutex<T: ?Sized> {
     wq: WaitQueue,
     owner_id: AtomicU64,
+    pi: bool,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
+    #[cfg(feature = "lock-stat")]
+    stat: LockStat,
     data: UnsafeCell<T>,
 }
 
@@ -34,10 +54,32 @@ unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
 impl<T> Mutex<T> {
     /// Creates a new [`Mutex`] wrapping the supplied data.
     #[inline(always)]
+    #[track_caller]
     pub const fn new(data: T) -> Self {
         Self {
             wq: WaitQueue::new(),
             owner_id: AtomicU64::new(0),
+            pi: false,
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
+            #[cfg(feature = "lock-stat")]
+            stat: LockStat::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Creates a new [`Mutex`] with priority inheritance enabled.
+    #[inline(always)]
+    #[track_caller]
+    pub const fn new_pi(data: T) -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            owner_id: AtomicU64::new(0),
+            pi: true,
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
+            #[cfg(feature = "lock-stat")]
+            stat: LockStat::new(),
             data: UnsafeCell::new(data),
         }
     }
@@ -64,12 +106,32 @@ impl<T: ?Sized> Mutex<T> {
         self.owner_id.load(Ordering::Relaxed) != 0
     }
 
+    /// Returns `true` if priority inheritance is enabled.
+    #[inline(always)]
+    pub fn is_pi(&self) -> bool {
+        self.pi
+    }
+
+    /// The ID of this lock used by priority inheritance.
+    #[inline(always)]
+    fn lock_id(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
     /// Locks the [`Mutex`] and returns a guard that permits access to the inner data.
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
     pub fn lock(&self) -> MutexGuard<T> {
+        #[cfg(feature = "lockdep")]
+        {
+            lockdep::might_sleep();
+            lockdep::lock_acquire(&self.class, LockKind::Sleep, false);
+        }
         let current_id = current().id().as_u64();
+        let mut blocked = false;
+        #[cfg(feature = "lock-stat")]
+        let mut contended_since = None;
         loop {
             // Can fail to lock even if the spinlock is not locked. May be more efficient than `try_lock`
             // when called in a loop.
@@ -81,17 +143,32 @@ impl<T: ?Sized> Mutex<T> {
             ) {
                 Ok(_) => break,
                 Err(owner_id) => {
+                    #[cfg(feature = "lock-stat")]
+                    contended_since.get_or_insert_with(LockStat::now);
                     assert_ne!(
                         owner_id,
                         current_id,
                         "{} tried to acquire mutex it already owns.",
                         current().id_name()
                     );
+                    if self.pi {
+                        // Lend our priority to the owner while we are waiting
+                        axtask::pi_block_on(self.lock_id(), &self.owner_id, owner_id);
+                        blocked = true;
+                    }
                     // Wait until the lock looks unlocked before retrying
                     self.wq.wait_until(|| !self.is_locked());
                 }
             }
         }
+        if blocked {
+            axtask::pi_unblock();
+        }
+        #[cfg(feature = "lock-stat")]
+        match contended_since {
+            Some(start) => self.stat.acquired(start, true),
+            None => self.stat.acquired(0, false),
+        }
         MutexGuard {
             lock: self,
             data: unsafe { &mut *self.data.get() },
@@ -109,6 +186,10 @@ impl<T: ?Sized> Mutex<T> {
             .compare_exchange(0, current_id, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            #[cfg(feature = "lockdep")]
+            lockdep::lock_acquire(&self.class, LockKind::Sleep, true);
+            #[cfg(feature = "lock-stat")]
+            self.stat.acquired(0, false);
             Some(MutexGuard {
                 lock: self,
                 data: unsafe { &mut *self.data.get() },
@@ -126,6 +207,8 @@ impl<T: ?Sized> Mutex<T> {
     /// thread. However, this can be useful in some instances for exposing
     /// the lock to FFI that doesn’t know how to deal with RAII.
     pub unsafe fn force_unlock(&self) {
+        #[cfg(feature = "lock-stat")]
+        self.stat.released();
         let owner_id = self.owner_id.swap(0, Ordering::Release);
         assert_eq!(
             owner_id,
@@ -133,7 +216,15 @@ impl<T: ?Sized> Mutex<T> {
             "{} tried to release mutex it doesn't own",
             current().id_name()
         );
-        self.wq.notify_one(true);
+        #[cfg(feature = "lockdep")]
+        lockdep::lock_release(&self.class);
+        if self.pi {
+            axtask::pi_release(self.lock_id());
+            // Let all waiters lend their priorities to the new owner.
+            self.wq.notify_all(true);
+        } else {
+            self.wq.notify_one(true);
+        }
     }
 
     /// Returns a mutable reference to the underlying data.
@@ -151,6 +242,7 @@ impl<T: ?Sized> Mutex<T> {
 
 impl<T: ?Sized + Default> Default for Mutex<T> {
     #[inline(always)]
+    #[track_caller]
     fn default() -> Self {
         Self::new(Default::default())
     }
@@ -167,6 +259,13 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
     }
 }
 
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    /// Returns the mutex this guard was created from.
+    pub(crate) fn mutex(&self) -> &'a Mutex<T> {
+        self.lock
+    }
+}
+
 impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
     type Target = T;
     #[inline(always)]
@@ -201,9 +300,6 @@ impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
 mod tests {
     use crate::Mutex;
     use axtask as thread;
-    use std::sync::Once;
-
-    static INIT: Once = Once::new();
 
     fn may_interrupt() {
         // simulate interrupts
@@ -214,7 +310,7 @@ mod tests {
 
     #[test]
     fn lots_and_lots() {
-        INIT.call_once(thread::init_scheduler);
+        crate::init_test_scheduler();
 
         const NUM_TASKS: u32 = 10;
         const NUM_ITERS: u32 = 10_000;
@@ -249,4 +345,44 @@ mod tests {
         assert_eq!(*M.lock(), NUM_ITERS * NUM_TASKS * 3);
         println!("Mutex test OK");
     }
+
+    #[test]
+    fn pi_nested() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: u32 = 10;
+        const NUM_ITERS: u32 = 1_000;
+        static OUTER: Mutex<u32> = Mutex::new_pi(0);
+        static INNER: Mutex<u32> = Mutex::new_pi(0);
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..NUM_ITERS {
+                        let mut outer = OUTER.lock();
+                        may_interrupt();
+                        let mut inner = INNER.lock();
+                        *outer += 1;
+                        *inner += 1;
+                        may_interrupt();
+                        drop(inner);
+                        drop(outer);
+                        may_interrupt();
+                    }
+                })
+            })
+            .collect();
+        for t in tasks {
+            t.join();
+        }
+
+        assert!(OUTER.is_pi());
+        assert_eq!(*OUTER.lock(), NUM_ITERS * NUM_TASKS);
+        assert_eq!(*INNER.lock(), NUM_ITERS * NUM_TASKS);
+        assert_eq!(
+            thread::current().priority(),
+            thread::current().base_priority()
+        );
+        println!("PI mutex test OK");
+    }
 }
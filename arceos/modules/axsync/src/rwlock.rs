@@ -0,0 +1,574 @@
+//! A sleeping reader-writer lock.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "lockdep")]
+use axtask::lockdep::{self, LockClass, LockKind};
+#[cfg(feature = "multitask")]
+use axtask::WaitQueue;
+
+#[cfg(feature = "lock-stat")]
+use crate::stat::LockStat;
+#[cfg(not(feature = "multitask"))]
+use crate::wait::WaitQueue;
+
+/// The lock is held by a writer.
+const WRITER: usize = 1;
+/// The lock is held by an upgradable reader.
+const UPGRADABLE: usize = 1 << 1;
+/// The unit of the reader count.
+const READER: usize = 1 << 2;
+
+/// A reader-writer lock, similar to
+/// [`std::sync::RwLock`](https://doc.rust-lang.org/std/sync/struct.RwLock.html).
+///
+/// It allows any number of readers or at most one writer at a time. Tasks
+/// that cannot acquire the lock are blocked in wait queues.
+///
+/// The lock is writer-preferring: new readers are blocked as long as there
+/// are writers waiting, so that writers cannot be starved.
+///
+/// An upgradable read lock ([`RwLock::upgradable_read`]) can be held along
+/// with normal readers but not with other upgradable readers, and can be
+/// upgraded to a write lock atomically.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicUsize,
+    /// The number of writers (including upgrading readers) waiting.
+    writers_waiting: AtomicUsize,
+    read_wq: WaitQueue,
+    write_wq: WaitQueue,
+    #[cfg(feature = "lockdep")]
+    class: LockClass,
+    #[cfg(feature = "lock-stat")]
+    stat: LockStat,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will release the read lock.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *const T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the write lock.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *mut T,
+}
+
+/// A guard that provides immutable data access, and can be upgraded to a
+/// [`RwLockWriteGuard`].
+///
+/// When the guard falls out of scope it will release the upgradable lock.
+pub struct RwLockUpgradableGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    data: *const T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockUpgradableGuard<'_, T> {}
+
+impl<T> RwLock<T> {
+    /// Creates a new [`RwLock`] wrapping the supplied data.
+    #[inline(always)]
+    #[track_caller]
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+            read_wq: WaitQueue::new(),
+            write_wq: WaitQueue::new(),
+            #[cfg(feature = "lockdep")]
+            class: LockClass::new(),
+            #[cfg(feature = "lock-stat")]
+            stat: LockStat::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this [`RwLock`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        let RwLock { data, .. } = self;
+        data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Returns the number of readers (excluding the upgradable one).
+    ///
+    /// Like [`Mutex::is_locked`](crate::Mutex::is_locked), the result is
+    /// only a heuristic.
+    #[inline(always)]
+    pub fn reader_count(&self) -> usize {
+        self.state.load(Ordering::Relaxed) / READER
+    }
+
+    /// Returns `true` if the lock is held by a writer.
+    ///
+    /// Like [`Mutex::is_locked`](crate::Mutex::is_locked), the result is
+    /// only a heuristic.
+    #[inline(always)]
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER != 0
+    }
+
+    fn can_read(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER == 0
+            && self.writers_waiting.load(Ordering::Relaxed) == 0
+    }
+
+    /// Locks this [`RwLock`] with shared read access, blocking the current
+    /// task until it can be acquired.
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        self.lockdep_acquire(false);
+        #[cfg(feature = "lock-stat")]
+        let mut contended_since = None;
+        while !self.try_lock_read() {
+            #[cfg(feature = "lock-stat")]
+            contended_since.get_or_insert_with(LockStat::now);
+            self.read_wq.wait_until(|| self.can_read());
+        }
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(contended_since);
+        RwLockReadGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
+    /// Tries to lock this [`RwLock`] with shared read access.
+    ///
+    /// It fails if the lock is held by a writer, or there are writers
+    /// waiting.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        if !self.try_lock_read() {
+            return None;
+        }
+        self.lockdep_acquire(true);
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(None);
+        Some(RwLockReadGuard {
+            lock: self,
+            data: self.data.get(),
+        })
+    }
+
+    /// Locks this [`RwLock`] with exclusive write access, blocking the
+    /// current task until it can be acquired.
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        self.lockdep_acquire(false);
+        #[cfg(feature = "lock-stat")]
+        let mut contended_since = None;
+        if !self.try_lock_write() {
+            #[cfg(feature = "lock-stat")]
+            contended_since.get_or_insert_with(LockStat::now);
+            self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+            while !self.try_lock_write() {
+                self.write_wq
+                    .wait_until(|| self.state.load(Ordering::Relaxed) == 0);
+            }
+            self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+        }
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(contended_since);
+        RwLockWriteGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
+    /// Tries to lock this [`RwLock`] with exclusive write access.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if !self.try_lock_write() {
+            return None;
+        }
+        self.lockdep_acquire(true);
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(None);
+        Some(RwLockWriteGuard {
+            lock: self,
+            data: self.data.get(),
+        })
+    }
+
+    /// Locks this [`RwLock`] with upgradable read access, blocking the
+    /// current task until it can be acquired.
+    pub fn upgradable_read(&self) -> RwLockUpgradableGuard<T> {
+        self.lockdep_acquire(false);
+        #[cfg(feature = "lock-stat")]
+        let mut contended_since = None;
+        while !self.try_lock_upgradable() {
+            #[cfg(feature = "lock-stat")]
+            contended_since.get_or_insert_with(LockStat::now);
+            // Wait on the write queue, as it is blocked by writers and other
+            // upgradable readers.
+            self.write_wq.wait_until(|| {
+                self.state.load(Ordering::Relaxed) & (WRITER | UPGRADABLE) == 0
+                    && self.writers_waiting.load(Ordering::Relaxed) == 0
+            });
+        }
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(contended_since);
+        RwLockUpgradableGuard {
+            lock: self,
+            data: self.data.get(),
+        }
+    }
+
+    /// Tries to lock this [`RwLock`] with upgradable read access.
+    pub fn try_upgradable_read(&self) -> Option<RwLockUpgradableGuard<T>> {
+        if !self.try_lock_upgradable() {
+            return None;
+        }
+        self.lockdep_acquire(true);
+        #[cfg(feature = "lock-stat")]
+        self.stat_acquired(None);
+        Some(RwLockUpgradableGuard {
+            lock: self,
+            data: self.data.get(),
+        })
+    }
+
+    fn try_lock_read(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 || self.writers_waiting.load(Ordering::Relaxed) != 0 {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn try_lock_write(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn try_lock_upgradable(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & (WRITER | UPGRADABLE) != 0
+                || self.writers_waiting.load(Ordering::Relaxed) != 0
+            {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | UPGRADABLE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    /// Records the acquisition with the lock dependency validator, before
+    /// blocking for the lock unless `trylock` is set.
+    #[inline(always)]
+    fn lockdep_acquire(&self, _trylock: bool) {
+        #[cfg(feature = "lockdep")]
+        {
+            if !_trylock {
+                lockdep::might_sleep();
+            }
+            lockdep::lock_acquire(&self.class, LockKind::Sleep, _trylock);
+        }
+    }
+
+    /// Records the acquisition in the contention statistics, which had to
+    /// wait since `contended_since` if it is not `None`.
+    ///
+    /// The hold time is measured from the latest acquisition, so it is only
+    /// approximate when there are multiple readers.
+    #[cfg(feature = "lock-stat")]
+    fn stat_acquired(&self, contended_since: Option<u64>) {
+        match contended_since {
+            Some(start) => self.stat.acquired(start, true),
+            None => self.stat.acquired(0, false),
+        }
+    }
+
+    /// Force unlock a read lock of the [`RwLock`].
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the current task does not hold a read
+    /// lock. It can be useful for exposing the lock to FFI that doesn't know
+    /// how to deal with RAII.
+    pub unsafe fn force_read_unlock(&self) {
+        self.read_unlock();
+    }
+
+    /// Force unlock the write lock of the [`RwLock`].
+    ///
+    /// # Safety
+    ///
+    /// This is *extremely* unsafe if the current task does not hold the
+    /// write lock. It can be useful for exposing the lock to FFI that doesn't
+    /// know how to deal with RAII.
+    pub unsafe fn force_write_unlock(&self) {
+        self.write_unlock();
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the [`RwLock`] mutably, no actual locking needs
+    /// to take place.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn read_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::lock_release(&self.class);
+        #[cfg(feature = "lock-stat")]
+        self.stat.released();
+        let state = self.state.fetch_sub(READER, Ordering::Release) - READER;
+        if state & !UPGRADABLE == 0 {
+            // The last reader wakes up writers, or the upgrading reader.
+            self.write_wq.notify_all(true);
+        }
+    }
+
+    fn upgradable_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::lock_release(&self.class);
+        #[cfg(feature = "lock-stat")]
+        self.stat.released();
+        self.state.fetch_and(!UPGRADABLE, Ordering::Release);
+        self.write_wq.notify_all(true);
+    }
+
+    fn write_unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::lock_release(&self.class);
+        #[cfg(feature = "lock-stat")]
+        self.stat.released();
+        self.state.fetch_and(!WRITER, Ordering::Release);
+        self.write_wq.notify_all(true);
+        self.read_wq.notify_all(true);
+    }
+}
+
+impl<T: ?Sized + Default> Default for RwLock<T> {
+    #[inline(always)]
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "RwLock {{ data: ")
+                .and_then(|()| (*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockUpgradableGuard<'a, T> {
+    /// Upgrades to a write lock, blocking the current task until all readers
+    /// release the lock.
+    ///
+    /// New readers are blocked while upgrading.
+    pub fn upgrade(self) -> RwLockWriteGuard<'a, T> {
+        let lock = self.lock;
+        core::mem::forget(self);
+        lock.writers_waiting.fetch_add(1, Ordering::Relaxed);
+        let upgrade = || {
+            lock.state
+                .compare_exchange(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        };
+        while !upgrade() {
+            lock.write_wq
+                .wait_until(|| lock.state.load(Ordering::Relaxed) == UPGRADABLE);
+        }
+        lock.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+        RwLockWriteGuard {
+            lock,
+            data: lock.data.get(),
+        }
+    }
+
+    /// Tries to upgrade to a write lock, which fails if there are readers.
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        let lock = self.lock;
+        match lock
+            .state
+            .compare_exchange(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => {
+                core::mem::forget(self);
+                Ok(RwLockWriteGuard {
+                    lock,
+                    data: lock.data.get(),
+                })
+            }
+            Err(_) => Err(self),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> RwLockWriteGuard<'a, T> {
+    /// Downgrades to a read lock atomically, and wakes up waiting readers.
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        let lock = self.lock;
+        core::mem::forget(self);
+        lock.state.store(READER, Ordering::Release);
+        lock.read_wq.notify_all(true);
+        lock.write_wq.notify_all(true);
+        RwLockReadGuard {
+            lock,
+            data: lock.data.get(),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockUpgradableGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockUpgradableGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockUpgradableGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.upgradable_unlock();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RwLock;
+    use axtask as thread;
+
+    #[test]
+    fn rwlock_readers_and_writers() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: u32 = 10;
+        const NUM_ITERS: u32 = 1_000;
+        static L: RwLock<(u32, u32)> = RwLock::new((0, 0));
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|i| {
+                thread::spawn(move || {
+                    for _ in 0..NUM_ITERS {
+                        if i % 2 == 0 {
+                            let mut val = L.write();
+                            val.0 += 1;
+                            thread::yield_now();
+                            val.1 += 1;
+                        } else if i % 4 == 1 {
+                            let val = L.read();
+                            thread::yield_now();
+                            assert_eq!(val.0, val.1);
+                        } else {
+                            let val = L.upgradable_read();
+                            assert_eq!(val.0, val.1);
+                            let mut val = val.upgrade();
+                            val.0 += 1;
+                            thread::yield_now();
+                            val.1 += 1;
+                            let val = val.downgrade();
+                            assert_eq!(val.0, val.1);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in tasks {
+            t.join();
+        }
+
+        let val = L.read();
+        assert_eq!(val.0, val.1);
+        assert_eq!(val.0, NUM_ITERS * (NUM_TASKS / 2 + NUM_TASKS / 4));
+        assert_eq!(L.reader_count(), 1);
+        assert!(!L.is_write_locked());
+        println!("RwLock test OK");
+    }
+}
@@ -0,0 +1,140 @@
+//! A counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "irq")]
+use core::time::Duration;
+
+#[cfg(feature = "multitask")]
+use axtask::WaitQueue;
+
+#[cfg(not(feature = "multitask"))]
+use crate::wait::WaitQueue;
+
+/// A counting semaphore.
+///
+/// It maintains a count of permits. [`Semaphore::acquire`] takes a permit,
+/// blocking the current task until one is available, and
+/// [`Semaphore::release`] returns a permit.
+pub struct Semaphore {
+    count: AtomicUsize,
+    wq: WaitQueue,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the number of available permits.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Takes a permit, blocking the current task until one is available.
+    pub fn acquire(&self) {
+        self.wq.wait_until(|| self.try_acquire());
+    }
+
+    /// Tries to take a permit without blocking.
+    ///
+    /// Returns `true` if a permit is taken.
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Takes a permit, blocking the current task until one is available or
+    /// the given duration has elapsed.
+    ///
+    /// Returns `true` if a permit is taken.
+    #[cfg(feature = "irq")]
+    pub fn acquire_timeout(&self, dur: Duration) -> bool {
+        !self.wq.wait_timeout_until(dur, || self.try_acquire())
+    }
+
+    /// Returns a permit, and wakes up one task waiting for it.
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+
+    /// Returns a permit unless there are already `max` permits available, and
+    /// wakes up one task waiting for it.
+    ///
+    /// The bound is checked and the permit is returned in one atomic step.
+    /// Returns `false` if the semaphore is full and nothing is done.
+    pub fn release_bounded(&self, max: usize) -> bool {
+        let released = self
+            .count
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .is_ok();
+        if released {
+            self.wq.notify_one(true);
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Semaphore;
+    use axtask as thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn semaphore_permits() {
+        crate::init_test_scheduler();
+
+        let sem = Semaphore::new(1);
+        assert!(sem.try_acquire());
+        assert!(!sem.try_acquire());
+        assert_eq!(sem.count(), 0);
+        sem.release();
+        assert_eq!(sem.count(), 1);
+
+        assert!(sem.release_bounded(2));
+        assert!(!sem.release_bounded(2));
+        assert_eq!(sem.count(), 2);
+    }
+
+    #[test]
+    fn semaphore_limits_concurrency() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: usize = 10;
+        const NUM_ITERS: usize = 100;
+        const PERMITS: usize = 3;
+        static SEM: Semaphore = Semaphore::new(PERMITS);
+        static HOLDERS: AtomicUsize = AtomicUsize::new(0);
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..NUM_ITERS {
+                        SEM.acquire();
+                        let holders = HOLDERS.fetch_add(1, Ordering::SeqCst) + 1;
+                        assert!(holders <= PERMITS);
+                        thread::yield_now();
+                        HOLDERS.fetch_sub(1, Ordering::SeqCst);
+                        SEM.release();
+                    }
+                })
+            })
+            .collect();
+        for t in tasks {
+            t.join();
+        }
+
+        assert_eq!(SEM.count(), PERMITS);
+        println!("Semaphore test OK");
+    }
+}
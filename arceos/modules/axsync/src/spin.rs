@@ -0,0 +1,173 @@
+//! Spinlocks of the [`kspin`] crate, with contention statistics and lock
+//! dependency validation.
+//!
+//! They are used instead of the [`kspin`] locks when the `lock-stat` or
+//! `lockdep` feature is enabled, and have the same interfaces.
+//!
+//! The locks of the scheduler (`axtask`) and the global allocator are not
+//! instrumented, since they are used by the instrumentation itself.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "lockdep")]
+use axtask::lockdep::{self, LockClass, LockKind};
+
+#[cfg(feature = "lock-stat")]
+use crate::stat::LockStat;
+
+macro_rules! def_spinlock {
+    ($(#[$attr:meta])* $name:ident, $guard:ident) => {
+        $(#[$attr])*
+        pub struct $name<T: ?Sized> {
+            #[cfg(feature = "lockdep")]
+            class: LockClass,
+            #[cfg(feature = "lock-stat")]
+            stat: LockStat,
+            inner: kspin::$name<T>,
+        }
+
+        #[doc = concat!("A guard of [`", stringify!($name), "`].")]
+        pub struct $guard<'a, T: ?Sized + 'a> {
+            lock: &'a $name<T>,
+            inner: kspin::$guard<'a, T>,
+        }
+
+        impl<T> $name<T> {
+            #[doc = concat!("Creates a new [`", stringify!($name), "`] wrapping the supplied data.")]
+            #[inline(always)]
+            #[track_caller]
+            pub const fn new(data: T) -> Self {
+                Self {
+                    #[cfg(feature = "lockdep")]
+                    class: LockClass::new(),
+                    #[cfg(feature = "lock-stat")]
+                    stat: LockStat::new(),
+                    inner: kspin::$name::new(data),
+                }
+            }
+
+            #[doc = concat!("Consumes this [`", stringify!($name), "`] and unwraps the underlying data.")]
+            #[inline(always)]
+            pub fn into_inner(self) -> T {
+                self.inner.into_inner()
+            }
+        }
+
+        impl<T: ?Sized> $name<T> {
+            /// Locks the spinlock and returns a guard that permits access to
+            /// the inner data.
+            #[inline(always)]
+            pub fn lock(&self) -> $guard<T> {
+                #[cfg(feature = "lockdep")]
+                lockdep::lock_acquire(&self.class, LockKind::Spin, false);
+                #[cfg(feature = "lock-stat")]
+                let inner = match self.inner.try_lock() {
+                    Some(inner) => {
+                        self.stat.acquired(0, false);
+                        inner
+                    }
+                    None => {
+                        let start = LockStat::now();
+                        let inner = self.inner.lock();
+                        self.stat.acquired(start, true);
+                        inner
+                    }
+                };
+                #[cfg(not(feature = "lock-stat"))]
+                let inner = self.inner.lock();
+                $guard { lock: self, inner }
+            }
+
+            /// Try to lock the spinlock, returning a lock guard if successful.
+            #[inline(always)]
+            pub fn try_lock(&self) -> Option<$guard<T>> {
+                let inner = self.inner.try_lock()?;
+                #[cfg(feature = "lockdep")]
+                lockdep::lock_acquire(&self.class, LockKind::Spin, true);
+                #[cfg(feature = "lock-stat")]
+                self.stat.acquired(0, false);
+                Some($guard { lock: self, inner })
+            }
+
+            /// Returns `true` if the lock is currently held.
+            #[inline(always)]
+            pub fn is_locked(&self) -> bool {
+                self.inner.is_locked()
+            }
+
+            /// Returns a mutable reference to the underlying data.
+            #[inline(always)]
+            pub fn get_mut(&mut self) -> &mut T {
+                self.inner.get_mut()
+            }
+        }
+
+        impl<T: Default> Default for $name<T> {
+            #[inline(always)]
+            #[track_caller]
+            fn default() -> Self {
+                Self::new(Default::default())
+            }
+        }
+
+        impl<T: ?Sized + fmt::Debug> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.inner.fmt(f)
+            }
+        }
+
+        impl<T: ?Sized> Deref for $guard<'_, T> {
+            type Target = T;
+            #[inline(always)]
+            fn deref(&self) -> &T {
+                &self.inner
+            }
+        }
+
+        impl<T: ?Sized> DerefMut for $guard<'_, T> {
+            #[inline(always)]
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.inner
+            }
+        }
+
+        impl<T: ?Sized + fmt::Debug> fmt::Debug for $guard<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(&**self, f)
+            }
+        }
+
+        impl<T: ?Sized> Drop for $guard<'_, T> {
+            fn drop(&mut self) {
+                // The lock itself is released after this, when `inner` is
+                // dropped.
+                #[cfg(feature = "lockdep")]
+                lockdep::lock_release(&self.lock.class);
+                #[cfg(feature = "lock-stat")]
+                self.lock.stat.released();
+            }
+        }
+    };
+}
+
+def_spinlock!(
+    /// A spinlock that disables kernel preemption while trying to lock, and
+    /// re-enables it after unlocking.
+    SpinNoPreempt,
+    SpinNoPreemptGuard
+);
+def_spinlock!(
+    /// A spinlock that disables kernel preemption and local IRQs while trying
+    /// to lock, and re-enables it after unlocking.
+    SpinNoIrq,
+    SpinNoIrqGuard
+);
+def_spinlock!(
+    /// A raw spinlock that does nothing while trying to lock.
+    ///
+    /// It must be used in the preemption and IRQ disabled context, or never
+    /// be used in interrupt handlers.
+    SpinRaw,
+    SpinRawGuard
+);
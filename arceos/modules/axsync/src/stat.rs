@@ -0,0 +1,243 @@
+//! Lock contention statistics.
+//!
+//! The statistics are collected per lock site, i.e., the place where locks
+//! are created, so all locks created at the same place (e.g., the locks of
+//! all sockets) are accounted together. Use [`top_contended`] to find the
+//! hottest lock sites. Locks that are zero-initialized (e.g., by
+//! `PTHREAD_MUTEX_INITIALIZER` in C) have no creation site, and are accounted
+//! together as well.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::panic::Location;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::monotonic_time_nanos;
+use kspin::SpinNoIrq;
+
+/// Statistics of all lock sites, which are never freed.
+static SITES: SpinNoIrq<BTreeMap<(&'static str, u32, u32), &'static SiteStat>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+struct SiteStat {
+    location: Option<&'static Location<'static>>,
+    acquisitions: AtomicU64,
+    contentions: AtomicU64,
+    wait_total_ns: AtomicU64,
+    wait_max_ns: AtomicU64,
+    hold_max_ns: AtomicU64,
+}
+
+/// The statistics of a lock site, returned by [`top_contended`].
+#[derive(Debug, Clone)]
+pub struct LockStatEntry {
+    /// The place where the locks are created, or `None` for the locks that
+    /// are zero-initialized.
+    pub location: Option<&'static Location<'static>>,
+    /// The number of acquisitions.
+    pub acquisitions: u64,
+    /// The number of acquisitions that had to wait for the lock.
+    pub contentions: u64,
+    /// The total time spent waiting for the lock.
+    pub wait_total: Duration,
+    /// The maximum time spent waiting for the lock.
+    pub wait_max: Duration,
+    /// The maximum time the lock is held.
+    pub hold_max: Duration,
+}
+
+/// The statistics handle embedded in a lock, which is valid when
+/// zero-initialized.
+pub(crate) struct LockStat {
+    location: Option<&'static Location<'static>>,
+    site: AtomicPtr<SiteStat>,
+    /// When the lock was acquired, in nanoseconds since boot.
+    locked_at: AtomicU64,
+}
+
+impl SiteStat {
+    fn entry(&self) -> LockStatEntry {
+        let ns = |v: &AtomicU64| Duration::from_nanos(v.load(Ordering::Relaxed));
+        LockStatEntry {
+            location: self.location,
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contentions: self.contentions.load(Ordering::Relaxed),
+            wait_total: ns(&self.wait_total_ns),
+            wait_max: ns(&self.wait_max_ns),
+            hold_max: ns(&self.hold_max_ns),
+        }
+    }
+
+    fn reset(&self) {
+        self.acquisitions.store(0, Ordering::Relaxed);
+        self.contentions.store(0, Ordering::Relaxed);
+        self.wait_total_ns.store(0, Ordering::Relaxed);
+        self.wait_max_ns.store(0, Ordering::Relaxed);
+        self.hold_max_ns.store(0, Ordering::Relaxed);
+    }
+}
+
+impl LockStat {
+    /// Creates the statistics handle of a lock created by the caller.
+    #[track_caller]
+    pub(crate) const fn new() -> Self {
+        Self {
+            location: Some(Location::caller()),
+            site: AtomicPtr::new(null_mut()),
+            locked_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the current time, to be passed to [`LockStat::acquired`].
+    #[inline]
+    pub(crate) fn now() -> u64 {
+        monotonic_time_nanos()
+    }
+
+    fn site(&self) -> &'static SiteStat {
+        let site = self.site.load(Ordering::Acquire);
+        if !site.is_null() {
+            return unsafe { &*site };
+        }
+        let loc = self.location;
+        let key = loc.map_or(("", 0, 0), |l| (l.file(), l.line(), l.column()));
+        let site = *SITES.lock().entry(key).or_insert_with(|| {
+            Box::leak(Box::new(SiteStat {
+                location: loc,
+                acquisitions: AtomicU64::new(0),
+                contentions: AtomicU64::new(0),
+                wait_total_ns: AtomicU64::new(0),
+                wait_max_ns: AtomicU64::new(0),
+                hold_max_ns: AtomicU64::new(0),
+            }))
+        });
+        self.site
+            .store(site as *const _ as *mut _, Ordering::Release);
+        site
+    }
+
+    /// Records an acquisition of the lock, which started waiting at `start`
+    /// if it is `contended`.
+    pub(crate) fn acquired(&self, start: u64, contended: bool) {
+        let site = self.site();
+        let now = Self::now();
+        site.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if contended {
+            let wait = now.saturating_sub(start);
+            site.contentions.fetch_add(1, Ordering::Relaxed);
+            site.wait_total_ns.fetch_add(wait, Ordering::Relaxed);
+            site.wait_max_ns.fetch_max(wait, Ordering::Relaxed);
+        }
+        self.locked_at.store(now, Ordering::Relaxed);
+    }
+
+    /// Records a release of the lock.
+    pub(crate) fn released(&self) {
+        let hold = Self::now().saturating_sub(self.locked_at.load(Ordering::Relaxed));
+        self.site().hold_max_ns.fetch_max(hold, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for LockStatEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:>10} {:>12} {:>12} {:>12} {}",
+            self.acquisitions,
+            self.contentions,
+            self.wait_total.as_micros(),
+            self.wait_max.as_micros(),
+            self.hold_max.as_micros(),
+            Site(self.location),
+        )
+    }
+}
+
+struct Site(Option<&'static Location<'static>>);
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(loc) => write!(f, "{}", loc),
+            None => write!(f, "<zero-initialized>"),
+        }
+    }
+}
+
+impl LockStatEntry {
+    /// The header of the table printed with [`Display`](fmt::Display).
+    pub const HEADER: &'static str =
+        "      ACQS   CONTENDS WAIT_SUM(us) WAIT_MAX(us) HOLD_MAX(us) SITE";
+}
+
+/// Returns the statistics of at most `n` lock sites with the most contended
+/// acquisitions, in descending order.
+pub fn top_contended(n: usize) -> Vec<LockStatEntry> {
+    let mut entries: Vec<_> = SITES.lock().values().map(|s| s.entry()).collect();
+    entries.sort_by(|a, b| {
+        (b.contentions, b.wait_total, b.acquisitions).cmp(&(
+            a.contentions,
+            a.wait_total,
+            a.acquisitions,
+        ))
+    });
+    entries.truncate(n);
+    entries
+}
+
+/// Resets the statistics of all lock sites.
+pub fn reset() {
+    for site in SITES.lock().values() {
+        site.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the position in [`top_contended`] and the statistics of the
+    /// site of `stat`.
+    fn find(stat: &LockStat) -> (usize, LockStatEntry) {
+        let entries = top_contended(usize::MAX);
+        let pos = entries
+            .iter()
+            .position(|e| e.location == stat.location)
+            .unwrap();
+        (pos, entries[pos].clone())
+    }
+
+    fn lock(stat: &LockStat, contended: bool) {
+        stat.acquired(LockStat::now(), contended);
+        stat.released();
+    }
+
+    #[test]
+    fn lock_stat_counting() {
+        let hot = LockStat::new();
+        let cold = LockStat::new();
+        let zeroed: LockStat = unsafe { core::mem::zeroed() };
+        for i in 0..100 {
+            lock(&hot, i % 2 == 0);
+            lock(&cold, i == 0);
+            lock(&zeroed, i < 10);
+        }
+
+        let (hot_pos, hot) = find(&hot);
+        let (cold_pos, cold) = find(&cold);
+        let (zeroed_pos, zeroed) = find(&zeroed);
+        assert_eq!((hot.acquisitions, hot.contentions), (100, 50));
+        assert_eq!((cold.acquisitions, cold.contentions), (100, 1));
+        assert_eq!((zeroed.acquisitions, zeroed.contentions), (100, 10));
+        assert!(hot.wait_max <= hot.wait_total);
+        assert!(zeroed.location.is_none());
+
+        // Sorted by the number of contentions in descending order.
+        assert!(hot_pos < zeroed_pos && zeroed_pos < cold_pos);
+        assert_eq!(top_contended(1).len(), 1);
+    }
+}
@@ -0,0 +1,29 @@
+//! A wait queue which spins instead of blocking, used when `multitask` is
+//! disabled.
+//!
+//! It has the same interface as [`axtask::WaitQueue`] for the parts used by
+//! the primitives in this crate. Without other tasks, a condition can only
+//! be changed by interrupt handlers.
+
+pub(crate) struct WaitQueue;
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    pub fn wait_until<F>(&self, condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        while !condition() {
+            axtask::yield_now();
+        }
+    }
+
+    pub fn notify_one(&self, _resched: bool) -> bool {
+        false
+    }
+
+    pub fn notify_all(&self, _resched: bool) {}
+}
@@ -1,27 +1,48 @@
 //! Task APIs for multi-task configuration.
 
-use alloc::{string::String, sync::Arc};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
-pub(crate) use crate::run_queue::{AxRunQueue, RUN_QUEUE};
+pub(crate) use crate::run_queue::{current_run_queue, AxRunQueue};
 
 #[doc(cfg(feature = "multitask"))]
-pub use crate::task::{CurrentTask, TaskId, TaskInner};
+pub use crate::cpumask::AxCpuMask;
+#[doc(cfg(feature = "multitask"))]
+pub use crate::pi::{pi_block_on, pi_release, pi_unblock};
+#[cfg(all(
+    feature = "sched_edf",
+    not(any(feature = "sched_det", feature = "sched_rr", feature = "sched_cfs"))
+))]
+pub use crate::sched_edf::{EdfTask, RtParams};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task::{Cancelled, CurrentTask, TaskId, TaskInner, TaskSnapshot, TaskState};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
+#[cfg(feature = "task_group")]
+pub use crate::task_group::{TaskGroup, TaskGroupRef, TaskGroupStat};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::task_local::LocalKey;
 #[doc(cfg(feature = "multitask"))]
 pub use crate::wait_queue::WaitQueue;
+#[cfg(feature = "watchdog")]
+pub use crate::watchdog::{set_watchdog_config, watchdog_config, WatchdogConfig};
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
 
 cfg_if::cfg_if! {
-    if #[cfg(feature = "sched_rr")] {
+    if #[cfg(feature = "sched_det")] {
+        pub(crate) type AxTask = crate::sched_det::DetTask<TaskInner>;
+        pub(crate) type Scheduler = crate::sched_det::DetScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_rr")] {
         const MAX_TIME_SLICE: usize = 5;
         pub(crate) type AxTask = scheduler::RRTask<TaskInner, MAX_TIME_SLICE>;
         pub(crate) type Scheduler = scheduler::RRScheduler<TaskInner, MAX_TIME_SLICE>;
     } else if #[cfg(feature = "sched_cfs")] {
         pub(crate) type AxTask = scheduler::CFSTask<TaskInner>;
         pub(crate) type Scheduler = scheduler::CFScheduler<TaskInner>;
+    } else if #[cfg(feature = "sched_edf")] {
+        pub(crate) type AxTask = crate::sched_edf::EdfTask<TaskInner>;
+        pub(crate) type Scheduler = crate::sched_edf::EdfScheduler<TaskInner>;
     } else {
         // If no scheduler features are set, use FIFO as the default.
         pub(crate) type AxTask = scheduler::FifoTask<TaskInner>;
@@ -70,6 +91,8 @@ pub fn init_scheduler() {
     crate::run_queue::init();
     #[cfg(feature = "irq")]
     crate::timers::init();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::init();
 
     info!("  use {} scheduler.", Scheduler::scheduler_name());
 }
@@ -81,18 +104,48 @@ pub fn init_scheduler_secondary() {
 
 /// Handles periodic timer ticks for the task manager.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
+/// For example, advance scheduler states, checks timed events, etc. It also
+/// programs the timer for the next tick, or for the next timed event if the
+/// current CPU is idle.
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     crate::timers::check_events();
-    RUN_QUEUE.lock().scheduler_timer_tick();
+    current_run_queue().scheduler_timer_tick();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::on_timer_tick();
+    crate::timers::update_timer();
+}
+
+/// Handles the reschedule IPI, which is sent by other CPUs after they put
+/// tasks into the pending list of the current CPU, or deferred priority
+/// changes of its tasks to it.
+///
+/// The pending tasks are moved into the run queue, and the current task is
+/// preempted if possible so that they can be scheduled at once.
+#[cfg(all(feature = "irq", feature = "smp"))]
+#[doc(cfg(all(feature = "irq", feature = "smp")))]
+pub fn on_reschedule_ipi() {
+    current_run_queue().take_pending_tasks();
+    #[cfg(feature = "preempt")]
+    current().set_preempt_pending(true);
+}
+
+/// Returns the number of timer interrupts and the number of times idle CPUs
+/// are woken up, on all CPUs since boot.
+///
+/// The periodic tick is stopped on idle CPUs, these counters can be used to
+/// measure how many interrupts are saved.
+#[cfg(feature = "irq")]
+#[doc(cfg(feature = "irq"))]
+pub fn timer_stats() -> (u64, u64) {
+    crate::timers::timer_stats()
 }
 
 /// Adds the given task to the run queue, returns the task reference.
 pub fn spawn_task(task: TaskInner) -> AxTaskRef {
     let task_ref = task.into_arc();
-    RUN_QUEUE.lock().add_task(task_ref.clone());
+    current_run_queue().add_task(task_ref.clone());
     task_ref
 }
 
@@ -119,6 +172,170 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Spawns a new real-time task with the given parameters.
+///
+/// The task is scheduled by the EDF scheduler before all other tasks. Its
+/// deadline misses can be queried by [`EdfTask::deadline_misses`].
+///
+/// Returns [`None`] if the parameters are invalid, or the total utilization
+/// of real-time tasks would exceed 100%.
+#[cfg(all(
+    feature = "sched_edf",
+    not(any(feature = "sched_det", feature = "sched_rr", feature = "sched_cfs"))
+))]
+pub fn spawn_rt<F>(f: F, name: String, params: RtParams) -> Option<AxTaskRef>
+where
+    F: FnOnce() + Send + 'static,
+{
+    if !crate::sched_edf::admit(&params) {
+        return None;
+    }
+    let task = TaskInner::new(f, name, axconfig::TASK_STACK_SIZE).into_arc();
+    task.init_rt(params);
+    current_run_queue().add_task(task.clone());
+    Some(task)
+}
+
+/// The error returned by [`wait_any`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WaitAnyError {
+    /// No tasks are given.
+    Empty,
+    /// The task at the given index in `tasks` is not a child of the current
+    /// task.
+    NotChild(usize),
+}
+
+/// Waits for any of the given tasks, which must be children of the current
+/// task (i.e., spawned by it), to exit.
+///
+/// Returns the first exited task in `tasks` and its exit code. It returns
+/// immediately if some of the tasks have already exited.
+pub fn wait_any(tasks: &[AxTaskRef]) -> Result<(AxTaskRef, i32), WaitAnyError> {
+    let curr = current();
+    let curr_id = curr.id().as_u64();
+    if tasks.is_empty() {
+        return Err(WaitAnyError::Empty);
+    }
+    if let Some(index) = tasks.iter().position(|t| t.parent_id() != Some(curr_id)) {
+        return Err(WaitAnyError::NotChild(index));
+    }
+    let exited = || tasks.iter().find(|t| t.state() == TaskState::Exited);
+    curr.child_exit().wait_until(|| exited().is_some());
+    let task = exited().unwrap().clone();
+    // It does not block, as the task has exited.
+    let exit_code = task.join().unwrap();
+    Ok((task, exit_code))
+}
+
+/// Calls `f` on each live task, in the order of task IDs.
+///
+/// Tasks created or dropped during the iteration may or may not be visited.
+pub fn for_each_task<F>(mut f: F)
+where
+    F: FnMut(&AxTaskRef),
+{
+    for task in crate::task::all_tasks() {
+        f(&task);
+    }
+}
+
+/// Takes snapshots of all live tasks, in the order of task IDs.
+pub fn task_snapshot() -> Vec<TaskSnapshot> {
+    crate::task::all_tasks()
+        .iter()
+        .map(|task| task.snapshot())
+        .collect()
+}
+
+/// Returns the user and kernel CPU time consumed by all tasks, including the
+/// exited ones, but excluding the idle tasks.
+///
+/// As all tasks share the same address space, it is the CPU time of the
+/// whole "process".
+pub fn total_cpu_times() -> (core::time::Duration, core::time::Duration) {
+    crate::task::total_cpu_times()
+}
+
+/// A builder to configure and spawn a new task.
+///
+/// # Examples
+///
+/// ```
+/// use axtask::{AxCpuMask, TaskBuilder};
+///
+/// axtask::init_scheduler();
+/// let task = TaskBuilder::new()
+///     .name("worker".into())
+///     .stack_size(0x4000)
+///     .cpumask(AxCpuMask::one_shot(0))
+///     .spawn(|| {
+///         assert!(axtask::current().cpumask().get(0));
+///     });
+/// assert_eq!(task.join(), Some(0));
+/// ```
+pub struct TaskBuilder {
+    name: String,
+    stack_size: usize,
+    cpumask: AxCpuMask,
+}
+
+impl TaskBuilder {
+    /// Creates a builder with the default parameters.
+    ///
+    /// The default task name is an empty string, the default stack size is
+    /// [`axconfig::TASK_STACK_SIZE`], and the task is allowed to run on all
+    /// CPUs.
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            stack_size: axconfig::TASK_STACK_SIZE,
+            cpumask: AxCpuMask::full(),
+        }
+    }
+
+    /// Sets the name of the task.
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Sets the kernel stack size of the task.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// Sets the CPUs on which the task is allowed to run.
+    ///
+    /// An empty mask is ignored.
+    pub fn cpumask(mut self, cpumask: AxCpuMask) -> Self {
+        if !cpumask.is_empty() {
+            self.cpumask = cpumask;
+        }
+        self
+    }
+
+    /// Spawns a new task with the configured parameters and the given entry
+    /// function.
+    ///
+    /// Returns the task reference.
+    pub fn spawn<F>(self, f: F) -> AxTaskRef
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let task = TaskInner::new(f, self.name, self.stack_size);
+        task.set_cpumask(self.cpumask);
+        spawn_task(task)
+    }
+}
+
+impl Default for TaskBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -127,19 +344,35 @@ where
 ///
 /// Returns `true` if the priority is set successfully.
 ///
+/// If the current task holds priority-inheritance locks, its effective
+/// priority stays boosted until the locks are released.
+///
 /// [CFS]: https://en.wikipedia.org/wiki/Completely_Fair_Scheduler
 pub fn set_priority(prio: isize) -> bool {
-    RUN_QUEUE.lock().set_current_priority(prio)
+    crate::pi::set_current_priority(prio)
+}
+
+/// Set the CPU affinity for current task.
+///
+/// If the current CPU is not in the given mask, the current task will be
+/// migrated to one of the allowed CPUs immediately.
+///
+/// Returns `false` if the mask is empty.
+pub fn set_affinity(cpumask: AxCpuMask) -> bool {
+    current_run_queue().set_current_affinity(cpumask)
 }
 
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
-    RUN_QUEUE.lock().yield_current();
+    current_run_queue().yield_current();
 }
 
 /// Current task is going to sleep for the given duration.
 ///
+/// It is a cancellation point: it returns early if the current task is
+/// cancelled by [`TaskInner::cancel`], see [`sleep_interruptible`].
+///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep(dur: core::time::Duration) {
     sleep_until(axhal::time::wall_time() + dur);
@@ -147,27 +380,81 @@ pub fn sleep(dur: core::time::Duration) {
 
 /// Current task is going to sleep, it will be woken up at the given deadline.
 ///
+/// It is a cancellation point: it returns early if the current task is
+/// cancelled by [`TaskInner::cancel`], see [`sleep_until_interruptible`].
+///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
+    let _ = sleep_until_interruptible(deadline);
+}
+
+/// Like [`sleep`], but returns [`Cancelled`] if the current task is cancelled
+/// by [`TaskInner::cancel`] before or during the sleep.
+pub fn sleep_interruptible(dur: core::time::Duration) -> Result<(), Cancelled> {
+    sleep_until_interruptible(axhal::time::wall_time() + dur)
+}
+
+/// Like [`sleep_until`], but returns [`Cancelled`] if the current task is
+/// cancelled by [`TaskInner::cancel`] before or during the sleep.
+///
+/// If the feature `irq` is not enabled, it uses busy-wait instead, and checks
+/// the cancellation while waiting.
+pub fn sleep_until_interruptible(deadline: axhal::time::TimeValue) -> Result<(), Cancelled> {
+    #[cfg(feature = "lockdep")]
+    crate::lockdep::might_sleep();
+    let curr = current();
     #[cfg(feature = "irq")]
-    RUN_QUEUE.lock().sleep_until(deadline);
+    current_run_queue().sleep_until_interruptible(deadline);
     #[cfg(not(feature = "irq"))]
-    axhal::time::busy_wait_until(deadline);
+    while axhal::time::wall_time() < deadline && !curr.is_cancelled() {
+        core::hint::spin_loop();
+    }
+    if curr.is_cancelled() {
+        Err(Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// Completes the job of the current real-time task, and sleeps until its
+/// next period begins.
+///
+/// A job that is completed after its deadline is counted as a deadline miss.
+/// It returns immediately if the current task is not a real-time task.
+#[cfg(all(
+    feature = "sched_edf",
+    not(any(feature = "sched_det", feature = "sched_rr", feature = "sched_cfs"))
+))]
+pub fn wait_next_period() {
+    let curr = current();
+    if let Some(next_release) = curr.as_task_ref().complete_job() {
+        let now = axhal::time::monotonic_time_nanos();
+        if next_release > now {
+            sleep(core::time::Duration::from_nanos(next_release - now));
+        }
+    }
 }
 
 /// Exits the current task.
 pub fn exit(exit_code: i32) -> ! {
-    RUN_QUEUE.lock().exit_current(exit_code)
+    crate::task_local::destroy_current();
+    current_run_queue().exit_current(exit_code)
 }
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`]. If no task is
+/// ready, the periodic tick is stopped while waiting for IRQs.
 pub fn run_idle() -> ! {
     loop {
+        #[cfg(feature = "irq")]
+        let seq = crate::timers::ready_seq();
         yield_now();
-        debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
-        axhal::arch::wait_for_irqs();
+        if crate::timers::stop_tick(seq) {
+            debug!("idle task: waiting for IRQs...");
+            axhal::arch::wait_for_irqs();
+            crate::timers::on_idle_wakeup();
+        }
     }
 }
@@ -0,0 +1,103 @@
+//! CPU affinity masks.
+
+use core::fmt;
+
+const _: () = assert!(axconfig::SMP <= usize::BITS as usize);
+
+/// A set of CPUs on which a task is allowed to run.
+///
+/// Bit `i` represents the CPU with ID `i`. Only the first [`axconfig::SMP`]
+/// bits are meaningful.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct AxCpuMask(usize);
+
+impl AxCpuMask {
+    const VALID_BITS: usize = if axconfig::SMP == usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << axconfig::SMP) - 1
+    };
+
+    /// Creates an empty mask, with no CPUs set.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a mask with all CPUs set.
+    pub const fn full() -> Self {
+        Self(Self::VALID_BITS)
+    }
+
+    /// Creates a mask with only the given CPU set.
+    ///
+    /// Returns an empty mask if `cpu_id` is out of range.
+    pub const fn one_shot(cpu_id: usize) -> Self {
+        if cpu_id < axconfig::SMP {
+            Self(1 << cpu_id)
+        } else {
+            Self(0)
+        }
+    }
+
+    /// Creates a mask from raw bits, bits of non-existent CPUs are ignored.
+    pub const fn from_raw_bits(bits: usize) -> Self {
+        Self(bits & Self::VALID_BITS)
+    }
+
+    /// Returns the raw bits of the mask.
+    pub const fn as_raw_bits(&self) -> usize {
+        self.0
+    }
+
+    /// Returns `true` if no CPU is set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if the given CPU is set.
+    pub const fn get(&self, cpu_id: usize) -> bool {
+        cpu_id < axconfig::SMP && self.0 & (1 << cpu_id) != 0
+    }
+
+    /// Sets or clears the given CPU. Out of range CPUs are ignored.
+    pub fn set(&mut self, cpu_id: usize, value: bool) {
+        if cpu_id < axconfig::SMP {
+            if value {
+                self.0 |= 1 << cpu_id;
+            } else {
+                self.0 &= !(1 << cpu_id);
+            }
+        }
+    }
+
+    /// Returns the number of CPUs set.
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Returns the ID of the first CPU set, or [`None`] if the mask is empty.
+    pub const fn first_index(&self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize)
+        }
+    }
+
+    /// Returns an iterator over the IDs of all CPUs set.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..axconfig::SMP).filter(|&i| self.get(i))
+    }
+}
+
+impl Default for AxCpuMask {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+impl fmt::Debug for AxCpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,211 @@
+//! Fast user-space mutexes (futexes).
+//!
+//! A futex is a 32-bit word in the user memory, identified by a [`FutexKey`]
+//! of the address space and the user virtual address. Tasks wait on a futex
+//! only if the word still holds the expected value, and are woken up by
+//! others after changing it, so the kernel is entered only on contention.
+//!
+//! This module only maintains the wait queues of futexes. The syscall layer
+//! is responsible for validating the user address and reading the word, which
+//! [`user::UserFutexes`] does for user address spaces (`paging` feature).
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::{SpinNoIrq, WaitQueue};
+
+#[cfg(feature = "paging")]
+pub mod user;
+
+/// The bitset that matches any waiter.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Wait queues of all futexes that have waiters.
+static FUTEXES: SpinNoIrq<BTreeMap<FutexKey, VecDeque<Arc<Waiter>>>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+/// The identifier of a futex.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct FutexKey {
+    /// An identifier of the address space, e.g., the address of its
+    /// structure.
+    pub aspace: usize,
+    /// The user virtual address of the futex word.
+    pub addr: usize,
+}
+
+/// The error returned by futex operations.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FutexError {
+    /// The futex word does not hold the expected value.
+    WouldBlock,
+    /// The timeout expired before the task was woken up.
+    TimedOut,
+    /// The task was cancelled by [`TaskInner::cancel`] while waiting.
+    ///
+    /// [`TaskInner::cancel`]: crate::TaskInner::cancel
+    Interrupted,
+}
+
+struct Waiter {
+    bitset: u32,
+    /// The key of the futex that the waiter is queued on, which is changed
+    /// by [`futex_requeue`] with the futex table locked.
+    aspace: AtomicUsize,
+    addr: AtomicUsize,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl FutexKey {
+    /// Creates a new futex key.
+    pub const fn new(aspace: usize, addr: usize) -> Self {
+        Self { aspace, addr }
+    }
+}
+
+/// Removes and wakes up at most `n` waiters of the queue which match the
+/// bitset, and returns the number of waiters woken up.
+fn wake_waiters(queue: &mut VecDeque<Arc<Waiter>>, n: usize, bitset: u32) -> usize {
+    let mut count = 0;
+    queue.retain(|w| {
+        if count >= n || w.bitset & bitset == 0 {
+            return true;
+        }
+        w.woken.store(true, Ordering::Release);
+        w.wq.notify_one(true);
+        count += 1;
+        false
+    });
+    count
+}
+
+/// Blocks the current task on the futex until it is woken up by
+/// [`futex_wake`], or the timeout expires.
+///
+/// `matches` checks whether the futex word still holds the expected value.
+/// It is called with the futex table locked, so that no wake-ups between the
+/// check and blocking are lost. If it returns `false`, the task does not
+/// block and [`FutexError::WouldBlock`] is returned.
+///
+/// The waiter can only be woken up by wake-ups whose bitset intersects
+/// `bitset`, which must not be zero.
+///
+/// If the feature `irq` is not enabled, `timeout` is ignored.
+pub fn futex_wait<F>(
+    key: FutexKey,
+    matches: F,
+    bitset: u32,
+    timeout: Option<Duration>,
+) -> Result<(), FutexError>
+where
+    F: FnOnce() -> bool,
+{
+    let waiter = Arc::new(Waiter {
+        bitset,
+        aspace: AtomicUsize::new(key.aspace),
+        addr: AtomicUsize::new(key.addr),
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    {
+        let mut futexes = FUTEXES.lock();
+        if !matches() {
+            return Err(FutexError::WouldBlock);
+        }
+        futexes.entry(key).or_default().push_back(waiter.clone());
+    }
+
+    let woken = || waiter.woken.load(Ordering::Acquire);
+    let res = match timeout {
+        #[cfg(feature = "irq")]
+        Some(dur) => match waiter.wq.wait_timeout_until_interruptible(dur, woken) {
+            Ok(false) => Ok(()),
+            Ok(true) => Err(FutexError::TimedOut),
+            Err(_) => Err(FutexError::Interrupted),
+        },
+        _ => waiter
+            .wq
+            .wait_until_interruptible(woken)
+            .map_err(|_| FutexError::Interrupted),
+    };
+    if res.is_ok() {
+        return res;
+    }
+
+    // Dequeue the waiter, unless it has been woken up in the meantime.
+    let mut futexes = FUTEXES.lock();
+    if woken() {
+        return Ok(());
+    }
+    let key = FutexKey::new(
+        waiter.aspace.load(Ordering::Relaxed),
+        waiter.addr.load(Ordering::Relaxed),
+    );
+    if let Some(queue) = futexes.get_mut(&key) {
+        queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+        if queue.is_empty() {
+            futexes.remove(&key);
+        }
+    }
+    res
+}
+
+/// Wakes up at most `n` tasks waiting on the futex, whose bitsets intersect
+/// `bitset`.
+///
+/// Returns the number of tasks woken up.
+pub fn futex_wake(key: FutexKey, n: usize, bitset: u32) -> usize {
+    let mut futexes = FUTEXES.lock();
+    let Some(queue) = futexes.get_mut(&key) else {
+        return 0;
+    };
+    let count = wake_waiters(queue, n, bitset);
+    if queue.is_empty() {
+        futexes.remove(&key);
+    }
+    count
+}
+
+/// Wakes up at most `nr_wake` tasks waiting on the futex `key`, and moves at
+/// most `nr_requeue` of the remaining waiters to the futex `key2`.
+///
+/// If `matches` is given, it checks whether the futex word of `key` still
+/// holds the expected value with the futex table locked, and
+/// [`FutexError::WouldBlock`] is returned if it does not.
+///
+/// Returns the numbers of tasks woken up and requeued.
+pub fn futex_requeue<F>(
+    key: FutexKey,
+    key2: FutexKey,
+    nr_wake: usize,
+    nr_requeue: usize,
+    matches: Option<F>,
+) -> Result<(usize, usize), FutexError>
+where
+    F: FnOnce() -> bool,
+{
+    let mut futexes = FUTEXES.lock();
+    if matches.is_some_and(|f| !f()) {
+        return Err(FutexError::WouldBlock);
+    }
+    let Some(mut queue) = futexes.remove(&key) else {
+        return Ok((0, 0));
+    };
+    let woken = wake_waiters(&mut queue, nr_wake, FUTEX_BITSET_MATCH_ANY);
+    let nr_requeue = nr_requeue.min(queue.len());
+    if key != key2 && nr_requeue > 0 {
+        let queue2 = futexes.entry(key2).or_default();
+        for w in queue.drain(..nr_requeue) {
+            w.aspace.store(key2.aspace, Ordering::Relaxed);
+            w.addr.store(key2.addr, Ordering::Relaxed);
+            queue2.push_back(w);
+        }
+    }
+    if !queue.is_empty() {
+        futexes.insert(key, queue);
+    }
+    Ok((woken, nr_requeue))
+}
@@ -0,0 +1,243 @@
+//! The futex syscall on user address spaces.
+//!
+//! The futex words are accessed with the address space locked, so that the
+//! pages cannot be unmapped or swapped out in the meantime. For waits, the
+//! lock is released only after the word has been compared with the futex
+//! table locked.
+
+use core::ffi::c_long;
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{phys_to_virt, PhysAddr, VirtAddr};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K};
+
+use super::{FutexError, FutexKey, FUTEX_BITSET_MATCH_ANY};
+
+const FUTEX_WAIT: i32 = 0;
+const FUTEX_WAKE: i32 = 1;
+const FUTEX_REQUEUE: i32 = 3;
+const FUTEX_CMP_REQUEUE: i32 = 4;
+const FUTEX_WAIT_BITSET: i32 = 9;
+const FUTEX_WAKE_BITSET: i32 = 10;
+
+const FUTEX_PRIVATE_FLAG: i32 = 128;
+const FUTEX_CLOCK_REALTIME: i32 = 256;
+
+/// `struct timespec` in the user space.
+#[repr(C)]
+struct Timespec {
+    tv_sec: c_long,
+    tv_nsec: c_long,
+}
+
+impl From<FutexError> for LinuxError {
+    fn from(e: FutexError) -> Self {
+        match e {
+            FutexError::WouldBlock => LinuxError::EAGAIN,
+            FutexError::TimedOut => LinuxError::ETIMEDOUT,
+            FutexError::Interrupted => LinuxError::EINTR,
+        }
+    }
+}
+
+/// The futexes of a user address space.
+///
+/// The address space is locked by calling `lock`, which returns a guard,
+/// e.g., `|| aspace.lock()` for an `axsync::Mutex<AddrSpace>`.
+pub struct UserFutexes<L> {
+    aspace: usize,
+    lock: L,
+}
+
+/// Checks that the user memory is accessible for `access`, and populates
+/// the pages that are not yet (or are swapped out).
+fn check_user_range(
+    aspace: &mut AddrSpace,
+    start: VirtAddr,
+    size: usize,
+    access: MappingFlags,
+) -> LinuxResult {
+    if !aspace.contains_range(start, size) {
+        return Err(LinuxError::EFAULT);
+    }
+    let flags = MappingFlags::USER | access;
+    let mut vaddr = start.align_down_4k();
+    while vaddr < start + size {
+        let mapped = |aspace: &AddrSpace| match aspace.page_table().query(vaddr) {
+            Ok((_, f, _)) => f.contains(flags),
+            Err(_) => false,
+        };
+        if !mapped(aspace) && !(aspace.handle_page_fault(vaddr, access) && mapped(aspace)) {
+            return Err(LinuxError::EFAULT);
+        }
+        vaddr += PAGE_SIZE_4K;
+    }
+    Ok(())
+}
+
+/// Returns the physical address of the futex word at `uaddr`, after checking
+/// it for `access`.
+///
+/// The word stays valid as long as the address space is locked.
+fn word_paddr(aspace: &mut AddrSpace, uaddr: usize, access: MappingFlags) -> LinuxResult<PhysAddr> {
+    if uaddr % 4 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let vaddr = VirtAddr::from(uaddr);
+    check_user_range(aspace, vaddr, 4, access)?;
+    let (paddr, ..) = aspace
+        .page_table()
+        .query(vaddr)
+        .map_err(|_| LinuxError::EFAULT)?;
+    Ok(paddr)
+}
+
+/// Returns the futex word at the physical address.
+///
+/// # Safety
+///
+/// The word must be mapped in a locked address space, and the reference must
+/// not be used after it is unlocked.
+unsafe fn word<'a>(paddr: PhysAddr) -> &'a AtomicU32 {
+    &*phys_to_virt(paddr).as_ptr().cast::<AtomicU32>()
+}
+
+/// Reads the timeout of `FUTEX_WAIT` (relative) or `FUTEX_WAIT_BITSET`
+/// (absolute) from the user space.
+fn read_timeout(
+    aspace: &mut AddrSpace,
+    uts: usize,
+    op: i32,
+    flags: i32,
+) -> LinuxResult<Option<Duration>> {
+    if uts == 0 {
+        return Ok(None);
+    }
+    let size = core::mem::size_of::<Timespec>();
+    let start = VirtAddr::from(uts);
+    check_user_range(aspace, start, size, MappingFlags::READ)?;
+    let mut ts = Timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let buf = unsafe { core::slice::from_raw_parts_mut((&mut ts as *mut Timespec).cast(), size) };
+    aspace.read(start, buf).map_err(|_| LinuxError::EFAULT)?;
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let dur = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    if op == FUTEX_WAIT {
+        return Ok(Some(dur));
+    }
+    let now = if flags & FUTEX_CLOCK_REALTIME != 0 {
+        axhal::time::wall_time()
+    } else {
+        axhal::time::monotonic_time()
+    };
+    Ok(Some(dur.saturating_sub(now)))
+}
+
+impl<L, G> UserFutexes<L>
+where
+    L: Fn() -> G,
+    G: DerefMut<Target = AddrSpace>,
+{
+    /// Creates the futexes of the user address space identified by `aspace`
+    /// (e.g., the address of its structure), which is locked by `lock`.
+    pub const fn new(aspace: usize, lock: L) -> Self {
+        Self { aspace, lock }
+    }
+
+    fn key(&self, uaddr: usize) -> FutexKey {
+        FutexKey::new(self.aspace, uaddr)
+    }
+
+    /// The futex syscall, see `futex(2)`.
+    ///
+    /// `timeout` is the user address of a `struct timespec` for the wait
+    /// operations, or `val2` for the requeue operations. All futexes are
+    /// private to the address space.
+    pub fn futex(
+        &self,
+        uaddr: usize,
+        futex_op: i32,
+        val: u32,
+        timeout: usize,
+        uaddr2: usize,
+        val3: u32,
+    ) -> LinuxResult<isize> {
+        let flags = futex_op & (FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+        let op = futex_op & !flags;
+        let key = self.key(uaddr);
+        let mut aspace = (self.lock)();
+        let paddr = word_paddr(&mut aspace, uaddr, MappingFlags::READ)?;
+        match op {
+            FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+                let bitset = if op == FUTEX_WAIT {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let timeout = read_timeout(&mut aspace, timeout, op, flags)?;
+                // Unlock the address space after the comparison, which is
+                // done with the futex table locked.
+                let matches = move || {
+                    let matches = unsafe { word(paddr) }.load(Ordering::Acquire) == val;
+                    drop(aspace);
+                    matches
+                };
+                super::futex_wait(key, matches, bitset, timeout)?;
+                Ok(0)
+            }
+            FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+                drop(aspace);
+                let bitset = if op == FUTEX_WAKE {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                Ok(super::futex_wake(key, val as usize, bitset) as isize)
+            }
+            FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+                let nr_requeue = timeout;
+                word_paddr(&mut aspace, uaddr2, MappingFlags::READ)?;
+                let cmp = op == FUTEX_CMP_REQUEUE;
+                let matches = cmp.then_some(move || {
+                    let matches = unsafe { word(paddr) }.load(Ordering::Acquire) == val3;
+                    drop(aspace);
+                    matches
+                });
+                let (woken, requeued) =
+                    super::futex_requeue(key, self.key(uaddr2), val as usize, nr_requeue, matches)?;
+                // `FUTEX_REQUEUE` only returns the number of tasks woken up.
+                Ok(if cmp { woken + requeued } else { woken } as isize)
+            }
+            _ => Err(LinuxError::ENOSYS),
+        }
+    }
+
+    /// Clears the word at `clear_child_tid` of an exiting task, and wakes up
+    /// a task waiting on it, as `set_tid_address(2)` describes.
+    pub fn clear_child_tid(&self, clear_child_tid: usize) {
+        if clear_child_tid == 0 {
+            return;
+        }
+        let mut aspace = (self.lock)();
+        if let Ok(paddr) = word_paddr(&mut aspace, clear_child_tid, MappingFlags::WRITE) {
+            unsafe { word(paddr) }.store(0, Ordering::Release);
+            drop(aspace);
+            super::futex_wake(self.key(clear_child_tid), 1, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! A simple executor to run futures on tasks.
+//!
+//! [`block_on`] runs a future on the current task, which is blocked in a
+//! [`WaitQueue`] while the future is pending. [`spawn`] runs a future in the
+//! background on worker tasks, one per CPU, which are created on first use.
+//!
+//! Wakers either notify the wait queue or put the future back to the ready
+//! queue of the workers, so pending futures do not take any CPU time.
+
+use alloc::{boxed::Box, collections::VecDeque, format, sync::Arc, task::Wake};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use axhal::time::{wall_time, TimeValue};
+
+use crate::{SpinNoIrq, WaitQueue};
+
+/// Wakes up the task running [`block_on`].
+struct BlockOnWaker {
+    notified: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Wake for BlockOnWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::Release);
+        self.wq.notify_one(true);
+    }
+}
+
+/// Runs a future to completion on the current task.
+///
+/// The current task is blocked until the future is woken up, so it must not
+/// be called in the idle task or with IRQs disabled.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = core::pin::pin!(fut);
+    let inner = Arc::new(BlockOnWaker {
+        notified: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    let waker = Waker::from(inner.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        inner
+            .wq
+            .wait_until(|| inner.notified.swap(false, Ordering::Acquire));
+    }
+}
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+// States of an `AsyncTask`.
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+const RUN_AGAIN: u8 = 3;
+const DONE: u8 = 4;
+
+/// A future spawned by [`spawn`].
+///
+/// The state ensures that it is in the ready queue at most once, so that it
+/// is polled by only one worker at a time.
+struct AsyncTask {
+    future: SpinNoIrq<Option<BoxFuture>>,
+    state: AtomicU8,
+}
+
+impl AsyncTask {
+    fn run(self: Arc<Self>) {
+        self.state.store(RUNNING, Ordering::Release);
+        // Do not poll with the lock held, as IRQs are disabled.
+        let Some(mut fut) = self.future.lock().take() else {
+            return;
+        };
+        let waker = Waker::from(self.clone());
+        if fut
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready()
+        {
+            self.state.store(DONE, Ordering::Release);
+            return;
+        }
+        *self.future.lock() = Some(fut);
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // Woken up during the poll.
+            self.state.store(SCHEDULED, Ordering::Release);
+            EXECUTOR.push(self);
+        }
+    }
+}
+
+impl Wake for AsyncTask {
+    fn wake(self: Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let new_state = match state {
+                IDLE => SCHEDULED,
+                RUNNING => RUN_AGAIN,
+                _ => return,
+            };
+            match self.state.compare_exchange_weak(
+                state,
+                new_state,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) if state == IDLE => return EXECUTOR.push(self),
+                Ok(_) => return,
+                Err(s) => state = s,
+            }
+        }
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.clone().wake()
+    }
+}
+
+struct Executor {
+    ready: SpinNoIrq<VecDeque<Arc<AsyncTask>>>,
+    wq: WaitQueue,
+    started: AtomicBool,
+}
+
+static EXECUTOR: Executor = Executor {
+    ready: SpinNoIrq::new(VecDeque::new()),
+    wq: WaitQueue::new(),
+    started: AtomicBool::new(false),
+};
+
+impl Executor {
+    fn push(&self, task: Arc<AsyncTask>) {
+        self.ready.lock().push_back(task);
+        self.wq.notify_one(false);
+    }
+
+    fn start_workers(&'static self) {
+        if self.started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        for i in 0..axconfig::SMP {
+            let _worker = crate::spawn_raw(
+                move || self.run_worker(),
+                format!("async-worker-{}", i),
+                axconfig::TASK_STACK_SIZE,
+            );
+            #[cfg(feature = "watchdog")]
+            _worker.disable_hung_check();
+        }
+    }
+
+    fn run_worker(&self) {
+        loop {
+            self.wq.wait_until(|| !self.ready.lock().is_empty());
+            let task = self.ready.lock().pop_front();
+            if let Some(task) = task {
+                task.run();
+            }
+        }
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// A handle to wait for a future spawned by [`spawn`].
+///
+/// It is a future that resolves to the output of the spawned future.
+/// Dropping the handle detaches the spawned future, which keeps running.
+pub struct JoinHandle<T> {
+    state: Arc<SpinNoIrq<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns `true` if the spawned future has completed.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().output.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock();
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+        let old = state.waker.replace(cx.waker().clone());
+        drop(state);
+        drop(old);
+        Poll::Pending
+    }
+}
+
+/// Spawns a future to run in the background on the worker tasks.
+///
+/// Returns a [`JoinHandle`] to await its output.
+pub fn spawn<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(SpinNoIrq::new(JoinState {
+        output: None,
+        waker: None,
+    }));
+    let join_state = state.clone();
+    let fut = async move {
+        let output = fut.await;
+        let waker = {
+            let mut state = join_state.lock();
+            state.output = Some(output);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    };
+    EXECUTOR.start_workers();
+    EXECUTOR.push(Arc::new(AsyncTask {
+        future: SpinNoIrq::new(Some(Box::pin(fut))),
+        state: AtomicU8::new(SCHEDULED),
+    }));
+    JoinHandle { state }
+}
+
+/// A future that completes at the given deadline, see [`sleep_until`].
+pub struct Sleep {
+    deadline: TimeValue,
+    #[cfg(feature = "irq")]
+    waker: Option<Waker>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if wall_time() >= this.deadline {
+            return Poll::Ready(());
+        }
+        // Without timers, poll again as soon as possible.
+        #[cfg(not(feature = "irq"))]
+        cx.waker().wake_by_ref();
+        // Set a new alarm only if it is polled by a different waker.
+        #[cfg(feature = "irq")]
+        if !this.waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+            this.waker = Some(cx.waker().clone());
+            crate::timers::set_alarm_waker(this.deadline, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Returns a future that completes after the given duration.
+///
+/// If the feature `irq` is not enabled, the future is polled repeatedly
+/// until the deadline.
+pub fn sleep(dur: Duration) -> Sleep {
+    sleep_until(wall_time() + dur)
+}
+
+/// Returns a future that completes at the given deadline.
+///
+/// The alarm is not cancelled if the future is dropped early, which only
+/// causes a spurious wakeup of its task.
+pub fn sleep_until(deadline: TimeValue) -> Sleep {
+    Sleep {
+        deadline,
+        #[cfg(feature = "irq")]
+        waker: None,
+    }
+}
+
+/// Returns a future that yields to other futures once.
+pub async fn yield_now() {
+    let mut yielded = false;
+    core::future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
@@ -12,7 +12,25 @@
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
 //!    [`WaitQueue::wait_timeout`].
+//! - `smp`: Enable SMP (symmetric multiprocessing) support. Each CPU has its
+//!   own run queue, and an idle CPU steals ready tasks from other CPUs.
 //! - `preempt`: Enable preemptive scheduling.
+//! - `paging`: Map task stacks with guard pages in the kernel address space,
+//!   so that stack overflows are caught by page faults. Otherwise, stack
+//!   overflows are detected by checking a canary on context switches. Also
+//!   enables the futex syscall on user address spaces ([`futex::user`]).
+//! - `async`: Enable the [`future`] module to run futures on tasks, with
+//!   wakers backed by wait queues and timers.
+//! - `watchdog`: Detect soft lockups (a CPU not rescheduling for a while) on
+//!   timer ticks, and hung tasks (tasks blocked for too long) by a kernel
+//!   task, see [`WatchdogConfig`]. It also enables the `irq` feature.
+//!   Backtraces are walked by frame pointers, so the kernel should be built
+//!   with `-C force-frame-pointers=yes` (done by the Makefile).
+//! - `task_group`: Enable [task groups](task_group) with CPU bandwidth
+//!   quotas. It also enables the `irq` feature.
+//! - `lockdep`: Enable the [lock dependency validator](lockdep), which
+//!   reports potential deadlocks caused by inconsistent lock orders, and
+//!   blocking in atomic context.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default,
 //!   and it can be overriden by other scheduler features.
@@ -20,6 +38,14 @@
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
 //!   the `multitask` and `preempt` features if it is enabled.
+//! - `sched_edf`: Use the earliest deadline first (EDF) real-time scheduler,
+//!   see [`spawn_rt`] and [`wait_next_period`]. It also enables the
+//!   `multitask` and `preempt` features if it is enabled. It is overriden by
+//!   `sched_rr` and `sched_cfs`, and the real-time APIs are only available
+//!   when it is the selected scheduler.
+//! - `sched_det`: Use the deterministic scheduler for tests, see
+//!   [`sched_det`]. It overrides `sched_fifo`, cannot be combined with other
+//!   scheduler features, and also enables the `multitask` feature.
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
@@ -33,6 +59,14 @@
 #![feature(const_ptr_is_null)]
 #![feature(const_unsafecell_get_mut)]
 
+#[cfg(all(
+    feature = "sched_det",
+    any(feature = "sched_rr", feature = "sched_cfs", feature = "sched_edf")
+))]
+compile_error!(
+    "the `sched_det` feature cannot be combined with `sched_rr`, `sched_cfs` or `sched_edf`"
+);
+
 #[cfg(test)]
 mod tests;
 
@@ -42,14 +76,36 @@ cfg_if::cfg_if! {
         extern crate log;
         extern crate alloc;
 
+        mod cpumask;
+        mod pi;
         mod run_queue;
+        mod stack;
         mod task;
         mod task_ext;
+        mod task_local;
         mod api;
         mod wait_queue;
+        pub mod futex;
 
         #[cfg(feature = "irq")]
         mod timers;
+        #[cfg(feature = "async")]
+        pub mod future;
+        #[cfg(feature = "watchdog")]
+        mod watchdog;
+        #[cfg(feature = "task_group")]
+        pub mod task_group;
+        #[cfg(all(feature = "sched_edf", not(any(feature = "sched_det", feature = "sched_rr", feature = "sched_cfs"))))]
+        mod sched_edf;
+        #[cfg(feature = "sched_det")]
+        pub mod sched_det;
+        #[cfg(feature = "lockdep")]
+        pub mod lockdep;
+
+        #[cfg(feature = "lockdep")]
+        use self::lockdep::SpinNoIrq;
+        #[cfg(not(feature = "lockdep"))]
+        use kspin::SpinNoIrq;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
@@ -0,0 +1,506 @@
+//! Lock dependency validator.
+//!
+//! Every tracked lock belongs to a [`LockClass`], which is identified by the
+//! place where the lock is created, so all locks created at the same place
+//! (e.g., the locks of all tasks) share a class. Each task records the locks
+//! it holds. When a task acquires a lock while holding others, the order is
+//! recorded as edges from the classes held to the class acquired.
+//!
+//! An edge that closes a cycle means that some locks are acquired in
+//! different orders by different code paths (e.g., `A -> B` and `B -> A`),
+//! which may deadlock even if it has not happened yet. Acquiring a lock that
+//! the task already holds (a self-deadlock) is also reported. The first
+//! violation is reported along with the locks held and a backtrace, and then
+//! the validator turns itself off.
+//!
+//! It also reports blocking while holding a spinlock or with IRQs disabled
+//! (see [`might_sleep`]).
+//!
+//! The locks of [`axsync`] (i.e., `Mutex`, `RwLock` and the spinlocks in
+//! `axsync::spin`, used by `axnet` and `axfs` as well) and the [`SpinNoIrq`]
+//! locks in this crate are tracked. Other locks can be tracked by calling
+//! [`lock_acquire`] and [`lock_release`] with their classes.
+//!
+//! [`axsync`]: https://arceos-org.github.io/arceos/axsync/index.html
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use kspin::SpinRaw;
+
+/// The maximum number of locks a task can hold at the same time.
+const MAX_HELD_LOCKS: usize = 32;
+
+/// Whether the validator is on. It turns off after the first report.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+/// Whether blocking in atomic context has been reported.
+static SLEEP_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// The dependency graph, always locked with IRQs disabled.
+static GRAPH: SpinRaw<Graph> = SpinRaw::new(Graph::new());
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LockKind {
+    /// A spinlock, with which the holder must not block.
+    Spin,
+    /// A sleeping lock, such as a mutex.
+    Sleep,
+}
+
+/// The class of a lock, identified by the place where the lock is created.
+///
+/// An all-zero `LockClass` is valid, which is the class of all locks
+/// initialized with zeros instead of [`LockClass::new`] (e.g., by
+/// `PTHREAD_MUTEX_INITIALIZER` in C).
+pub struct LockClass {
+    loc: Option<&'static Location<'static>>,
+    /// The index in the graph plus one, or 0 if not registered yet.
+    id: AtomicUsize,
+}
+
+impl LockClass {
+    /// Creates the class of a lock created by the caller.
+    #[track_caller]
+    pub const fn new() -> Self {
+        Self {
+            loc: Some(Location::caller()),
+            id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the location where locks of this class are created, or
+    /// `None` if they are initialized with zeros.
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.loc
+    }
+
+    /// The address of the lock instance this class is embedded in.
+    fn addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Returns the index in the graph, registering the class if needed.
+    ///
+    /// It must be called with the graph locked.
+    fn index(&self, graph: &mut Graph) -> usize {
+        match self.id.load(Ordering::Acquire) {
+            0 => {
+                let index = graph.register(self.loc);
+                self.id.store(index + 1, Ordering::Release);
+                index
+            }
+            id => id - 1,
+        }
+    }
+}
+
+impl Default for LockClass {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LockClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LockClass({})", Site(self.loc))
+    }
+}
+
+/// The place where the locks of a class are created, for display.
+#[derive(Clone, Copy)]
+struct Site(Option<&'static Location<'static>>);
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(loc) => write!(f, "{}", loc),
+            None => write!(f, "<zero-initialized>"),
+        }
+    }
+}
+
+struct Graph {
+    /// Indices of classes by their locations.
+    indices: BTreeMap<Option<(&'static str, u32, u32)>, usize>,
+    classes: Vec<Site>,
+    /// The classes acquired while holding each class.
+    edges: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    const fn new() -> Self {
+        Self {
+            indices: BTreeMap::new(),
+            classes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    fn register(&mut self, loc: Option<&'static Location<'static>>) -> usize {
+        let key = loc.map(|loc| (loc.file(), loc.line(), loc.column()));
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        let index = self.classes.len();
+        self.indices.insert(key, index);
+        self.classes.push(Site(loc));
+        self.edges.push(Vec::new());
+        index
+    }
+
+    /// Returns a path from `from` to `to` (both inclusive), if any.
+    fn find_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut parent = vec![usize::MAX; self.classes.len()];
+        let mut stack = vec![from];
+        parent[from] = from;
+        while let Some(node) = stack.pop() {
+            if node == to {
+                let mut path = vec![to];
+                let mut node = to;
+                while node != from {
+                    node = parent[node];
+                    path.push(node);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &next in &self.edges[node] {
+                if parent[next] == usize::MAX {
+                    parent[next] = node;
+                    stack.push(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds an edge `from -> to`.
+    ///
+    /// If it closes a cycle, the edge is not added, and the path from `to`
+    /// back to `from` is returned.
+    fn add_edge(&mut self, from: usize, to: usize) -> Result<(), Vec<usize>> {
+        if self.edges[from].contains(&to) {
+            return Ok(());
+        }
+        if let Some(path) = self.find_path(to, from) {
+            return Err(path);
+        }
+        self.edges[from].push(to);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HeldLock {
+    index: usize,
+    /// The address of the lock, see [`LockClass::addr`].
+    addr: usize,
+    kind: LockKind,
+}
+
+/// The locks held by a task, in the order of acquisition.
+pub(crate) struct HeldLocks {
+    locks: [HeldLock; MAX_HELD_LOCKS],
+    depth: usize,
+}
+
+impl HeldLocks {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locks: [HeldLock {
+                index: 0,
+                addr: 0,
+                kind: LockKind::Spin,
+            }; MAX_HELD_LOCKS],
+            depth: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &HeldLock> {
+        self.locks[..self.depth].iter()
+    }
+
+    /// Removes the lock at `addr`, which may not be the last one acquired.
+    fn remove(&mut self, addr: usize) {
+        if let Some(pos) = self.iter().rposition(|h| h.addr == addr) {
+            self.locks.copy_within(pos + 1..self.depth, pos);
+            self.depth -= 1;
+        }
+    }
+}
+
+/// Turns off the validator, and returns `true` if it was on.
+fn turn_off() -> bool {
+    ENABLED.swap(false, Ordering::AcqRel)
+}
+
+/// Returns `true` if the validator is on, i.e., no lock order violation has
+/// been reported.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Turns the validator back on after a report, and forgets the locks held by
+/// the current task, which are not tracked while it is off.
+#[cfg(test)]
+pub(crate) fn reset() {
+    let _guard = kernel_guard::IrqSave::new();
+    if let Some(curr) = crate::current_may_uninit() {
+        unsafe { curr.held_locks() }.depth = 0;
+    }
+    SLEEP_REPORTED.store(false, Ordering::Release);
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Records that the current task is acquiring a lock of the class.
+///
+/// It must be called before spinning or blocking for the lock, so that a
+/// lock order inversion is reported even if it deadlocks. A successful
+/// `try_lock` is recorded with `trylock` set, which cannot deadlock and
+/// thus adds no dependency.
+pub fn lock_acquire(class: &LockClass, kind: LockKind, trylock: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let _guard = kernel_guard::IrqSave::new();
+    let Some(curr) = crate::current_may_uninit() else {
+        return;
+    };
+    // SAFETY: IRQs are disabled, so only the current task accesses it.
+    let held = unsafe { curr.held_locks() };
+    let mut graph = GRAPH.lock();
+    let index = class.index(&mut graph);
+    if !trylock {
+        if held.iter().any(|h| h.addr == class.addr()) {
+            drop(graph);
+            if turn_off() {
+                report_recursive(&curr, class.loc);
+            }
+            return;
+        }
+        for h in held.iter() {
+            if h.index == index {
+                // Locks of the same class may be nested, e.g., the locks of
+                // two tasks. They are not validated.
+                continue;
+            }
+            if let Err(path) = graph.add_edge(h.index, index) {
+                let held_classes: Vec<_> = held.iter().map(|h| graph.classes[h.index]).collect();
+                let chain: Vec<_> = path.iter().map(|&i| graph.classes[i]).collect();
+                drop(graph);
+                if turn_off() {
+                    report_cycle(&curr, Site(class.loc), &held_classes, &chain);
+                }
+                return;
+            }
+        }
+    }
+    if held.depth == MAX_HELD_LOCKS {
+        drop(graph);
+        if turn_off() {
+            error!(
+                "lockdep: task {} holds too many locks, turning off the validator",
+                curr.id_name()
+            );
+        }
+        return;
+    }
+    held.locks[held.depth] = HeldLock {
+        index,
+        addr: class.addr(),
+        kind,
+    };
+    held.depth += 1;
+}
+
+/// Records that the current task has released a lock of the class.
+pub fn lock_release(class: &LockClass) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if class.id.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let _guard = kernel_guard::IrqSave::new();
+    if let Some(curr) = crate::current_may_uninit() {
+        // SAFETY: IRQs are disabled, so only the current task accesses it.
+        unsafe { curr.held_locks() }.remove(class.addr());
+    }
+}
+
+/// Reports if the current task is about to block while holding a spinlock,
+/// or with IRQs disabled.
+///
+/// It is called by the blocking functions of this crate. Only the first
+/// violation is reported.
+pub fn might_sleep() {
+    if !ENABLED.load(Ordering::Relaxed) || SLEEP_REPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+    #[cfg(feature = "irq")]
+    let irqs_disabled = !axhal::arch::irqs_enabled();
+    #[cfg(not(feature = "irq"))]
+    let irqs_disabled = false;
+
+    let _guard = kernel_guard::IrqSave::new();
+    let Some(curr) = crate::current_may_uninit() else {
+        return;
+    };
+    // SAFETY: IRQs are disabled, so only the current task accesses it.
+    let held = unsafe { curr.held_locks() };
+    let spin = held
+        .iter()
+        .rev()
+        .find(|h| h.kind == LockKind::Spin)
+        .copied();
+    if !irqs_disabled && spin.is_none() {
+        return;
+    }
+    if SLEEP_REPORTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    error!(
+        "lockdep: task {} may block in atomic context",
+        curr.id_name()
+    );
+    if irqs_disabled {
+        error!("lockdep:   with IRQs disabled");
+    }
+    if let Some(spin) = spin {
+        let loc = GRAPH.lock().classes[spin.index];
+        error!("lockdep:   while holding the spinlock created at {}", loc);
+    }
+    error!("lockdep: backtrace:{}", curr.backtrace());
+}
+
+fn report_recursive(curr: &crate::CurrentTask, acquiring: Option<&'static Location<'static>>) {
+    error!("lockdep: possible recursive locking detected");
+    error!(
+        "lockdep: task {} is acquiring the lock created at {}, which it already holds",
+        curr.id_name(),
+        Site(acquiring)
+    );
+    error!("lockdep: backtrace:{}", curr.backtrace());
+    error!("lockdep: turning off the validator");
+}
+
+fn report_cycle(curr: &crate::CurrentTask, acquiring: Site, held: &[Site], chain: &[Site]) {
+    error!("lockdep: possible circular locking dependency detected");
+    error!(
+        "lockdep: task {} is acquiring the lock created at {}",
+        curr.id_name(),
+        acquiring
+    );
+    error!("lockdep: while holding the locks created at:");
+    for loc in held {
+        error!("lockdep:   {}", loc);
+    }
+    error!("lockdep: but the reverse order has been seen:");
+    for (i, loc) in chain.iter().enumerate() {
+        let arrow = if i == 0 { "" } else { "-> " };
+        error!("lockdep:   {}{}", arrow, loc);
+    }
+    error!("lockdep: backtrace:{}", curr.backtrace());
+    error!("lockdep: turning off the validator");
+}
+
+/// A [`kspin::SpinNoIrq`] tracked by the validator.
+pub struct SpinNoIrq<T: ?Sized> {
+    class: LockClass,
+    inner: kspin::SpinNoIrq<T>,
+}
+
+/// A guard of [`SpinNoIrq`].
+pub struct SpinNoIrqGuard<'a, T: ?Sized + 'a> {
+    class: &'a LockClass,
+    inner: kspin::SpinNoIrqGuard<'a, T>,
+}
+
+impl<T> SpinNoIrq<T> {
+    /// Creates a new [`SpinNoIrq`] wrapping the supplied data.
+    #[inline(always)]
+    #[track_caller]
+    pub const fn new(data: T) -> Self {
+        Self {
+            class: LockClass::new(),
+            inner: kspin::SpinNoIrq::new(data),
+        }
+    }
+
+    /// Consumes this [`SpinNoIrq`] and unwraps the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinNoIrq<T> {
+    /// Locks the [`SpinNoIrq`] and returns a guard that permits access to the
+    /// inner data.
+    #[inline(always)]
+    pub fn lock(&self) -> SpinNoIrqGuard<T> {
+        lock_acquire(&self.class, LockKind::Spin, false);
+        SpinNoIrqGuard {
+            class: &self.class,
+            inner: self.inner.lock(),
+        }
+    }
+
+    /// Try to lock this [`SpinNoIrq`], returning a lock guard if successful.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<SpinNoIrqGuard<T>> {
+        let inner = self.inner.try_lock()?;
+        lock_acquire(&self.class, LockKind::Spin, true);
+        Some(SpinNoIrqGuard {
+            class: &self.class,
+            inner,
+        })
+    }
+
+    /// Returns `true` if the lock is currently held.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: Default> Default for SpinNoIrq<T> {
+    #[inline(always)]
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: ?Sized> Deref for SpinNoIrqGuard<'_, T> {
+    type Target = T;
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinNoIrqGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for SpinNoIrqGuard<'_, T> {
+    fn drop(&mut self) {
+        // The lock itself is released after this, when `inner` is dropped.
+        lock_release(self.class);
+    }
+}
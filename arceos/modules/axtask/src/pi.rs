@@ -0,0 +1,125 @@
+//! Priority inheritance for sleeping locks.
+//!
+//! When a task blocks on a priority-inheritance lock, it donates its effective
+//! priority to the owner of the lock. If the owner is itself blocked on
+//! another such lock, the donation is propagated along the chain of owners.
+//! The donation is withdrawn when the owner releases the lock.
+//!
+//! A numerically lower priority value means a higher priority, which is the
+//! convention of the nice values used by the CFS scheduler. For schedulers
+//! without priorities (FIFO and RR), the effective priority is recorded but
+//! has no effect on scheduling.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::run_queue::set_task_priority;
+use crate::task::find_task;
+use crate::{current_run_queue, AxTaskRef, SpinNoIrq};
+
+/// Protects the priority inheritance states of all tasks.
+pub(crate) static PI_LOCK: SpinNoIrq<()> = SpinNoIrq::new(());
+
+/// The maximum length of a lock chain to propagate priorities through, which
+/// also prevents looping forever on a deadlock cycle.
+const MAX_PI_CHAIN_DEPTH: usize = 16;
+
+/// Recomputes the effective priority of `task` from its base priority and
+/// all donations it received, and applies it to the scheduler.
+///
+/// Returns the new effective priority.
+fn update_priority(task: &AxTaskRef) -> isize {
+    let state = unsafe { task.pi_state() };
+    let prio = state
+        .donations
+        .iter()
+        .map(|&(_, prio)| prio)
+        .fold(task.base_priority(), isize::min);
+    if prio != task.priority() {
+        debug!(
+            "task {} priority: {} -> {}",
+            task.id_name(),
+            task.priority(),
+            prio
+        );
+        task.set_effective_priority(prio);
+        set_task_priority(task, prio);
+    }
+    prio
+}
+
+/// Records that `owner` receives priority `prio` from a waiter of the lock
+/// `lock_id`, and propagates it along the lock chain.
+fn donate(mut owner: AxTaskRef, mut lock_id: usize, mut prio: isize) {
+    for _ in 0..MAX_PI_CHAIN_DEPTH {
+        let state = unsafe { owner.pi_state() };
+        match state.donations.iter_mut().find(|(id, _)| *id == lock_id) {
+            Some((_, p)) if *p <= prio => return,
+            Some((_, p)) => *p = prio,
+            None => state.donations.push((lock_id, prio)),
+        }
+        prio = update_priority(&owner);
+
+        // The owner is blocked on another lock, boost that lock's owner too.
+        let Some((next_lock, next_owner_id)) = state.blocked_on else {
+            return;
+        };
+        let Some(next_owner) = find_task(next_owner_id) else {
+            return;
+        };
+        owner = next_owner;
+        lock_id = next_lock;
+    }
+    warn!("priority inheritance: lock chain is too long or has a cycle");
+}
+
+/// Called by the current task before it blocks on the priority-inheritance
+/// lock `lock_id`, whose owner ID is stored in `owner`.
+///
+/// `owner_id` is the owner ID observed by the caller. Nothing is donated if
+/// the lock has been released or acquired by another task since then.
+pub fn pi_block_on(lock_id: usize, owner: &AtomicU64, owner_id: u64) {
+    let _guard = PI_LOCK.lock();
+    if owner.load(Ordering::Acquire) != owner_id {
+        return;
+    }
+    let Some(owner_task) = find_task(owner_id) else {
+        return;
+    };
+    let curr = crate::current();
+    unsafe { curr.pi_state() }.blocked_on = Some((lock_id, owner_id));
+    donate(owner_task, lock_id, curr.priority());
+}
+
+/// Called by the current task after it has acquired a priority-inheritance
+/// lock it was blocked on.
+pub fn pi_unblock() {
+    let _guard = PI_LOCK.lock();
+    unsafe { crate::current().pi_state() }.blocked_on = None;
+}
+
+/// Called by the current task after it has released the priority-inheritance
+/// lock `lock_id`. The priority donated through this lock is withdrawn.
+pub fn pi_release(lock_id: usize) {
+    let _guard = PI_LOCK.lock();
+    let curr = crate::current();
+    let state = unsafe { curr.pi_state() };
+    if state.donations.iter().any(|&(id, _)| id == lock_id) {
+        state.donations.retain(|&(id, _)| id != lock_id);
+        update_priority(curr.as_task_ref());
+    }
+}
+
+/// Sets the base priority of the current task, the effective priority is
+/// kept boosted if it holds priority-inheritance locks.
+pub(crate) fn set_current_priority(prio: isize) -> bool {
+    let _guard = PI_LOCK.lock();
+    let curr = crate::current();
+    // Let the scheduler check whether the priority is valid.
+    if !current_run_queue().set_task_priority(curr.as_task_ref(), prio) {
+        return false;
+    }
+    curr.set_base_priority(prio);
+    curr.set_effective_priority(prio);
+    update_priority(curr.as_task_ref());
+    true
+}
@@ -1,45 +1,218 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
-use kspin::SpinNoIrq;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use kernel_guard::NoPreemptIrqSave;
+use kspin::{SpinRaw, SpinRawGuard};
 use lazyinit::LazyInit;
 use scheduler::BaseScheduler;
 
 use crate::task::{CurrentTask, TaskState};
-use crate::{AxTaskRef, Scheduler, TaskInner, WaitQueue};
+use crate::{AxCpuMask, AxTaskRef, Scheduler, SpinNoIrq, TaskInner, WaitQueue};
+
+/// The run queue of each CPU.
+#[percpu::def_percpu]
+static RUN_QUEUE: LazyInit<SpinRaw<AxRunQueue>> = LazyInit::new();
+
+/// References to the run queues of all CPUs, used to steal tasks from other
+/// CPUs.
+static RUN_QUEUES: [LazyInit<&'static SpinRaw<AxRunQueue>>; axconfig::SMP] =
+    [const { LazyInit::new() }; axconfig::SMP];
 
-// TODO: per-CPU
-pub(crate) static RUN_QUEUE: LazyInit<SpinNoIrq<AxRunQueue>> = LazyInit::new();
+/// Tasks that are made ready by other CPUs but are not allowed to run there,
+/// waiting to be moved into the run queue of the target CPU.
+///
+/// They are not put into the target run queue directly, to avoid holding the
+/// run queue locks of two CPUs at the same time.
+static PENDING_TASKS: [SpinNoIrq<VecDeque<AxTaskRef>>; axconfig::SMP] =
+    [const { SpinNoIrq::new(VecDeque::new()) }; axconfig::SMP];
 
-// TODO: per-CPU
-static EXITED_TASKS: SpinNoIrq<VecDeque<AxTaskRef>> = SpinNoIrq::new(VecDeque::new());
+/// Tasks whose priorities are changed while the run queues of their CPUs
+/// are busy, waiting for the CPUs to apply the new priorities.
+static PENDING_PRIORITIES: [SpinNoIrq<VecDeque<AxTaskRef>>; axconfig::SMP] =
+    [const { SpinNoIrq::new(VecDeque::new()) }; axconfig::SMP];
 
+/// Exited tasks of each CPU, waiting to be recycled by its `gc` task.
+static EXITED_TASKS: [SpinNoIrq<VecDeque<AxTaskRef>>; axconfig::SMP] =
+    [const { SpinNoIrq::new(VecDeque::new()) }; axconfig::SMP];
+
+#[percpu::def_percpu]
 static WAIT_FOR_EXIT: WaitQueue = WaitQueue::new();
 
 #[percpu::def_percpu]
 static IDLE_TASK: LazyInit<AxTaskRef> = LazyInit::new();
 
+/// The task that has just been switched out on this CPU. It is still marked
+/// as `on_cpu` until the context switch is completed.
+#[percpu::def_percpu]
+static PREV_TASK: usize = 0;
+
 pub(crate) struct AxRunQueue {
+    cpu_id: usize,
     scheduler: Scheduler,
 }
 
+/// A locked reference to the run queue of the current CPU.
+///
+/// IRQs and preemption are disabled while it is alive. The task holding it
+/// may be switched out and resumed later on another CPU, so the lock that is
+/// released on drop is the one of the CPU the task is running on at that time.
+pub(crate) struct CurrentRunQueueRef {
+    inner: ManuallyDrop<SpinRawGuard<'static, AxRunQueue>>,
+    _guard: NoPreemptIrqSave,
+}
+
+impl Deref for CurrentRunQueueRef {
+    type Target = AxRunQueue;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for CurrentRunQueueRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl Drop for CurrentRunQueueRef {
+    fn drop(&mut self) {
+        // Do not drop `self.inner`, it may refer to the run queue of another
+        // CPU if the task has been migrated during a reschedule.
+        unsafe { this_run_queue().force_unlock() };
+    }
+}
+
+/// Locks the run queue of the current CPU.
+pub(crate) fn current_run_queue() -> CurrentRunQueueRef {
+    let guard = NoPreemptIrqSave::new();
+    CurrentRunQueueRef {
+        inner: ManuallyDrop::new(this_run_queue().lock()),
+        _guard: guard,
+    }
+}
+
+/// Changes the priority of `task` in the run queue of the CPU it belongs to,
+/// which is usually not the current CPU for the owners of contended locks.
+///
+/// It is called with `PI_LOCK` held, so the run queue is only locked with
+/// `try_lock`, as its owner may be waiting for `PI_LOCK` with it locked. If
+/// the run queue is busy, or the task has been migrated to another CPU
+/// meanwhile, the change is deferred to the CPU of the task, which applies
+/// the priority recorded in the task on its next reschedule.
+pub(crate) fn set_task_priority(task: &AxTaskRef, prio: isize) -> bool {
+    let _guard = NoPreemptIrqSave::new();
+    let cpu_id = task.cpu_id();
+    if let Some(mut rq) = RUN_QUEUES[cpu_id].try_lock() {
+        if task.cpu_id() == cpu_id {
+            return rq.set_task_priority(task, prio);
+        }
+    }
+    defer_priority(task.clone());
+    true
+}
+
+/// Lets the CPU of `task` apply its priority, see [`set_task_priority`].
+fn defer_priority(task: AxTaskRef) {
+    let cpu_id = task.cpu_id();
+    debug!(
+        "task {} priority is deferred to CPU {}",
+        task.id_name(),
+        cpu_id
+    );
+    PENDING_PRIORITIES[cpu_id].lock().push_back(task);
+    #[cfg(all(feature = "irq", feature = "smp"))]
+    if cpu_id != axhal::cpu::this_cpu_id() {
+        axhal::irq::send_ipi(cpu_id);
+    }
+}
+
+/// Returns the monotonic time when the earliest throttled real-time task of
+/// the current CPU is released, or [`None`] if there is no such task.
+#[cfg(feature = "irq")]
+pub(crate) fn next_rt_release() -> Option<u64> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(feature = "sched_det", feature = "sched_rr", feature = "sched_cfs"))] {
+            None
+        } else if #[cfg(feature = "sched_edf")] {
+            current_run_queue().scheduler.next_release()
+        } else {
+            None
+        }
+    }
+}
+
+/// Releases the run queue lock of the current CPU that was implicitly held
+/// across the context switch, and marks the previous task as off-CPU.
+///
+/// # Safety
+///
+/// It must be called only once by a newly started task right after it is
+/// switched to.
+pub(crate) unsafe fn finish_first_switch() {
+    finish_task_switch();
+    this_run_queue().force_unlock();
+}
+
+fn this_run_queue() -> &'static SpinRaw<AxRunQueue> {
+    // Safety: IRQs or preemption must be disabled by the caller.
+    unsafe { RUN_QUEUE.current_ref_raw() }
+}
+
+/// Marks the task switched out on this CPU as no longer running, so that
+/// other CPUs can switch to it.
+fn finish_task_switch() {
+    let ptr = unsafe { PREV_TASK.read_current_raw() } as *const crate::AxTask;
+    if !ptr.is_null() {
+        unsafe { PREV_TASK.write_current_raw(0) };
+        let prev = unsafe { AxTaskRef::from_raw(ptr) };
+        prev.set_on_cpu(false);
+    }
+}
+
 impl AxRunQueue {
-    pub fn new() -> SpinNoIrq<Self> {
-        let gc_task = TaskInner::new(gc_entry, "gc".into(), axconfig::TASK_STACK_SIZE).into_arc();
+    pub fn new(cpu_id: usize) -> SpinRaw<Self> {
+        let exited_tasks = &EXITED_TASKS[cpu_id];
+        let wait_for_exit = unsafe { WAIT_FOR_EXIT.current_ref_raw() };
+        let gc_task = TaskInner::new(
+            move || gc_entry(exited_tasks, wait_for_exit),
+            "gc".into(),
+            axconfig::TASK_STACK_SIZE,
+        )
+        .into_arc();
+        gc_task.set_cpumask(AxCpuMask::one_shot(cpu_id));
+        #[cfg(feature = "watchdog")]
+        gc_task.disable_hung_check();
         let mut scheduler = Scheduler::new();
         scheduler.add_task(gc_task);
-        SpinNoIrq::new(Self { scheduler })
+        SpinRaw::new(Self { cpu_id, scheduler })
     }
 
     pub fn add_task(&mut self, task: AxTaskRef) {
-        debug!("task spawn: {}", task.id_name());
+        debug!("task spawn: {} on CPU {}", task.id_name(), self.cpu_id);
         assert!(task.is_ready());
-        self.scheduler.add_task(task);
+        self.enqueue(task);
+    }
+
+    /// Puts a ready task parked by a throttled task group back.
+    #[cfg(feature = "task_group")]
+    pub fn requeue_task(&mut self, task: AxTaskRef) {
+        if task.is_ready() {
+            self.enqueue(task);
+        }
     }
 
     #[cfg(feature = "irq")]
     pub fn scheduler_timer_tick(&mut self) {
         let curr = crate::current();
-        if !curr.is_idle() && self.scheduler.task_tick(curr.as_task_ref()) {
+        let now = axhal::time::monotonic_time_nanos();
+        let _delta = curr.account_time(now, axhal::trap::irq_from_user());
+        #[cfg(feature = "task_group")]
+        let throttled = crate::task_group::charge(curr.as_task_ref(), _delta, now);
+        #[cfg(not(feature = "task_group"))]
+        let throttled = false;
+        if !curr.is_idle() && (self.scheduler.task_tick(curr.as_task_ref()) || throttled) {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
@@ -52,9 +225,21 @@ impl AxRunQueue {
         self.resched(false);
     }
 
-    pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.scheduler
-            .set_priority(crate::current().as_task_ref(), prio)
+    pub fn set_task_priority(&mut self, task: &AxTaskRef, prio: isize) -> bool {
+        self.scheduler.set_priority(task, prio)
+    }
+
+    pub fn set_current_affinity(&mut self, cpumask: AxCpuMask) -> bool {
+        if cpumask.is_empty() {
+            return false;
+        }
+        let curr = crate::current();
+        curr.set_cpumask(cpumask);
+        // Migrate to an allowed CPU if the current one is no longer allowed.
+        if !cpumask.get(self.cpu_id) {
+            self.resched(false);
+        }
+        true
     }
 
     #[cfg(feature = "preempt")]
@@ -63,7 +248,7 @@ impl AxRunQueue {
         assert!(curr.is_running());
 
         // When we get the mutable reference of the run queue, we must
-        // have held the `SpinNoIrq` lock with both IRQs and preemption
+        // have held the run queue lock with both IRQs and preemption
         // disabled. So we need to set `current_disable_count` to 1 in
         // `can_preempt()` to obtain the preemption permission before
         //  locking the run queue.
@@ -87,13 +272,22 @@ impl AxRunQueue {
         assert!(curr.is_running());
         assert!(!curr.is_idle());
         if curr.is_init() {
-            EXITED_TASKS.lock().clear();
+            #[cfg(feature = "irq")]
+            {
+                let (ticks, wakeups) = crate::timers::timer_stats();
+                info!("timer interrupts: {}, idle wakeups: {}", ticks, wakeups);
+            }
+            // Drop the exited tasks of all CPUs, as their `gc` tasks will
+            // never run again.
+            for exited_tasks in &EXITED_TASKS {
+                exited_tasks.lock().clear();
+            }
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
             curr.notify_exit(exit_code, self);
-            EXITED_TASKS.lock().push_back(curr.clone());
-            WAIT_FOR_EXIT.notify_one_locked(false, self);
+            EXITED_TASKS[self.cpu_id].lock().push_back(curr.clone());
+            unsafe { WAIT_FOR_EXIT.current_ref_raw() }.notify_one_locked(false, self);
             self.resched(false);
         }
         unreachable!("task exited!");
@@ -117,11 +311,40 @@ impl AxRunQueue {
         self.resched(false);
     }
 
+    /// Like [`block_current`](Self::block_current), but the task can also be
+    /// woken up by [`TaskInner::cancel`].
+    ///
+    /// It returns immediately without blocking if the current task has been
+    /// cancelled.
+    pub fn block_current_interruptible<F>(&mut self, wait_queue_push: F)
+    where
+        F: FnOnce(AxTaskRef),
+    {
+        let curr = crate::current();
+        debug!("task block (interruptible): {}", curr.id_name());
+        assert!(curr.is_running());
+        assert!(!curr.is_idle());
+
+        #[cfg(feature = "preempt")]
+        assert!(curr.can_preempt(1));
+
+        if curr.block_interruptible() {
+            wait_queue_push(curr.clone());
+            self.resched(false);
+            curr.end_interruptible();
+        }
+    }
+
+    /// Puts a blocked task into this run queue.
+    ///
+    /// The task may still be running on another CPU until it is fully switched
+    /// out, [`switch_to`](Self::switch_to) will wait for that.
     pub fn unblock_task(&mut self, task: AxTaskRef, resched: bool) {
-        debug!("task unblock: {}", task.id_name());
-        if task.is_blocked() {
-            task.set_state(TaskState::Ready);
-            self.scheduler.add_task(task); // TODO: priority
+        debug!("task unblock: {} on CPU {}", task.id_name(), self.cpu_id);
+        // The task may be woken up by multiple events at the same time (e.g,
+        // a notification and a cancellation), only enqueue it once.
+        if task.transition_state(TaskState::Blocked, TaskState::Ready) {
+            self.enqueue(task); // TODO: priority
             if resched {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
@@ -130,17 +353,21 @@ impl AxRunQueue {
     }
 
     #[cfg(feature = "irq")]
-    pub fn sleep_until(&mut self, deadline: axhal::time::TimeValue) {
+    pub fn sleep_until_interruptible(&mut self, deadline: axhal::time::TimeValue) {
         let curr = crate::current();
-        debug!("task sleep: {}, deadline={:?}", curr.id_name(), deadline);
-        assert!(curr.is_running());
-        assert!(!curr.is_idle());
-
-        let now = axhal::time::wall_time();
-        if now < deadline {
-            crate::timers::set_alarm_wakeup(deadline, curr.clone());
-            curr.set_state(TaskState::Blocked);
-            self.resched(false);
+        debug!(
+            "task sleep (interruptible): {}, deadline={:?}",
+            curr.id_name(),
+            deadline
+        );
+        if axhal::time::wall_time() < deadline {
+            self.block_current_interruptible(|task| {
+                crate::timers::set_alarm_wakeup(deadline, task);
+            });
+            if curr.in_timer_list() {
+                // woken up by cancellation
+                crate::timers::cancel_alarm(curr.as_task_ref());
+            }
         }
     }
 }
@@ -149,33 +376,160 @@ impl AxRunQueue {
     /// Common reschedule subroutine. If `preempt`, keep current task's time
     /// slice, otherwise reset it.
     fn resched(&mut self, preempt: bool) {
+        #[cfg(feature = "watchdog")]
+        crate::watchdog::touch();
+        self.take_pending_tasks();
         let prev = crate::current();
         if prev.is_running() {
             prev.set_state(TaskState::Ready);
             if !prev.is_idle() {
-                self.scheduler.put_prev_task(prev.clone(), preempt);
+                if prev.cpumask().get(self.cpu_id) {
+                    self.scheduler.put_prev_task(prev.clone(), preempt);
+                } else {
+                    self.enqueue(prev.clone());
+                }
             }
         }
-        let next = self.scheduler.pick_next_task().unwrap_or_else(|| unsafe {
-            // Safety: IRQs must be disabled at this time.
-            IDLE_TASK.current_ref_raw().get_unchecked().clone()
-        });
+        let next = loop {
+            let next = self
+                .scheduler
+                .pick_next_task()
+                .or_else(|| self.steal_task());
+            // Tasks of throttled groups are parked until the next period.
+            #[cfg(feature = "task_group")]
+            let next = match next {
+                Some(task) => match crate::task_group::park_if_throttled(task) {
+                    Some(task) => Some(task),
+                    None => continue,
+                },
+                None => None,
+            };
+            break next.unwrap_or_else(|| unsafe {
+                // Safety: IRQs must be disabled at this time.
+                IDLE_TASK.current_ref_raw().get_unchecked().clone()
+            });
+        };
         self.switch_to(prev, next);
     }
 
+    /// Selects a CPU for the given task to run on, prefers the current CPU and
+    /// then the CPU it last ran on.
+    fn select_cpu(&self, task: &AxTaskRef) -> usize {
+        let cpumask = task.cpumask();
+        if cpumask.get(self.cpu_id) {
+            self.cpu_id
+        } else if cpumask.get(task.cpu_id()) {
+            task.cpu_id()
+        } else {
+            cpumask.first_index().unwrap_or(self.cpu_id)
+        }
+    }
+
+    /// Puts a ready task into this run queue, or the pending list of another
+    /// CPU if it is not allowed to run on this CPU.
+    fn enqueue(&mut self, task: AxTaskRef) {
+        let cpu_id = self.select_cpu(&task);
+        if cpu_id == self.cpu_id {
+            self.scheduler.add_task(task);
+            #[cfg(feature = "irq")]
+            crate::timers::on_task_ready();
+        } else {
+            debug!("task {} is sent to CPU {}", task.id_name(), cpu_id);
+            PENDING_TASKS[cpu_id].lock().push_back(task);
+            // Let the target CPU take it now, rather than on its next tick
+            // (which may be stopped if it is idle).
+            #[cfg(all(feature = "irq", feature = "smp"))]
+            axhal::irq::send_ipi(cpu_id);
+        }
+    }
+
+    /// Moves tasks sent by other CPUs into this run queue, and applies the
+    /// priorities deferred by them.
+    pub(crate) fn take_pending_tasks(&mut self) {
+        let pending = core::mem::take(&mut *PENDING_TASKS[self.cpu_id].lock());
+        for task in pending {
+            self.scheduler.add_task(task);
+            #[cfg(feature = "irq")]
+            crate::timers::on_task_ready();
+        }
+        let priorities = core::mem::take(&mut *PENDING_PRIORITIES[self.cpu_id].lock());
+        for task in priorities {
+            if task.cpu_id() == self.cpu_id {
+                self.scheduler.set_priority(&task, task.priority());
+            } else {
+                defer_priority(task);
+            }
+        }
+    }
+
+    /// Takes a ready task from the run queue of another CPU, if this CPU has
+    /// nothing to run.
+    ///
+    /// Only `try_lock` is used on other run queues, as their owners may try
+    /// to steal from us at the same time.
+    fn steal_task(&self) -> Option<AxTaskRef> {
+        for i in 1..axconfig::SMP {
+            let cpu_id = (self.cpu_id + i) % axconfig::SMP;
+            if !RUN_QUEUES[cpu_id].is_inited() {
+                continue;
+            }
+            if let Some(mut victim) = RUN_QUEUES[cpu_id].try_lock() {
+                if let Some(task) = victim.scheduler.pick_next_task() {
+                    if !task.cpumask().get(self.cpu_id) {
+                        // Not allowed to run here, give it back.
+                        victim.scheduler.put_prev_task(task, true);
+                        continue;
+                    }
+                    debug!(
+                        "task steal: {} from CPU {} to CPU {}",
+                        task.id_name(),
+                        cpu_id,
+                        self.cpu_id
+                    );
+                    return Some(task);
+                }
+            }
+        }
+        None
+    }
+
     fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
         trace!(
             "context switch: {} -> {}",
             prev_task.id_name(),
             next_task.id_name()
         );
+        // Without guard pages, check the canary at the bottom of the stack.
+        if prev_task.kstack().is_some_and(|s| s.overflowed()) {
+            panic!("stack overflow in task {}", prev_task.id_name());
+        }
         #[cfg(feature = "preempt")]
         next_task.set_preempt_pending(false);
         next_task.set_state(TaskState::Running);
+        // Preemption and the accounting of the running task need the
+        // periodic tick, which is stopped while idle.
+        #[cfg(feature = "irq")]
+        if !next_task.is_idle() {
+            crate::timers::restart_tick();
+        }
         if prev_task.ptr_eq(&next_task) {
             return;
         }
 
+        // `next_task` may be still in the middle of switching out on another
+        // CPU (e.g, it was just unblocked by us), wait for it to finish.
+        while next_task.on_cpu() {
+            core::hint::spin_loop();
+        }
+        next_task.set_on_cpu(true);
+        next_task.set_cpu_id(self.cpu_id);
+
+        let now = axhal::time::monotonic_time_nanos();
+        let _delta = prev_task.switch_out(now);
+        #[cfg(feature = "task_group")]
+        crate::task_group::charge(prev_task.as_task_ref(), _delta, now);
+        next_task.switch_in(now);
+
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
             let next_ctx_ptr = next_task.ctx_mut_ptr();
@@ -185,19 +539,25 @@ impl AxRunQueue {
             assert!(Arc::strong_count(prev_task.as_task_ref()) > 1);
             assert!(Arc::strong_count(&next_task) >= 1);
 
+            // Keep `prev_task` alive until it is marked as off-CPU by the next
+            // task in `finish_task_switch()`.
+            PREV_TASK.write_current_raw(Arc::into_raw(prev_task.clone()) as usize);
+
             CurrentTask::set_current(prev_task, next_task);
             (*prev_ctx_ptr).switch_to(&*next_ctx_ptr);
         }
+        // Now we are running as `next_task`, possibly on another CPU.
+        finish_task_switch();
     }
 }
 
-fn gc_entry() {
+fn gc_entry(exited_tasks: &SpinNoIrq<VecDeque<AxTaskRef>>, wait_for_exit: &WaitQueue) {
     loop {
         // Drop all exited tasks and recycle resources.
-        let n = EXITED_TASKS.lock().len();
+        let n = exited_tasks.lock().len();
         for _ in 0..n {
             // Do not do the slow drops in the critical section.
-            let task = EXITED_TASKS.lock().pop_front();
+            let task = exited_tasks.lock().pop_front();
             if let Some(task) = task {
                 if Arc::strong_count(&task) == 1 {
                     // If I'm the last holder of the task, drop it immediately.
@@ -205,15 +565,25 @@ fn gc_entry() {
                 } else {
                     // Otherwise (e.g, `switch_to` is not compeleted, held by the
                     // joiner, etc), push it back and wait for them to drop first.
-                    EXITED_TASKS.lock().push_back(task);
+                    exited_tasks.lock().push_back(task);
                 }
             }
         }
-        WAIT_FOR_EXIT.wait();
+        wait_for_exit.wait();
     }
 }
 
+fn init_run_queue() {
+    let cpu_id = axhal::cpu::this_cpu_id();
+    RUN_QUEUE.with_current(|rq| {
+        rq.init_once(AxRunQueue::new(cpu_id));
+    });
+    RUN_QUEUES[cpu_id].init_once(this_run_queue());
+}
+
 pub(crate) fn init() {
+    crate::stack::init();
+
     // Create the `idle` task (not current task).
     const IDLE_TASK_STACK_SIZE: usize = 4096;
     let idle_task = TaskInner::new(|| crate::run_idle(), "idle".into(), IDLE_TASK_STACK_SIZE);
@@ -226,7 +596,7 @@ pub(crate) fn init() {
     main_task.set_state(TaskState::Running);
     unsafe { CurrentTask::init_current(main_task) };
 
-    RUN_QUEUE.init_once(AxRunQueue::new());
+    init_run_queue();
 }
 
 pub(crate) fn init_secondary() {
@@ -237,4 +607,6 @@ pub(crate) fn init_secondary() {
         i.init_once(idle_task.clone());
     });
     unsafe { CurrentTask::init_current(idle_task) }
+
+    init_run_queue();
 }
@@ -0,0 +1,227 @@
+//! Deterministic scheduler for reproducible concurrency tests.
+//!
+//! At every scheduling point (i.e., a task yields, blocks or exits), the next
+//! task is picked from the ready tasks by a PRNG seeded with [`set_seed`].
+//! Each choice is recorded, so a failing schedule can be reproduced from its
+//! seed, or replayed exactly with [`replay`].
+//!
+//! [`explore`] enumerates all schedules of a small test exhaustively, by
+//! running it repeatedly with different choices in depth-first order.
+//!
+//! Tasks are never preempted, so the schedule only depends on the choices.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ops::Deref;
+
+use scheduler::BaseScheduler;
+
+use crate::SpinNoIrq;
+
+/// A choice made at a scheduling point.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SchedChoice {
+    /// The number of ready tasks to choose from.
+    pub num_ready: usize,
+    /// The index of the chosen task among the ready tasks, in the order they
+    /// became ready.
+    pub index: usize,
+    /// The ID of the chosen task.
+    pub task_id: u64,
+}
+
+/// How to make choices beyond the replayed prefix.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Mode {
+    Random,
+    /// Always choose the first ready task, used by [`explore`].
+    First,
+}
+
+struct DetState {
+    seed: u64,
+    rng: u64,
+    mode: Mode,
+    /// Choices to make first, as indices.
+    replay: Vec<usize>,
+    trace: Vec<SchedChoice>,
+}
+
+static STATE: SpinNoIrq<DetState> = SpinNoIrq::new(DetState {
+    seed: 0,
+    rng: 0,
+    mode: Mode::Random,
+    replay: Vec::new(),
+    trace: Vec::new(),
+});
+
+impl DetState {
+    /// SplitMix64, which is fine with any seed including 0.
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn choose(&mut self, num_ready: usize) -> usize {
+        match self.replay.get(self.trace.len()) {
+            Some(&index) => index.min(num_ready - 1),
+            None if self.mode == Mode::First => 0,
+            None => (self.next_random() % num_ready as u64) as usize,
+        }
+    }
+
+    fn reset(&mut self, seed: u64, mode: Mode, replay: Vec<usize>) {
+        self.seed = seed;
+        self.rng = seed;
+        self.mode = mode;
+        self.replay = replay;
+        self.trace.clear();
+    }
+}
+
+/// Resets the scheduler with a new seed, and clears the recorded schedule.
+pub fn set_seed(seed: u64) {
+    STATE.lock().reset(seed, Mode::Random, Vec::new());
+}
+
+/// Returns the current seed.
+pub fn seed() -> u64 {
+    STATE.lock().seed
+}
+
+/// Takes the choices recorded since the last reset, or the last call.
+pub fn take_schedule() -> Vec<SchedChoice> {
+    core::mem::take(&mut STATE.lock().trace)
+}
+
+/// Makes the given choices first, as indices of [`SchedChoice`]s, and then
+/// continues with the current seed.
+///
+/// It also clears the recorded schedule. An index out of range is clamped to
+/// the last ready task.
+pub fn replay(choices: Vec<usize>) {
+    let mut state = STATE.lock();
+    let seed = state.seed;
+    state.reset(seed, Mode::Random, choices);
+}
+
+/// Runs `f` once for every possible schedule, up to `max_runs` times, and
+/// returns the number of runs.
+///
+/// `f` must wait for all the tasks it spawns, so that every run starts from
+/// the same state. Schedules are enumerated in depth-first order: each run
+/// replays the choices of the previous run up to its last choice with
+/// untried alternatives, and then takes the next alternative.
+pub fn explore<F: FnMut()>(max_runs: usize, mut f: F) -> usize {
+    let seed = seed();
+    let mut prefix = Vec::new();
+    let mut runs = 0;
+    while runs < max_runs {
+        STATE.lock().reset(seed, Mode::First, prefix);
+        f();
+        runs += 1;
+        let trace = take_schedule();
+        let Some(last) = trace.iter().rposition(|c| c.index + 1 < c.num_ready) else {
+            break;
+        };
+        prefix = trace[..last].iter().map(|c| c.index).collect();
+        prefix.push(trace[last].index + 1);
+    }
+    STATE.lock().reset(seed, Mode::Random, Vec::new());
+    runs
+}
+
+/// A task wrapper for the [`DetScheduler`].
+pub struct DetTask<T> {
+    inner: T,
+}
+
+impl<T> DetTask<T> {
+    /// Creates a new [`DetTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for DetTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A scheduler which picks the next task with a seeded PRNG.
+pub struct DetScheduler<T> {
+    ready: Vec<Arc<DetTask<T>>>,
+}
+
+impl<T> DetScheduler<T> {
+    /// Creates a new empty [`DetScheduler`].
+    pub const fn new() -> Self {
+        Self { ready: Vec::new() }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "Deterministic"
+    }
+}
+
+impl BaseScheduler for DetScheduler<crate::TaskInner> {
+    type SchedItem = Arc<DetTask<crate::TaskInner>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        self.ready.push(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        let idx = self.ready.iter().position(|t| Arc::ptr_eq(t, task))?;
+        Some(self.ready.remove(idx))
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        match self.ready.len() {
+            0 => None,
+            1 => self.ready.pop(),
+            num_ready => {
+                let mut state = STATE.lock();
+                let index = state.choose(num_ready);
+                let task = self.ready.remove(index);
+                state.trace.push(SchedChoice {
+                    num_ready,
+                    index,
+                    task_id: task.id().as_u64(),
+                });
+                Some(task)
+            }
+        }
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, _preempt: bool) {
+        self.ready.push(prev);
+    }
+
+    fn task_tick(&mut self, _current: &Self::SchedItem) -> bool {
+        false
+    }
+
+    fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
+        false
+    }
+}
+
+impl<T> Default for DetScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
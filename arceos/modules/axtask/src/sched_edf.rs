@@ -0,0 +1,404 @@
+//! Earliest deadline first (EDF) real-time scheduler.
+//!
+//! Real-time tasks are released periodically. In each period, a task runs a
+//! *job* which may consume at most `runtime` of CPU time and should complete
+//! before the relative `deadline`. A job is completed by calling
+//! [`wait_next_period`](crate::wait_next_period). Ready real-time tasks are
+//! always scheduled before other tasks, in the order of their absolute
+//! deadlines.
+//!
+//! The CPU time is charged on each timer tick. When a task overruns its
+//! budget, it is throttled until its next period.
+//!
+//! Tasks without real-time parameters are scheduled in FIFO order when no
+//! real-time task is ready.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axhal::time::monotonic_time_nanos;
+use scheduler::BaseScheduler;
+
+/// The fixed-point unit of CPU bandwidth, which represents 100% of a CPU.
+const BW_UNIT: u64 = 1 << 20;
+
+/// The total bandwidth reserved by all real-time tasks.
+static TOTAL_BW: AtomicU64 = AtomicU64::new(0);
+
+/// Real-time parameters of a periodic task.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RtParams {
+    /// The maximum CPU time of each job (the budget).
+    pub runtime: Duration,
+    /// The deadline of each job, relative to the release time.
+    pub deadline: Duration,
+    /// The interval between two releases.
+    pub period: Duration,
+}
+
+impl RtParams {
+    /// Creates real-time parameters.
+    ///
+    /// They are valid if `0 < runtime <= deadline <= period`.
+    pub const fn new(runtime: Duration, deadline: Duration, period: Duration) -> Self {
+        Self {
+            runtime,
+            deadline,
+            period,
+        }
+    }
+
+    /// Creates real-time parameters whose deadline is equal to the period.
+    pub const fn periodic(runtime: Duration, period: Duration) -> Self {
+        Self::new(runtime, period, period)
+    }
+
+    /// Whether `0 < runtime <= deadline <= period`.
+    pub fn is_valid(&self) -> bool {
+        !self.runtime.is_zero() && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+
+    /// The CPU bandwidth required, in units of [`BW_UNIT`].
+    ///
+    /// The deadline is used instead of the period if it is shorter, which
+    /// keeps the admission test sufficient for constrained deadlines.
+    fn bandwidth(&self) -> u64 {
+        let runtime = self.runtime.as_nanos() as u64;
+        let deadline = self.deadline.as_nanos() as u64;
+        (runtime * BW_UNIT).div_ceil(deadline)
+    }
+}
+
+/// Reserves the CPU bandwidth for a new real-time task.
+///
+/// Returns `false` if the parameters are invalid, or the total utilization of
+/// real-time tasks would exceed 100%.
+pub(crate) fn admit(params: &RtParams) -> bool {
+    if !params.is_valid() {
+        return false;
+    }
+    let bw = params.bandwidth();
+    TOTAL_BW
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+            total.checked_add(bw).filter(|&total| total <= BW_UNIT)
+        })
+        .is_ok()
+}
+
+/// A task wrapper for the [`EdfScheduler`].
+///
+/// A task without real-time parameters is a best-effort task.
+pub struct EdfTask<T> {
+    inner: T,
+    /// Real-time parameters in nanoseconds, `runtime == 0` if not set.
+    runtime: AtomicU64,
+    deadline: AtomicU64,
+    period: AtomicU64,
+    /// The absolute deadline of the current job.
+    abs_deadline: AtomicU64,
+    /// The release time of the next job.
+    next_release: AtomicU64,
+    /// The remaining budget of the current job.
+    remaining: AtomicU64,
+    /// The time when the budget was last charged.
+    exec_start: AtomicU64,
+    job_done: AtomicBool,
+    throttled: AtomicBool,
+    deadline_misses: AtomicUsize,
+}
+
+impl<T> EdfTask<T> {
+    /// Creates a new best-effort [`EdfTask`] from the inner task struct.
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            runtime: AtomicU64::new(0),
+            deadline: AtomicU64::new(0),
+            period: AtomicU64::new(0),
+            abs_deadline: AtomicU64::new(0),
+            next_release: AtomicU64::new(0),
+            remaining: AtomicU64::new(0),
+            exec_start: AtomicU64::new(0),
+            job_done: AtomicBool::new(false),
+            throttled: AtomicBool::new(false),
+            deadline_misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a reference to the inner task struct.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the real-time parameters, or [`None`] for best-effort tasks.
+    pub fn rt_params(&self) -> Option<RtParams> {
+        if !self.is_rt() {
+            return None;
+        }
+        Some(RtParams::new(
+            Duration::from_nanos(self.runtime.load(Ordering::Acquire)),
+            Duration::from_nanos(self.deadline.load(Ordering::Acquire)),
+            Duration::from_nanos(self.period.load(Ordering::Acquire)),
+        ))
+    }
+
+    /// Returns the number of jobs that were not completed before their
+    /// deadlines.
+    pub fn deadline_misses(&self) -> usize {
+        self.deadline_misses.load(Ordering::Acquire)
+    }
+
+    /// Makes it a real-time task.
+    ///
+    /// The bandwidth must have been reserved by [`admit`], and the task must
+    /// not have been added to a scheduler.
+    pub(crate) fn init_rt(&self, params: RtParams) {
+        assert!(!self.is_rt());
+        self.runtime
+            .store(params.runtime.as_nanos() as u64, Ordering::Release);
+        self.deadline
+            .store(params.deadline.as_nanos() as u64, Ordering::Release);
+        self.period
+            .store(params.period.as_nanos() as u64, Ordering::Release);
+    }
+
+    /// Marks the current job as completed.
+    ///
+    /// Returns the release time of the next job, or [`None`] for best-effort
+    /// tasks.
+    pub(crate) fn complete_job(&self) -> Option<u64> {
+        if !self.is_rt() {
+            return None;
+        }
+        if !self.job_done.swap(true, Ordering::AcqRel)
+            && monotonic_time_nanos() > self.abs_deadline.load(Ordering::Acquire)
+        {
+            self.deadline_misses.fetch_add(1, Ordering::AcqRel);
+        }
+        Some(self.next_release.load(Ordering::Acquire))
+    }
+
+    fn is_rt(&self) -> bool {
+        self.runtime.load(Ordering::Acquire) != 0
+    }
+
+    fn is_throttled(&self) -> bool {
+        self.throttled.load(Ordering::Acquire)
+    }
+
+    /// Starts a new job released at `release`.
+    fn start_job(&self, release: u64) {
+        let has_prev_job = self.next_release.load(Ordering::Acquire) != 0;
+        if has_prev_job && !self.job_done.load(Ordering::Acquire) {
+            // The previous job has not been completed.
+            self.deadline_misses.fetch_add(1, Ordering::AcqRel);
+        }
+        let deadline = release + self.deadline.load(Ordering::Acquire);
+        self.abs_deadline.store(deadline, Ordering::Release);
+        self.next_release.store(
+            release + self.period.load(Ordering::Acquire),
+            Ordering::Release,
+        );
+        self.remaining
+            .store(self.runtime.load(Ordering::Acquire), Ordering::Release);
+        self.job_done.store(false, Ordering::Release);
+        self.throttled.store(false, Ordering::Release);
+    }
+
+    /// Charges the CPU time since the last charge, and throttles the task if
+    /// the budget is exhausted.
+    ///
+    /// Returns `true` if the task is throttled.
+    fn charge(&self, now: u64) -> bool {
+        let start = self.exec_start.swap(now, Ordering::AcqRel);
+        let remaining = self
+            .remaining
+            .load(Ordering::Acquire)
+            .saturating_sub(now.saturating_sub(start));
+        self.remaining.store(remaining, Ordering::Release);
+        if remaining == 0 {
+            self.throttled.store(true, Ordering::Release);
+        }
+        remaining == 0
+    }
+
+    fn ready_key(self: &Arc<Self>) -> (u64, usize) {
+        (
+            self.abs_deadline.load(Ordering::Acquire),
+            Arc::as_ptr(self) as usize,
+        )
+    }
+
+    fn throttled_key(self: &Arc<Self>) -> (u64, usize) {
+        (
+            self.next_release.load(Ordering::Acquire),
+            Arc::as_ptr(self) as usize,
+        )
+    }
+}
+
+impl<T> Deref for EdfTask<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T> Drop for EdfTask<T> {
+    fn drop(&mut self) {
+        if let Some(params) = self.rt_params() {
+            TOTAL_BW.fetch_sub(params.bandwidth(), Ordering::AcqRel);
+        }
+    }
+}
+
+/// An earliest deadline first (EDF) scheduler with budget throttling.
+///
+/// Best-effort tasks are scheduled in FIFO order when no real-time task is
+/// ready.
+pub struct EdfScheduler<T> {
+    /// Ready real-time tasks, ordered by absolute deadlines.
+    ready: BTreeMap<(u64, usize), Arc<EdfTask<T>>>,
+    /// Throttled real-time tasks, ordered by next release times.
+    throttled: BTreeMap<(u64, usize), Arc<EdfTask<T>>>,
+    /// Ready best-effort tasks.
+    best_effort: VecDeque<Arc<EdfTask<T>>>,
+}
+
+impl<T> EdfScheduler<T> {
+    /// Creates a new empty [`EdfScheduler`].
+    pub const fn new() -> Self {
+        Self {
+            ready: BTreeMap::new(),
+            throttled: BTreeMap::new(),
+            best_effort: VecDeque::new(),
+        }
+    }
+
+    /// get the name of scheduler
+    pub fn scheduler_name() -> &'static str {
+        "EDF"
+    }
+
+    /// Returns the monotonic time when the earliest throttled task is
+    /// released, or [`None`] if no task is throttled.
+    ///
+    /// Throttled tasks are only replenished when the scheduler runs, so the
+    /// CPU must be woken up at that time even if its tick is stopped.
+    pub fn next_release(&self) -> Option<u64> {
+        self.throttled
+            .first_key_value()
+            .map(|(&(release, _), _)| release)
+    }
+
+    /// Moves throttled tasks whose next periods have begun to the ready set.
+    fn replenish(&mut self, now: u64) {
+        while let Some(entry) = self.throttled.first_entry() {
+            let release = entry.key().0;
+            if release > now {
+                break;
+            }
+            let task = entry.remove();
+            // Do not start a job whose deadline has already passed.
+            if release + task.deadline.load(Ordering::Acquire) <= now {
+                task.start_job(now);
+            } else {
+                task.start_job(release);
+            }
+            self.ready.insert(task.ready_key(), task);
+        }
+    }
+
+    fn enqueue_rt(&mut self, task: Arc<EdfTask<T>>) {
+        if task.is_throttled() {
+            self.throttled.insert(task.throttled_key(), task);
+        } else {
+            self.ready.insert(task.ready_key(), task);
+        }
+    }
+}
+
+impl<T> BaseScheduler for EdfScheduler<T> {
+    type SchedItem = Arc<EdfTask<T>>;
+
+    fn init(&mut self) {}
+
+    fn add_task(&mut self, task: Self::SchedItem) {
+        if !task.is_rt() {
+            self.best_effort.push_back(task);
+            return;
+        }
+        let now = monotonic_time_nanos();
+        if now >= task.next_release.load(Ordering::Acquire) {
+            // Woken up (or spawned) in a new period.
+            task.start_job(now);
+        } else if task.remaining.load(Ordering::Acquire) == 0 {
+            task.throttled.store(true, Ordering::Release);
+        }
+        self.enqueue_rt(task);
+    }
+
+    fn remove_task(&mut self, task: &Self::SchedItem) -> Option<Self::SchedItem> {
+        if !task.is_rt() {
+            let idx = self.best_effort.iter().position(|t| Arc::ptr_eq(t, task))?;
+            return self.best_effort.remove(idx);
+        }
+        if task.is_throttled() {
+            self.throttled.remove(&task.throttled_key())
+        } else {
+            self.ready.remove(&task.ready_key())
+        }
+    }
+
+    fn pick_next_task(&mut self) -> Option<Self::SchedItem> {
+        let now = monotonic_time_nanos();
+        self.replenish(now);
+        if let Some((_, task)) = self.ready.pop_first() {
+            task.exec_start.store(now, Ordering::Release);
+            return Some(task);
+        }
+        self.best_effort.pop_front()
+    }
+
+    fn put_prev_task(&mut self, prev: Self::SchedItem, preempt: bool) {
+        if prev.is_rt() {
+            prev.charge(monotonic_time_nanos());
+            self.enqueue_rt(prev);
+        } else if preempt {
+            self.best_effort.push_front(prev);
+        } else {
+            self.best_effort.push_back(prev);
+        }
+    }
+
+    fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        let now = monotonic_time_nanos();
+        self.replenish(now);
+        if !current.is_rt() {
+            return !self.ready.is_empty();
+        }
+        if current.charge(now) {
+            return true;
+        }
+        // Preempt if a ready task has an earlier deadline.
+        self.ready
+            .first_key_value()
+            .is_some_and(|(&(deadline, _), _)| {
+                deadline < current.abs_deadline.load(Ordering::Acquire)
+            })
+    }
+
+    fn set_priority(&mut self, _task: &Self::SchedItem, _prio: isize) -> bool {
+        false
+    }
+}
+
+impl<T> Default for EdfScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
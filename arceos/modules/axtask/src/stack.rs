@@ -0,0 +1,199 @@
+//! Kernel stacks of tasks.
+//!
+//! If the `paging` feature is enabled, stacks are mapped in a dedicated
+//! region of the kernel address space, with an unmapped guard page below each
+//! stack. A stack overflow then causes a page fault, which is reported as
+//! such. Otherwise, stacks are allocated from the heap, and a canary at the
+//! bottom of each stack is checked on every context switch.
+//!
+//! Note that the trap frame of the page fault is saved on the overflowed
+//! stack on RISC-V and AArch64. The overflow can only be reported if there
+//! is still room for the trap frame, i.e., if the faulting access is not
+//! right at the stack pointer. On x86_64, the page fault then becomes a
+//! double fault, which is handled on a separate stack and reports the
+//! overflow as well.
+
+use core::ptr::NonNull;
+
+use memory_addr::VirtAddr;
+
+/// The word to fill new stacks with, used to find the stack high-water mark.
+const FILL_WORD: u64 = 0xcccc_cccc_cccc_cccc;
+
+/// The value at the bottom of each stack, which is overwritten on stack
+/// overflows.
+#[cfg(not(feature = "paging"))]
+const STACK_CANARY: u64 = 0xdead_beef_cafe_f00d;
+
+pub(crate) struct TaskStack {
+    ptr: NonNull<u8>,
+    size: usize,
+}
+
+impl TaskStack {
+    pub fn alloc(size: usize) -> Self {
+        let ptr = imp::alloc(size);
+        let fill = unsafe {
+            core::slice::from_raw_parts_mut(ptr.cast::<u64>().as_ptr(), Self::words(size))
+        };
+        fill.fill(FILL_WORD);
+        #[cfg(not(feature = "paging"))]
+        unsafe {
+            ptr.cast::<u64>().as_ptr().write(STACK_CANARY)
+        };
+        Self { ptr, size }
+    }
+
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    pub const fn top(&self) -> VirtAddr {
+        unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.size)) }
+    }
+
+    /// Returns the number of words in a stack of `size` bytes.
+    const fn words(size: usize) -> usize {
+        size / core::mem::size_of::<u64>()
+    }
+
+    /// Returns the maximum stack usage, by scanning up from the bottom of the
+    /// stack for the first word that has been overwritten.
+    ///
+    /// The whole stack is filled with [`FILL_WORD`] on allocation, so the
+    /// usage is exact up to a word, unless the task happens to write the
+    /// fill word itself.
+    pub fn high_water(&self) -> usize {
+        let fill = unsafe {
+            core::slice::from_raw_parts(self.ptr.cast::<u64>().as_ptr(), Self::words(self.size))
+        };
+        // The canary does not count as used.
+        #[cfg(not(feature = "paging"))]
+        let skip = 1.min(fill.len());
+        #[cfg(feature = "paging")]
+        let skip = 0;
+        let untouched = skip + fill[skip..].iter().take_while(|&&w| w == FILL_WORD).count();
+        self.size - untouched * core::mem::size_of::<u64>()
+    }
+
+    /// Returns `true` if the stack has overflowed.
+    ///
+    /// With guard pages, overflows are caught by page faults instead, so it
+    /// always returns `false`.
+    pub fn overflowed(&self) -> bool {
+        #[cfg(not(feature = "paging"))]
+        {
+            unsafe { self.ptr.cast::<u64>().as_ptr().read_volatile() != STACK_CANARY }
+        }
+        #[cfg(feature = "paging")]
+        false
+    }
+
+    /// Returns `true` if the given address is in the guard page of the stack.
+    #[cfg(feature = "paging")]
+    pub fn in_guard_page(&self, vaddr: VirtAddr) -> bool {
+        let base = VirtAddr::from(self.ptr.as_ptr() as usize);
+        vaddr < base && vaddr >= base - imp::GUARD_SIZE
+    }
+}
+
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        imp::dealloc(self.ptr, self.size)
+    }
+}
+
+#[cfg(not(feature = "paging"))]
+mod imp {
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+
+    pub fn alloc(size: usize) -> NonNull<u8> {
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap()
+    }
+
+    pub fn dealloc(ptr: NonNull<u8>, size: usize) {
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        unsafe { alloc::alloc::dealloc(ptr.as_ptr(), layout) }
+    }
+}
+
+#[cfg(feature = "paging")]
+mod imp {
+    use core::ptr::NonNull;
+
+    use axhal::paging::MappingFlags;
+    use axhal::trap::{register_trap_handler, UNHANDLED_PAGE_FAULT};
+    use lazyinit::LazyInit;
+    use memory_addr::{VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+    pub const GUARD_SIZE: usize = PAGE_SIZE_4K;
+
+    /// The size of the virtual region for stacks. It is covered by a single
+    /// root page table entry on all architectures, see [`init`].
+    const REGION_SIZE: usize = 0x4000_0000; // 1G
+
+    static STACK_REGION: LazyInit<VirtAddrRange> = LazyInit::new();
+
+    /// Reserves the stack region at the top of the kernel address space.
+    ///
+    /// Address spaces of user processes share the kernel page tables below
+    /// the root level (see `axmm::new_user_aspace`), so the root entry of
+    /// the region is populated in advance, by mapping and unmapping a page.
+    /// Then stacks mapped later are also visible in user address spaces.
+    pub fn init() {
+        let mut aspace = axmm::kernel_aspace().lock();
+        let end = aspace.end().align_down(REGION_SIZE);
+        let region = VirtAddrRange::from_start_size(end - REGION_SIZE, REGION_SIZE);
+        assert!(aspace.contains_range(region.start, REGION_SIZE));
+        aspace
+            .map_alloc(region.start, PAGE_SIZE_4K, MappingFlags::READ, true)
+            .and_then(|_| aspace.unmap(region.start, PAGE_SIZE_4K))
+            .expect("failed to initialize the task stack region");
+        debug!("task stack region: {:#x?}", region);
+        STACK_REGION.init_once(region);
+    }
+
+    pub fn alloc(size: usize) -> NonNull<u8> {
+        let mut aspace = axmm::kernel_aspace().lock();
+        let start = aspace
+            .find_free_area(STACK_REGION.start, size + GUARD_SIZE, *STACK_REGION)
+            .expect("no free space for task stacks");
+        // Leave the lowest page unmapped as the guard page.
+        let base = start + GUARD_SIZE;
+        aspace
+            .map_alloc(base, size, MappingFlags::READ | MappingFlags::WRITE, true)
+            .expect("failed to map task stack");
+        NonNull::new(base.as_mut_ptr()).unwrap()
+    }
+
+    pub fn dealloc(ptr: NonNull<u8>, size: usize) {
+        let base = VirtAddr::from(ptr.as_ptr() as usize);
+        if let Err(e) = axmm::kernel_aspace().lock().unmap(base, size) {
+            warn!("failed to unmap task stack at {:#x}: {:?}", base, e);
+        }
+    }
+
+    #[register_trap_handler(UNHANDLED_PAGE_FAULT)]
+    fn report_stack_overflow(vaddr: VirtAddr, _access_flags: MappingFlags, is_user: bool) {
+        if is_user {
+            return;
+        }
+        if let Some(curr) = crate::current_may_uninit() {
+            if curr.kstack().is_some_and(|s| s.in_guard_page(vaddr)) {
+                panic!(
+                    "stack overflow in task {}, fault_vaddr={:#x}",
+                    curr.id_name(),
+                    vaddr
+                );
+            }
+        }
+    }
+}
+
+/// Initializes the task stack allocator.
+pub(crate) fn init() {
+    #[cfg(feature = "paging")]
+    imp::init();
+}
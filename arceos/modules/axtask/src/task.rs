@@ -1,10 +1,12 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::{boxed::Box, string::String, vec::Vec};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
-use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
-
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicIsize, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+};
+use core::time::Duration;
+use core::{cell::UnsafeCell, fmt};
 
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
@@ -12,8 +14,17 @@ use axhal::tls::TlsArea;
 use axhal::arch::TaskContext;
 use memory_addr::{align_up_4k, VirtAddr};
 
+#[cfg(feature = "lockdep")]
+use crate::lockdep::HeldLocks;
+use crate::stack::TaskStack;
 use crate::task_ext::AxTaskExt;
-use crate::{AxRunQueue, AxTask, AxTaskRef, WaitQueue};
+#[cfg(feature = "task_group")]
+use crate::task_group::TaskGroupRef;
+use crate::task_local::TaskLocals;
+use crate::{AxCpuMask, AxRunQueue, AxTask, AxTaskRef, SpinNoIrq, WaitQueue};
+
+/// All tasks that have been created and not yet dropped, indexed by task ID.
+static TASK_REGISTRY: SpinNoIrq<BTreeMap<u64, Weak<AxTask>>> = SpinNoIrq::new(BTreeMap::new());
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -22,13 +33,50 @@ pub struct TaskId(u64);
 /// The possible states of a task.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum TaskState {
+pub enum TaskState {
+    /// The task is running on a CPU.
     Running = 1,
+    /// The task is ready to run, and is waiting in a run queue.
     Ready = 2,
+    /// The task is blocked, e.g, waiting in a wait queue or sleeping.
     Blocked = 3,
+    /// The task has exited, but has not been dropped yet.
     Exited = 4,
 }
 
+/// The error returned by interruptible blocking operations, when the current
+/// task is cancelled by [`TaskInner::cancel`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Cancelled;
+
+/// A point-in-time view of a task, see [`TaskInner::snapshot`].
+#[derive(Debug, Clone)]
+pub struct TaskSnapshot {
+    /// The task ID.
+    pub id: u64,
+    /// The task name.
+    pub name: String,
+    /// The task state.
+    pub state: TaskState,
+    /// The CPU on which the task runs (or last ran).
+    pub cpu_id: usize,
+    /// The effective priority.
+    pub priority: isize,
+    /// The total time the task has been running on CPUs.
+    pub run_time: Duration,
+    /// The CPU time spent in user mode.
+    pub user_time: Duration,
+    /// The CPU time spent in kernel mode.
+    pub kernel_time: Duration,
+    /// The number of times the task has been switched in.
+    pub ctx_switches: u64,
+    /// The size of the task stack in bytes, `0` if the task runs on the boot
+    /// stack.
+    pub stack_size: usize,
+    /// The maximum stack usage in bytes so far.
+    pub stack_high_water: usize,
+}
+
 /// The inner task structure.
 pub struct TaskInner {
     id: TaskId,
@@ -43,17 +91,64 @@ pub struct TaskInner {
     #[cfg(feature = "irq")]
     in_timer_list: AtomicBool,
 
+    /// Whether the task is running on a CPU, including the time it is being
+    /// switched out.
+    on_cpu: AtomicBool,
+    /// The CPU on which the task runs (or last ran).
+    cpu_id: AtomicUsize,
+    /// The CPUs on which the task is allowed to run.
+    cpumask: AtomicUsize,
+
+    /// The priority set by the task itself.
+    base_prio: AtomicIsize,
+    /// The effective priority, may be boosted by priority inheritance.
+    prio: AtomicIsize,
+    /// Priority inheritance states, protected by [`crate::pi::PI_LOCK`].
+    pi_state: UnsafeCell<PiState>,
+
+    /// The CPU time in user mode in nanoseconds.
+    user_time: AtomicU64,
+    /// The CPU time in kernel mode in nanoseconds, excluding the time since
+    /// the last accounting.
+    kernel_time: AtomicU64,
+    /// The monotonic time when the CPU time was last accounted.
+    last_account_time: AtomicU64,
+    ctx_switches: AtomicU64,
+
+    /// Cancellation states, see [`TaskInner::cancel`].
+    cancel_state: SpinNoIrq<CancelState>,
+
     #[cfg(feature = "preempt")]
     need_resched: AtomicBool,
     #[cfg(feature = "preempt")]
     preempt_disable_count: AtomicUsize,
 
+    /// The monotonic time in nanoseconds when the task was last blocked.
+    #[cfg(feature = "watchdog")]
+    blocked_since: AtomicU64,
+    /// Whether the task is checked by the hung-task watchdog.
+    #[cfg(feature = "watchdog")]
+    hung_check: AtomicBool,
+
+    /// The group the task belongs to, see [`crate::task_group`].
+    #[cfg(feature = "task_group")]
+    task_group: SpinNoIrq<Option<TaskGroupRef>>,
+
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
+    /// Notified when any child task (spawned by this task) exits.
+    child_exit: Arc<WaitQueue>,
+    /// The ID and the `child_exit` queue of the parent task.
+    parent: Option<(u64, Arc<WaitQueue>)>,
 
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
+    /// Task-local values, only accessed by the task itself.
+    task_locals: UnsafeCell<TaskLocals>,
+    /// Locks held by the task, only accessed by the task itself.
+    #[cfg(feature = "lockdep")]
+    held_locks: UnsafeCell<HeldLocks>,
 
     #[cfg(feature = "tls")]
     tls: TlsArea,
@@ -95,6 +190,8 @@ impl TaskInner {
     {
         let mut t = Self::new_common(TaskId::new(), name);
         debug!("new task: {}", t.id_name());
+        t.parent =
+            crate::current_may_uninit().map(|curr| (curr.id().as_u64(), curr.child_exit.clone()));
         let kstack = TaskStack::alloc(align_up_4k(stack_size));
 
         #[cfg(feature = "tls")]
@@ -108,6 +205,10 @@ impl TaskInner {
         if t.name == "idle" {
             t.is_idle = true;
         }
+        #[cfg(feature = "task_group")]
+        if !t.is_idle {
+            t.task_group = SpinNoIrq::new(crate::current_may_uninit().and_then(|c| c.task_group()));
+        }
         t
     }
 
@@ -129,12 +230,128 @@ impl TaskInner {
     /// Wait for the task to exit, and return the exit code.
     ///
     /// It will return immediately if the task has already exited (but not dropped).
+    ///
+    /// It is a cancellation point: it returns [`None`] if the current task is
+    /// cancelled by [`cancel`](Self::cancel) before this task exits.
     pub fn join(&self) -> Option<i32> {
         self.wait_for_exit
-            .wait_until(|| self.state() == TaskState::Exited);
+            .wait_until_interruptible(|| self.state() == TaskState::Exited)
+            .ok()?;
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Returns the ID of the parent task, which spawned this task, or
+    /// [`None`] if it is an init task.
+    pub fn parent_id(&self) -> Option<u64> {
+        self.parent.as_ref().map(|(id, _)| *id)
+    }
+
+    /// Returns the wait queue notified when any child task exits.
+    pub(crate) fn child_exit(&self) -> &WaitQueue {
+        &self.child_exit
+    }
+
+    /// Wait for the task to exit with a timeout, and return the exit code.
+    ///
+    /// Returns [`None`] if the task has not exited after `dur`, or if the
+    /// current task is cancelled before that, as [`join`](Self::join).
+    #[cfg(feature = "irq")]
+    pub fn join_timeout(&self, dur: Duration) -> Option<i32> {
+        let _ = self
+            .wait_for_exit
+            .wait_timeout_until_interruptible(dur, || self.state() == TaskState::Exited);
+        if self.state() == TaskState::Exited {
+            Some(self.exit_code.load(Ordering::Acquire))
+        } else {
+            None
+        }
+    }
+
+    /// Requests the task to be cancelled.
+    ///
+    /// The cancellation is cooperative: it only marks the task as cancelled,
+    /// which the task can check by [`is_cancelled`](Self::is_cancelled). If
+    /// the task is blocked in a cancellation point, it is woken up and the
+    /// wait returns early. The cancellation points are:
+    ///
+    /// - the `*_interruptible` waits of [`WaitQueue`], which return
+    ///   [`Cancelled`];
+    /// - [`crate::sleep`], [`crate::sleep_until`] and their `*_interruptible`
+    ///   variants;
+    /// - [`join`](Self::join) and [`join_timeout`](Self::join_timeout).
+    ///
+    /// Once the task is cancelled, they all return immediately. Other waits,
+    /// such as [`WaitQueue::wait`], [`WaitQueue::wait_until`] and
+    /// [`WaitQueue::wait_timeout`] used by locks, are not affected.
+    pub fn cancel(&self) {
+        debug!("task cancel: {}", self.id_name());
+        let task = find_task(self.id.as_u64());
+        let mut rq = crate::current_run_queue();
+        let mut state = self.cancel_state.lock();
+        state.pending = true;
+        if let Some(task) = task.filter(|_| state.interruptible) {
+            rq.unblock_task(task, true);
+        }
+    }
+
+    /// Returns `true` if the task has been requested to be cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_state.lock().pending
+    }
+
+    /// Returns the total time the task has been running on CPUs.
+    pub fn run_time(&self) -> Duration {
+        self.user_time() + self.kernel_time()
+    }
+
+    /// Returns the CPU time the task has spent in user mode.
+    ///
+    /// The time is sampled on timer ticks, so it has the precision of a tick.
+    pub fn user_time(&self) -> Duration {
+        Duration::from_nanos(self.user_time.load(Ordering::Acquire))
+    }
+
+    /// Returns the CPU time the task has spent in kernel mode.
+    pub fn kernel_time(&self) -> Duration {
+        let mut nanos = self.kernel_time.load(Ordering::Acquire);
+        if self.is_running() {
+            // The time since the last accounting is not accounted yet.
+            let now = axhal::time::monotonic_time_nanos();
+            nanos += now.saturating_sub(self.last_account_time.load(Ordering::Acquire));
+        }
+        Duration::from_nanos(nanos)
+    }
+
+    /// Returns the number of times the task has been switched in.
+    pub fn ctx_switches(&self) -> u64 {
+        self.ctx_switches.load(Ordering::Acquire)
+    }
+
+    /// Returns the maximum stack usage in bytes so far.
+    ///
+    /// It returns `0` if the task runs on the boot stack (e.g, the `main`
+    /// task), whose usage is not tracked.
+    pub fn stack_high_water(&self) -> usize {
+        self.kstack.as_ref().map_or(0, |kstack| kstack.high_water())
+    }
+
+    /// Takes a snapshot of the task.
+    pub fn snapshot(&self) -> TaskSnapshot {
+        TaskSnapshot {
+            id: self.id.as_u64(),
+            name: self.name.clone(),
+            state: self.state(),
+            cpu_id: self.cpu_id(),
+            priority: self.priority(),
+            run_time: self.run_time(),
+            user_time: self.user_time(),
+            kernel_time: self.kernel_time(),
+            ctx_switches: self.ctx_switches(),
+            stack_size: self.kstack.as_ref().map_or(0, |kstack| kstack.size()),
+            stack_high_water: self.stack_high_water(),
+        }
+    }
+
     /// Returns the pointer to the user-defined task extended data.
     ///
     /// # Safety
@@ -174,15 +391,40 @@ impl TaskInner {
             in_wait_queue: AtomicBool::new(false),
             #[cfg(feature = "irq")]
             in_timer_list: AtomicBool::new(false),
+            on_cpu: AtomicBool::new(false),
+            cpu_id: AtomicUsize::new(0),
+            cpumask: AtomicUsize::new(AxCpuMask::full().as_raw_bits()),
+            base_prio: AtomicIsize::new(0),
+            prio: AtomicIsize::new(0),
+            pi_state: UnsafeCell::new(PiState::new()),
+            user_time: AtomicU64::new(0),
+            kernel_time: AtomicU64::new(0),
+            last_account_time: AtomicU64::new(0),
+            ctx_switches: AtomicU64::new(0),
+            cancel_state: SpinNoIrq::new(CancelState {
+                pending: false,
+                interruptible: false,
+            }),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
+            #[cfg(feature = "watchdog")]
+            blocked_since: AtomicU64::new(0),
+            #[cfg(feature = "watchdog")]
+            hung_check: AtomicBool::new(true),
+            #[cfg(feature = "task_group")]
+            task_group: SpinNoIrq::new(None),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            child_exit: Arc::new(WaitQueue::new()),
+            parent: None,
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
+            task_locals: UnsafeCell::new(TaskLocals::default()),
+            #[cfg(feature = "lockdep")]
+            held_locks: UnsafeCell::new(HeldLocks::new()),
             #[cfg(feature = "tls")]
             tls: TlsArea::alloc(),
         }
@@ -206,19 +448,37 @@ impl TaskInner {
     }
 
     pub(crate) fn into_arc(self) -> AxTaskRef {
-        Arc::new(AxTask::new(self))
+        let id = self.id.as_u64();
+        let task = Arc::new(AxTask::new(self));
+        TASK_REGISTRY.lock().insert(id, Arc::downgrade(&task));
+        task
     }
 
+    /// Returns the state of the task.
     #[inline]
-    pub(crate) fn state(&self) -> TaskState {
+    pub fn state(&self) -> TaskState {
         self.state.load(Ordering::Acquire).into()
     }
 
     #[inline]
     pub(crate) fn set_state(&self, state: TaskState) {
+        #[cfg(feature = "watchdog")]
+        if state == TaskState::Blocked {
+            let now = axhal::time::monotonic_time_nanos();
+            self.blocked_since.store(now, Ordering::Relaxed);
+        }
         self.state.store(state as u8, Ordering::Release)
     }
 
+    /// Atomically changes the state from `from` to `to`, returns `false` if
+    /// the state is not `from`.
+    #[inline]
+    pub(crate) fn transition_state(&self, from: TaskState, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -229,11 +489,6 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
-    #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
-        matches!(self.state(), TaskState::Blocked)
-    }
-
     #[inline]
     pub(crate) const fn is_init(&self) -> bool {
         self.is_init
@@ -266,6 +521,169 @@ impl TaskInner {
         self.in_timer_list.store(in_timer_list, Ordering::Release);
     }
 
+    /// Marks the task as blocked in an interruptible wait.
+    ///
+    /// Returns `false` without blocking if the task has been cancelled.
+    pub(crate) fn block_interruptible(&self) -> bool {
+        let mut state = self.cancel_state.lock();
+        if state.pending {
+            return false;
+        }
+        state.interruptible = true;
+        self.set_state(TaskState::Blocked);
+        true
+    }
+
+    /// Marks the task as no longer in an interruptible wait, after it is
+    /// woken up.
+    pub(crate) fn end_interruptible(&self) {
+        self.cancel_state.lock().interruptible = false;
+    }
+
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn in_interruptible_wait(&self) -> bool {
+        self.cancel_state.lock().interruptible
+    }
+
+    /// Returns the monotonic time in nanoseconds when the task was last
+    /// blocked.
+    #[inline]
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn blocked_since(&self) -> u64 {
+        self.blocked_since.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn hung_check_enabled(&self) -> bool {
+        self.hung_check.load(Ordering::Relaxed)
+    }
+
+    /// Excludes the task from the hung-task check, for service tasks which
+    /// wait for work indefinitely.
+    #[inline]
+    #[cfg(feature = "watchdog")]
+    pub(crate) fn disable_hung_check(&self) {
+        self.hung_check.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns the effective priority of the task.
+    ///
+    /// It may be higher (numerically lower) than the priority set by the task
+    /// itself, if it holds a priority-inheritance lock that higher priority
+    /// tasks are waiting for.
+    #[inline]
+    pub fn priority(&self) -> isize {
+        self.prio.load(Ordering::Acquire)
+    }
+
+    /// Returns the priority set by the task itself.
+    #[inline]
+    pub fn base_priority(&self) -> isize {
+        self.base_prio.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_base_priority(&self, prio: isize) {
+        self.base_prio.store(prio, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn set_effective_priority(&self, prio: isize) {
+        self.prio.store(prio, Ordering::Release);
+    }
+
+    /// Returns the priority inheritance states.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold [`crate::pi::PI_LOCK`].
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn pi_state(&self) -> &mut PiState {
+        &mut *self.pi_state.get()
+    }
+
+    /// Records that the task is switched in at `now`.
+    pub(crate) fn switch_in(&self, now: u64) {
+        self.last_account_time.store(now, Ordering::Release);
+        self.ctx_switches.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Records that the task is switched out at `now`.
+    ///
+    /// As context switches always happen in the kernel, the time since the
+    /// last accounting is charged as kernel time.
+    pub(crate) fn switch_out(&self, now: u64) -> u64 {
+        self.account_time(now, false)
+    }
+
+    /// Charges the CPU time since the last accounting to the user or kernel
+    /// time, and returns the charged time. It must be called on the CPU the
+    /// task is running on.
+    pub(crate) fn account_time(&self, now: u64, user: bool) -> u64 {
+        let last = self.last_account_time.swap(now, Ordering::AcqRel);
+        let delta = now.saturating_sub(last);
+        if user {
+            self.user_time.fetch_add(delta, Ordering::AcqRel);
+        } else {
+            self.kernel_time.fetch_add(delta, Ordering::AcqRel);
+        }
+        delta
+    }
+
+    #[inline]
+    pub(crate) fn on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
+        self.on_cpu.store(on_cpu, Ordering::Release);
+    }
+
+    /// Returns the ID of the CPU on which the task runs (or last ran).
+    #[inline]
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Release);
+    }
+
+    /// Returns the group the task belongs to, or [`None`] if it is not in any
+    /// group.
+    #[cfg(feature = "task_group")]
+    pub fn task_group(&self) -> Option<TaskGroupRef> {
+        self.task_group.lock().clone()
+    }
+
+    /// Moves the task to the given group, or out of any group if `group` is
+    /// [`None`].
+    ///
+    /// If the task is parked in a throttled group, it stays parked until the
+    /// old group is unthrottled.
+    #[cfg(feature = "task_group")]
+    pub fn set_task_group(&self, group: Option<TaskGroupRef>) {
+        if self.is_idle {
+            return;
+        }
+        let old = core::mem::replace(&mut *self.task_group.lock(), group);
+        drop(old);
+    }
+
+    /// Returns the CPU affinity mask of the task.
+    #[inline]
+    pub fn cpumask(&self) -> AxCpuMask {
+        AxCpuMask::from_raw_bits(self.cpumask.load(Ordering::Acquire))
+    }
+
+    #[inline]
+    pub(crate) fn set_cpumask(&self, cpumask: AxCpuMask) {
+        self.cpumask.store(cpumask.as_raw_bits(), Ordering::Release);
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn set_preempt_pending(&self, pending: bool) {
@@ -278,6 +696,12 @@ impl TaskInner {
         self.preempt_disable_count.load(Ordering::Acquire) == current_disable_count
     }
 
+    #[inline]
+    #[cfg(feature = "preempt")]
+    pub(crate) fn preempt_disable_count(&self) -> usize {
+        self.preempt_disable_count.load(Ordering::Acquire)
+    }
+
     #[inline]
     #[cfg(feature = "preempt")]
     pub(crate) fn disable_preempt(&self) {
@@ -297,7 +721,7 @@ impl TaskInner {
     fn current_check_preempt_pending() {
         let curr = crate::current();
         if curr.need_resched.load(Ordering::Acquire) && curr.can_preempt(0) {
-            let mut rq = crate::RUN_QUEUE.lock();
+            let mut rq = crate::run_queue::current_run_queue();
             if curr.need_resched.load(Ordering::Acquire) {
                 rq.preempt_resched();
             }
@@ -307,6 +731,9 @@ impl TaskInner {
     pub(crate) fn notify_exit(&self, exit_code: i32, rq: &mut AxRunQueue) {
         self.exit_code.store(exit_code, Ordering::Release);
         self.wait_for_exit.notify_all_locked(false, rq);
+        if let Some((_, child_exit)) = &self.parent {
+            child_exit.notify_all_locked(false, rq);
+        }
     }
 
     #[inline]
@@ -328,6 +755,57 @@ impl TaskInner {
             None => None,
         }
     }
+
+    /// Returns the task-local values of the task.
+    ///
+    /// # Safety
+    ///
+    /// It must be called by the task itself, and the returned reference must
+    /// not outlive other calls.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn task_locals(&self) -> &mut TaskLocals {
+        unsafe { &mut *self.task_locals.get() }
+    }
+
+    /// Returns the locks held by the task.
+    ///
+    /// # Safety
+    ///
+    /// It must be called by the task itself with IRQs disabled, and the
+    /// returned reference must not outlive other calls.
+    #[cfg(feature = "lockdep")]
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn held_locks(&self) -> &mut HeldLocks {
+        unsafe { &mut *self.held_locks.get() }
+    }
+
+    /// Returns the kernel stack of the task.
+    #[inline]
+    pub(crate) const fn kstack(&self) -> Option<&TaskStack> {
+        self.kstack.as_ref()
+    }
+
+    /// Returns the return addresses on the current call stack, separated by
+    /// spaces.
+    ///
+    /// It must be called by the task itself.
+    #[cfg(any(feature = "watchdog", feature = "lockdep"))]
+    pub(crate) fn backtrace(&self) -> String {
+        use core::fmt::Write;
+        let fp = axhal::backtrace::frame_pointer();
+        let stack = match self.kstack() {
+            Some(s) => s.top().as_usize() - s.size()..s.top().as_usize(),
+            // The init tasks run on the boot stacks, whose bounds are unknown.
+            None => fp..fp + axconfig::TASK_STACK_SIZE,
+        };
+        let mut trace = String::new();
+        axhal::backtrace::unwind(fp, stack, |ra| {
+            let _ = write!(trace, " {:#x}", ra);
+        });
+        trace
+    }
 }
 
 impl fmt::Debug for TaskInner {
@@ -343,31 +821,70 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        TASK_REGISTRY.lock().remove(&self.id.as_u64());
+        if !self.is_idle {
+            let user = self.user_time.load(Ordering::Acquire);
+            let kernel = self.kernel_time.load(Ordering::Acquire);
+            DROPPED_USER_TIME.fetch_add(user, Ordering::AcqRel);
+            DROPPED_KERNEL_TIME.fetch_add(kernel, Ordering::AcqRel);
+        }
     }
 }
 
-struct TaskStack {
-    ptr: NonNull<u8>,
-    layout: Layout,
-}
+/// The CPU time in nanoseconds consumed by tasks that have been dropped.
+static DROPPED_USER_TIME: AtomicU64 = AtomicU64::new(0);
+static DROPPED_KERNEL_TIME: AtomicU64 = AtomicU64::new(0);
 
-impl TaskStack {
-    pub fn alloc(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, 16).unwrap();
-        Self {
-            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
-            layout,
-        }
+/// Returns the user and kernel CPU time consumed by all tasks, including
+/// exited ones, but excluding the idle tasks.
+pub(crate) fn total_cpu_times() -> (Duration, Duration) {
+    let mut user = Duration::from_nanos(DROPPED_USER_TIME.load(Ordering::Acquire));
+    let mut kernel = Duration::from_nanos(DROPPED_KERNEL_TIME.load(Ordering::Acquire));
+    for task in all_tasks().iter().filter(|task| !task.is_idle()) {
+        user += task.user_time();
+        kernel += task.kernel_time();
     }
+    (user, kernel)
+}
 
-    pub const fn top(&self) -> VirtAddr {
-        unsafe { core::mem::transmute(self.ptr.as_ptr().add(self.layout.size())) }
-    }
+/// Finds a live task by its ID.
+pub(crate) fn find_task(id: u64) -> Option<AxTaskRef> {
+    TASK_REGISTRY.lock().get(&id).and_then(Weak::upgrade)
 }
 
-impl Drop for TaskStack {
-    fn drop(&mut self) {
-        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+/// Returns all live tasks, ordered by task IDs.
+pub(crate) fn all_tasks() -> Vec<AxTaskRef> {
+    // Do not drop tasks with the lock held, as it is also taken on dropping.
+    TASK_REGISTRY
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Cancellation states of a task.
+struct CancelState {
+    /// Whether the task has been requested to be cancelled.
+    pending: bool,
+    /// Whether the task is blocked in an interruptible wait.
+    interruptible: bool,
+}
+
+/// Priority inheritance states of a task.
+pub(crate) struct PiState {
+    /// Priorities donated by the waiters of the locks held by this task,
+    /// indexed by lock ID.
+    pub donations: Vec<(usize, isize)>,
+    /// The lock this task is blocked on and the ID of its owner.
+    pub blocked_on: Option<(usize, u64)>,
+}
+
+impl PiState {
+    const fn new() -> Self {
+        Self {
+            donations: Vec::new(),
+            blocked_on: None,
+        }
     }
 }
 
@@ -407,6 +924,9 @@ impl CurrentTask {
 
     pub(crate) unsafe fn init_current(init_task: AxTaskRef) {
         assert!(init_task.is_init());
+        init_task.set_on_cpu(true);
+        init_task.set_cpu_id(axhal::cpu::this_cpu_id());
+        init_task.switch_in(axhal::time::monotonic_time_nanos());
         #[cfg(feature = "tls")]
         axhal::arch::write_thread_pointer(init_task.tls.tls_ptr() as usize);
         let ptr = Arc::into_raw(init_task);
@@ -430,7 +950,7 @@ impl Deref for CurrentTask {
 
 extern "C" fn task_entry() -> ! {
     // release the lock that was implicitly held across the reschedule
-    unsafe { crate::RUN_QUEUE.force_unlock() };
+    unsafe { crate::run_queue::finish_first_switch() };
     #[cfg(feature = "irq")]
     axhal::arch::enable_irqs();
     let task = crate::current();
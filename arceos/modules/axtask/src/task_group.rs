@@ -0,0 +1,212 @@
+//! Task groups with CPU bandwidth quotas, similar to `cpu.max` of cgroups.
+//!
+//! Tasks of a group may consume at most `quota` of CPU time (summed over all
+//! CPUs) in each `period`. The CPU time is charged on each timer tick and
+//! each context switch. When a group exhausts its quota, it is throttled:
+//! its running tasks are preempted on their next ticks, and its ready tasks
+//! are parked instead of being picked, until the next period begins.
+//!
+//! Tasks are not in any group by default, and a new task joins the group of
+//! the task that spawns it.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::{epochoffset_nanos, monotonic_time_nanos};
+
+use crate::{AxTaskRef, SpinNoIrq};
+
+/// The default period of CPU bandwidth control.
+const DEFAULT_PERIOD: Duration = Duration::from_millis(100);
+
+/// The reference type of a task group.
+pub type TaskGroupRef = Arc<TaskGroup>;
+
+/// Usage statistics of a task group.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct TaskGroupStat {
+    /// The total CPU time consumed by tasks of the group.
+    pub usage: Duration,
+    /// The number of periods that have elapsed while tasks of the group ran.
+    pub nr_periods: u64,
+    /// The number of times the group has been throttled.
+    pub nr_throttled: u64,
+    /// The total time the group has been throttled.
+    pub throttled_time: Duration,
+}
+
+struct GroupState {
+    /// The start of the current period.
+    period_start: u64,
+    /// The CPU time consumed in the current period.
+    used: u64,
+    /// The time when the group was throttled, or `None` if it is not.
+    throttled_since: Option<u64>,
+    /// Ready tasks picked while the group is throttled.
+    parked: Vec<AxTaskRef>,
+}
+
+/// A group of tasks sharing a CPU bandwidth quota.
+pub struct TaskGroup {
+    name: String,
+    /// The quota in nanoseconds, 0 means unlimited.
+    quota: AtomicU64,
+    period: AtomicU64,
+    state: SpinNoIrq<GroupState>,
+    usage: AtomicU64,
+    nr_periods: AtomicU64,
+    nr_throttled: AtomicU64,
+    throttled_time: AtomicU64,
+}
+
+impl TaskGroup {
+    /// Creates a new task group, whose tasks may consume at most `quota` of
+    /// CPU time in each `period`, or unlimited if `quota` is [`None`].
+    ///
+    /// A zero period is replaced with the default one of 100ms.
+    pub fn new(name: &str, quota: Option<Duration>, period: Duration) -> TaskGroupRef {
+        let group = Arc::new(Self {
+            name: name.into(),
+            quota: AtomicU64::new(0),
+            period: AtomicU64::new(0),
+            state: SpinNoIrq::new(GroupState {
+                period_start: monotonic_time_nanos(),
+                used: 0,
+                throttled_since: None,
+                parked: Vec::new(),
+            }),
+            usage: AtomicU64::new(0),
+            nr_periods: AtomicU64::new(0),
+            nr_throttled: AtomicU64::new(0),
+            throttled_time: AtomicU64::new(0),
+        });
+        group.set_cpu_max(quota, period);
+        group
+    }
+
+    /// Returns the name of the group.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Returns the quota and the period.
+    pub fn cpu_max(&self) -> (Option<Duration>, Duration) {
+        let quota = self.quota.load(Ordering::Acquire);
+        let period = self.period.load(Ordering::Acquire);
+        (
+            (quota != 0).then(|| Duration::from_nanos(quota)),
+            Duration::from_nanos(period),
+        )
+    }
+
+    /// Sets the quota and the period, which take effect from the next charge.
+    ///
+    /// A zero period is replaced with the default one of 100ms.
+    pub fn set_cpu_max(&self, quota: Option<Duration>, period: Duration) {
+        let period = if period.is_zero() {
+            DEFAULT_PERIOD
+        } else {
+            period
+        };
+        let quota = quota.map_or(0, |q| (q.as_nanos() as u64).max(1));
+        self.quota.store(quota, Ordering::Release);
+        self.period
+            .store(period.as_nanos() as u64, Ordering::Release);
+    }
+
+    /// Returns the usage statistics.
+    pub fn stat(&self) -> TaskGroupStat {
+        let mut throttled_time = self.throttled_time.load(Ordering::Acquire);
+        if let Some(since) = self.state.lock().throttled_since {
+            throttled_time += monotonic_time_nanos().saturating_sub(since);
+        }
+        TaskGroupStat {
+            usage: Duration::from_nanos(self.usage.load(Ordering::Acquire)),
+            nr_periods: self.nr_periods.load(Ordering::Acquire),
+            nr_throttled: self.nr_throttled.load(Ordering::Acquire),
+            throttled_time: Duration::from_nanos(throttled_time),
+        }
+    }
+
+    /// Returns `true` if the group is throttled.
+    pub fn is_throttled(&self) -> bool {
+        self.state.lock().throttled_since.is_some()
+    }
+
+    /// Charges `delta` of CPU time at `now`, and throttles the group if the
+    /// quota is exhausted.
+    ///
+    /// Returns `true` if the group is throttled.
+    pub(crate) fn charge(self: &Arc<Self>, delta: u64, now: u64) -> bool {
+        self.usage.fetch_add(delta, Ordering::AcqRel);
+        let quota = self.quota.load(Ordering::Acquire);
+        let period = self.period.load(Ordering::Acquire);
+        let mut state = self.state.lock();
+        if state.throttled_since.is_some() {
+            return true;
+        }
+        if now >= state.period_start + period {
+            self.nr_periods.fetch_add(1, Ordering::AcqRel);
+            state.period_start = now;
+            state.used = 0;
+        }
+        state.used += delta;
+        if quota == 0 || state.used < quota {
+            return false;
+        }
+        state.throttled_since = Some(now);
+        self.nr_throttled.fetch_add(1, Ordering::AcqRel);
+        let period_end = state.period_start + period;
+        drop(state);
+        debug!("task group {} is throttled", self.name);
+        let deadline = Duration::from_nanos(period_end + epochoffset_nanos());
+        crate::timers::set_alarm_unthrottle(deadline, self.clone());
+        true
+    }
+
+    /// Parks a ready task which has been picked, if the group is throttled.
+    ///
+    /// Returns the task back if it is not parked.
+    pub(crate) fn park(&self, task: AxTaskRef) -> Option<AxTaskRef> {
+        let mut state = self.state.lock();
+        if state.throttled_since.is_none() {
+            return Some(task);
+        }
+        state.parked.push(task);
+        None
+    }
+
+    /// Starts a new period, and returns the parked tasks to run again.
+    pub(crate) fn unthrottle(&self) -> Vec<AxTaskRef> {
+        let now = monotonic_time_nanos();
+        let mut state = self.state.lock();
+        if let Some(since) = state.throttled_since.take() {
+            self.throttled_time
+                .fetch_add(now.saturating_sub(since), Ordering::AcqRel);
+            self.nr_periods.fetch_add(1, Ordering::AcqRel);
+        }
+        state.period_start = now;
+        state.used = 0;
+        debug!("task group {} is unthrottled", self.name);
+        core::mem::take(&mut state.parked)
+    }
+}
+
+/// Charges `delta` of CPU time consumed by the task to its group.
+///
+/// Returns `true` if the group is throttled.
+pub(crate) fn charge(task: &AxTaskRef, delta: u64, now: u64) -> bool {
+    match task.task_group() {
+        Some(group) => group.charge(delta, now),
+        None => false,
+    }
+}
+
+/// Parks a picked task if its group is throttled, see [`TaskGroup::park`].
+pub(crate) fn park_if_throttled(task: AxTaskRef) -> Option<AxTaskRef> {
+    match task.task_group() {
+        Some(group) => group.park(task),
+        None => Some(task),
+    }
+}
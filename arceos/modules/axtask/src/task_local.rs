@@ -0,0 +1,167 @@
+//! Task-local storage.
+//!
+//! Unlike the task extended data (see [`def_task_ext!`]), which is defined
+//! once by the application, any number of task-local keys can be declared
+//! by different crates with [`task_local!`]. The value of a key is lazily
+//! initialized on the first access in each task, and dropped when the task
+//! exits.
+//!
+//! [`def_task_ext!`]: crate::def_task_ext
+//! [`task_local!`]: crate::task_local
+
+use alloc::{boxed::Box, vec::Vec};
+use core::any::Any;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The ID of a key which has not been accessed yet.
+const UNREGISTERED: usize = usize::MAX;
+
+static NEXT_KEY_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A key of task-local storage, declared by [`task_local!`].
+///
+/// [`task_local!`]: crate::task_local
+pub struct LocalKey<T: 'static> {
+    id: AtomicUsize,
+    init: fn() -> T,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            id: AtomicUsize::new(UNREGISTERED),
+            init,
+        }
+    }
+
+    /// Returns the ID of the key, which is allocated on the first access.
+    fn id(&self) -> usize {
+        let id = self.id.load(Ordering::Acquire);
+        if id != UNREGISTERED {
+            return id;
+        }
+        let new_id = NEXT_KEY_ID.fetch_add(1, Ordering::Relaxed);
+        match self
+            .id
+            .compare_exchange(UNREGISTERED, new_id, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => new_id,
+            Err(id) => id,
+        }
+    }
+
+    /// Calls `f` with a reference to the value of this key in the current
+    /// task, which is initialized first if it is not yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is accessed during its own initialization.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let curr = crate::current();
+        let id = self.id();
+        // SAFETY: the task-local values are only accessed by the task itself.
+        let ptr = match unsafe { curr.task_locals() }.get(id) {
+            Some(ptr) => ptr,
+            None => {
+                // The initializer may access other keys.
+                let value = Box::new((self.init)());
+                unsafe { curr.task_locals() }.insert(id, value)
+            }
+        };
+        // SAFETY: values are not dropped until the task exits, and the key ID
+        // is only used for the type `T`.
+        f(unsafe { ptr.cast::<T>().as_ref() })
+    }
+}
+
+/// The task-local values of a task, indexed by key IDs.
+#[derive(Default)]
+pub(crate) struct TaskLocals {
+    values: Vec<Option<NonNull<dyn Any>>>,
+}
+
+impl TaskLocals {
+    pub fn is_empty(&self) -> bool {
+        self.values.iter().all(Option::is_none)
+    }
+
+    fn get(&self, id: usize) -> Option<NonNull<dyn Any>> {
+        self.values.get(id).copied().flatten()
+    }
+
+    fn insert(&mut self, id: usize, value: Box<dyn Any>) -> NonNull<dyn Any> {
+        if id >= self.values.len() {
+            self.values.resize(id + 1, None);
+        }
+        assert!(
+            self.values[id].is_none(),
+            "task-local value initialized recursively"
+        );
+        let ptr = NonNull::from(Box::leak(value));
+        self.values[id] = Some(ptr);
+        ptr
+    }
+}
+
+impl Drop for TaskLocals {
+    fn drop(&mut self) {
+        for ptr in self.values.drain(..).flatten() {
+            drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+        }
+    }
+}
+
+/// Drops the task-local values of the current task, called before it exits.
+///
+/// Destructors may access task-local keys again, so it repeats until no value
+/// is left.
+pub(crate) fn destroy_current() {
+    let curr = crate::current();
+    loop {
+        let locals = core::mem::take(unsafe { curr.task_locals() });
+        if locals.is_empty() {
+            break;
+        }
+        drop(locals);
+    }
+}
+
+/// Declares task-local keys of the type [`LocalKey`].
+///
+/// Each key has an independent value in every task, initialized by the given
+/// expression on the first access with [`LocalKey::with`], and dropped when
+/// the task exits.
+///
+/// # Example
+///
+/// ```
+/// use core::cell::Cell;
+///
+/// axtask::task_local! {
+///     static COUNTER: Cell<u32> = Cell::new(0);
+/// }
+///
+/// axtask::init_scheduler();
+///
+/// COUNTER.with(|c| c.set(c.get() + 1));
+/// assert_eq!(COUNTER.with(|c| c.get()), 1);
+/// axtask::spawn(|| assert_eq!(COUNTER.with(|c| c.get()), 0)).join();
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::LocalKey<$t> = $crate::LocalKey::new(|| $init);
+        $crate::task_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::LocalKey<$t> = $crate::LocalKey::new(|| $init);
+    };
+}
@@ -1,40 +1,246 @@
+//! Timed events and the timer tick.
+//!
+//! The timer tick is stopped when a CPU becomes idle: the timer is then
+//! programmed for the earliest timed event only. It is restarted when the CPU
+//! switches to a task, and with preemptive schedulers, as soon as a task
+//! becomes ready on the CPU.
+//!
+//! Each CPU has its own timer list, which holds the timed events set on that
+//! CPU, so that a CPU with the tick stopped is always programmed for all the
+//! events it has to handle.
+
 use alloc::sync::Arc;
-use axhal::time::wall_time;
-use kspin::SpinNoIrq;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::time::{epochoffset_nanos, monotonic_time_nanos, wall_time, NANOS_PER_SEC};
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use crate::{AxTaskRef, RUN_QUEUE};
+use crate::{current_run_queue, AxTaskRef, SpinNoIrq};
+
+static TIMER_LISTS: [LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>>; axconfig::SMP] =
+    [const { LazyInit::new() }; axconfig::SMP];
 
-// TODO: per-CPU
-static TIMER_LIST: LazyInit<SpinNoIrq<TimerList<TaskWakeupEvent>>> = LazyInit::new();
+/// Returns the timer list of this CPU.
+fn timer_list() -> &'static SpinNoIrq<TimerList<TaskWakeupEvent>> {
+    &TIMER_LISTS[axhal::cpu::this_cpu_id()]
+}
 
-struct TaskWakeupEvent(AxTaskRef);
+enum TaskWakeupEvent {
+    /// Unblocks a sleeping task.
+    Task(AxTaskRef),
+    /// Wakes up a pending future.
+    #[cfg(feature = "async")]
+    Waker(core::task::Waker),
+    /// Starts a new period of a throttled task group.
+    #[cfg(feature = "task_group")]
+    Unthrottle(crate::task_group::TaskGroupRef),
+}
 
 impl TimerEvent for TaskWakeupEvent {
     fn callback(self, _now: TimeValue) {
-        let mut rq = RUN_QUEUE.lock();
-        self.0.set_in_timer_list(false);
-        rq.unblock_task(self.0, true);
+        match self {
+            Self::Task(task) => {
+                let mut rq = current_run_queue();
+                task.set_in_timer_list(false);
+                rq.unblock_task(task, true);
+            }
+            #[cfg(feature = "async")]
+            Self::Waker(waker) => waker.wake(),
+            #[cfg(feature = "task_group")]
+            Self::Unthrottle(group) => {
+                let tasks = group.unthrottle();
+                let mut rq = current_run_queue();
+                for task in tasks {
+                    rq.requeue_task(task);
+                }
+            }
+        }
     }
 }
 
+const PERIODIC_INTERVAL_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// The maximum time an idle CPU sleeps without a timer interrupt.
+///
+/// Without IPIs, other CPUs cannot wake up an idle CPU to run the tasks they
+/// sent or to steal their tasks, so it has to poll at a lower rate. On a
+/// single CPU, it only keeps the deadline in the range of all timers.
+const MAX_IDLE_NANOS: u64 = if cfg!(feature = "smp") {
+    PERIODIC_INTERVAL_NANOS * 10
+} else {
+    NANOS_PER_SEC
+};
+
+/// Whether the periodic tick is stopped on this CPU.
+#[percpu::def_percpu]
+static TICK_STOPPED: bool = false;
+
+/// The deadline the timer of this CPU is programmed for.
+#[percpu::def_percpu]
+static NEXT_DEADLINE: u64 = 0;
+
+/// Increased each time a task becomes ready on this CPU.
+#[percpu::def_percpu]
+static READY_SEQ: u64 = 0;
+
+/// The number of timer interrupts on all CPUs.
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+/// The number of times idle CPUs are woken up.
+static IDLE_WAKEUPS: AtomicU64 = AtomicU64::new(0);
+
+/// Programs the timer of this CPU. IRQs must be disabled.
+fn program_timer(deadline: u64) {
+    unsafe { NEXT_DEADLINE.write_current_raw(deadline) };
+    axhal::time::set_oneshot_timer(deadline);
+}
+
+/// Returns the monotonic time of the earliest timed event, bounded by
+/// [`MAX_IDLE_NANOS`] from `now`.
+///
+/// The releases of throttled real-time tasks on this CPU are also timed
+/// events, as they are only replenished when the scheduler runs.
+fn next_event_deadline(now: u64) -> u64 {
+    let limit = now + MAX_IDLE_NANOS;
+    let limit = crate::run_queue::next_rt_release().map_or(limit, |release| release.min(limit));
+    match timer_list().lock().next_deadline() {
+        Some(deadline) => (deadline.as_nanos() as u64)
+            .saturating_sub(epochoffset_nanos())
+            .min(limit),
+        None => limit,
+    }
+}
+
+/// Reprograms the timer after a timer interrupt.
+pub fn update_timer() {
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+    let now = monotonic_time_nanos();
+    // Safety: IRQs are disabled in IRQ handlers.
+    if unsafe { TICK_STOPPED.read_current_raw() } {
+        program_timer(next_event_deadline(now));
+        return;
+    }
+    let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() } + PERIODIC_INTERVAL_NANOS;
+    if now >= deadline {
+        deadline = now + PERIODIC_INTERVAL_NANOS;
+    }
+    program_timer(deadline);
+}
+
+/// Restarts the periodic tick on this CPU if it is stopped. IRQs must be
+/// disabled.
+pub fn restart_tick() {
+    if unsafe { TICK_STOPPED.read_current_raw() } {
+        unsafe { TICK_STOPPED.write_current_raw(false) };
+        program_timer(monotonic_time_nanos() + PERIODIC_INTERVAL_NANOS);
+    }
+}
+
+/// Called when a task becomes ready on this CPU. IRQs must be disabled.
+///
+/// Only preemptive schedulers need the periodic tick to run ready tasks.
+/// Otherwise, they run when the current task yields, and the tick stays
+/// stopped until then.
+pub fn on_task_ready() {
+    unsafe { READY_SEQ.write_current_raw(READY_SEQ.read_current_raw() + 1) };
+    #[cfg(feature = "preempt")]
+    restart_tick();
+}
+
+/// Returns the value to be passed to [`stop_tick`].
+pub fn ready_seq() -> u64 {
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe { READY_SEQ.read_current_raw() }
+}
+
+/// Stops the periodic tick when this CPU becomes idle, unless a task has
+/// become ready since [`ready_seq`] returned `seq`.
+///
+/// Returns `false` if the tick is not stopped.
+pub fn stop_tick(seq: u64) -> bool {
+    let _guard = kernel_guard::IrqSave::new();
+    if unsafe { READY_SEQ.read_current_raw() } != seq {
+        return false;
+    }
+    unsafe { TICK_STOPPED.write_current_raw(true) };
+    program_timer(next_event_deadline(monotonic_time_nanos()));
+    true
+}
+
+/// Records that an idle CPU is woken up.
+pub fn on_idle_wakeup() {
+    IDLE_WAKEUPS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of timer interrupts and idle wakeups on all CPUs.
+pub fn timer_stats() -> (u64, u64) {
+    (
+        TIMER_TICKS.load(Ordering::Relaxed),
+        IDLE_WAKEUPS.load(Ordering::Relaxed),
+    )
+}
+
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
+    // Stay on this CPU until the timer is reprogrammed for the event.
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    let mut timers = timer_list().lock();
     task.set_in_timer_list(true);
-    timers.set(deadline, TaskWakeupEvent(task));
+    timers.set(deadline, TaskWakeupEvent::Task(task));
+    drop(timers);
+    on_alarm_set(deadline);
+}
+
+/// Wakes up `waker` when `deadline` is reached.
+#[cfg(feature = "async")]
+pub fn set_alarm_waker(deadline: TimeValue, waker: core::task::Waker) {
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    timer_list()
+        .lock()
+        .set(deadline, TaskWakeupEvent::Waker(waker));
+    on_alarm_set(deadline);
 }
 
+/// Unthrottles `group` when `deadline` is reached.
+#[cfg(feature = "task_group")]
+pub fn set_alarm_unthrottle(deadline: TimeValue, group: crate::task_group::TaskGroupRef) {
+    let _guard = kernel_guard::NoPreemptIrqSave::new();
+    timer_list()
+        .lock()
+        .set(deadline, TaskWakeupEvent::Unthrottle(group));
+    on_alarm_set(deadline);
+}
+
+/// Called after an event is set in the timer list of this CPU. IRQs must be
+/// disabled.
+fn on_alarm_set(deadline: TimeValue) {
+    // Without the periodic tick, the timer must fire at the new deadline if
+    // it is earlier than the programmed one.
+    if unsafe { TICK_STOPPED.read_current_raw() } {
+        let deadline = (deadline.as_nanos() as u64).saturating_sub(epochoffset_nanos());
+        if deadline < unsafe { NEXT_DEADLINE.read_current_raw() } {
+            program_timer(deadline);
+        }
+    }
+}
+
+/// Cancels the wakeup of `task`.
+///
+/// The task may have set the alarm on another CPU before it is migrated, so
+/// the timer lists of all CPUs are checked.
 pub fn cancel_alarm(task: &AxTaskRef) {
-    let mut timers = TIMER_LIST.lock();
     task.set_in_timer_list(false);
-    timers.cancel(|t| Arc::ptr_eq(&t.0, task));
+    for timers in &TIMER_LISTS {
+        timers
+            .lock()
+            .cancel(|t| matches!(t, TaskWakeupEvent::Task(t) if Arc::ptr_eq(t, task)));
+    }
 }
 
+/// Handles the expired events of this CPU. IRQs must be disabled.
 pub fn check_events() {
     loop {
         let now = wall_time();
-        let event = TIMER_LIST.lock().expire_one(now);
+        let event = timer_list().lock().expire_one(now);
         if let Some((_deadline, event)) = event {
             event.callback(now);
         } else {
@@ -44,5 +250,7 @@ pub fn check_events() {
 }
 
 pub fn init() {
-    TIMER_LIST.init_once(SpinNoIrq::new(TimerList::new()));
+    for timers in &TIMER_LISTS {
+        timers.init_once(SpinNoIrq::new(TimerList::new()));
+    }
 }
@@ -2,7 +2,7 @@ use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use kspin::SpinRaw;
 
-use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
+use crate::{current_run_queue, AxRunQueue, AxTaskRef, Cancelled, CurrentTask};
 
 /// A queue to store sleeping tasks.
 ///
@@ -27,7 +27,7 @@ use crate::{AxRunQueue, AxTaskRef, CurrentTask, RUN_QUEUE};
 /// assert_eq!(VALUE.load(Ordering::Relaxed), 1);
 /// ```
 pub struct WaitQueue {
-    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the `RUN_QUEUE`
+    queue: SpinRaw<VecDeque<AxTaskRef>>, // we already disabled IRQs when lock the run queue
 }
 
 impl WaitQueue {
@@ -50,7 +50,7 @@ impl WaitQueue {
         // the event from another queue.
         if curr.in_wait_queue() {
             // wake up by timer (timeout).
-            // `RUN_QUEUE` is not locked here, so disable IRQs.
+            // The run queue is not locked here, so disable IRQs.
             let _guard = kernel_guard::IrqSave::new();
             self.queue.lock().retain(|t| !curr.ptr_eq(t));
             curr.set_in_wait_queue(false);
@@ -64,8 +64,13 @@ impl WaitQueue {
 
     /// Blocks the current task and put it into the wait queue, until other task
     /// notifies it.
+    ///
+    /// It is not a cancellation point, see
+    /// [`wait_interruptible`](Self::wait_interruptible).
     pub fn wait(&self) {
-        RUN_QUEUE.lock().block_current(|task| {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
+        current_run_queue().block_current(|task| {
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
@@ -76,19 +81,25 @@ impl WaitQueue {
     /// `condition` becomes true.
     ///
     /// Note that even other tasks notify this task, it will not wake up until
-    /// the condition becomes true.
+    /// the condition becomes true. It is not a cancellation point either, see
+    /// [`wait_until_interruptible`](Self::wait_until_interruptible).
     pub fn wait_until<F>(&self, condition: F)
     where
         F: Fn() -> bool,
     {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
+            // Hold the queue lock while checking the condition, so that a
+            // notification from another CPU cannot be lost in between.
+            let mut wq = self.queue.lock();
             if condition() {
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
             });
         }
         self.cancel_events(crate::current());
@@ -96,8 +107,13 @@ impl WaitQueue {
 
     /// Blocks the current task and put it into the wait queue, until other tasks
     /// notify it, or the given duration has elapsed.
+    ///
+    /// It is not a cancellation point, see
+    /// [`wait_timeout_until_interruptible`](Self::wait_timeout_until_interruptible).
     #[cfg(feature = "irq")]
     pub fn wait_timeout(&self, dur: core::time::Duration) -> bool {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
         let curr = crate::current();
         let deadline = axhal::time::wall_time() + dur;
         debug!(
@@ -105,9 +121,8 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
-        RUN_QUEUE.lock().block_current(|task| {
+        current_run_queue().block_current(|task| {
+            crate::timers::set_alarm_wakeup(deadline, task.clone());
             task.set_in_wait_queue(true);
             self.queue.lock().push_back(task)
         });
@@ -126,6 +141,8 @@ impl WaitQueue {
     where
         F: Fn() -> bool,
     {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
         let curr = crate::current();
         let deadline = axhal::time::wall_time() + dur;
         debug!(
@@ -133,30 +150,127 @@ impl WaitQueue {
             curr.id_name(),
             deadline
         );
-        crate::timers::set_alarm_wakeup(deadline, curr.clone());
-
         let mut timeout = true;
-        while axhal::time::wall_time() < deadline {
-            let mut rq = RUN_QUEUE.lock();
+        loop {
+            let mut rq = current_run_queue();
+            if axhal::time::wall_time() >= deadline {
+                break;
+            }
+            let mut wq = self.queue.lock();
             if condition() {
                 timeout = false;
                 break;
             }
-            rq.block_current(|task| {
+            rq.block_current(move |task| {
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task.clone());
+                }
                 task.set_in_wait_queue(true);
-                self.queue.lock().push_back(task);
+                wq.push_back(task);
             });
         }
         self.cancel_events(curr);
         timeout
     }
 
+    /// Like [`wait`](Self::wait), but returns [`Cancelled`] if the current
+    /// task is cancelled by [`TaskInner::cancel`](crate::TaskInner::cancel)
+    /// before or during the wait.
+    pub fn wait_interruptible(&self) -> Result<(), Cancelled> {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
+        current_run_queue().block_current_interruptible(|task| {
+            task.set_in_wait_queue(true);
+            self.queue.lock().push_back(task)
+        });
+        let curr = crate::current();
+        let cancelled = curr.is_cancelled();
+        self.cancel_events(curr);
+        if cancelled {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like [`wait_until`](Self::wait_until), but returns [`Cancelled`] if
+    /// the current task is cancelled before the condition becomes true.
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> Result<(), Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
+        let curr = crate::current();
+        let res = loop {
+            let mut rq = current_run_queue();
+            let mut wq = self.queue.lock();
+            if condition() {
+                break Ok(());
+            }
+            if curr.is_cancelled() {
+                break Err(Cancelled);
+            }
+            rq.block_current_interruptible(move |task| {
+                task.set_in_wait_queue(true);
+                wq.push_back(task);
+            });
+        };
+        self.cancel_events(curr);
+        res
+    }
+
+    /// Like [`wait_timeout_until`](Self::wait_timeout_until), but returns
+    /// [`Cancelled`] if the current task is cancelled before the condition
+    /// becomes true or the timeout expires.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_interruptible<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> Result<bool, Cancelled>
+    where
+        F: Fn() -> bool,
+    {
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::might_sleep();
+        let curr = crate::current();
+        let deadline = axhal::time::wall_time() + dur;
+        debug!(
+            "task wait_timeout (interruptible): {}, deadline={:?}",
+            curr.id_name(),
+            deadline
+        );
+        let res = loop {
+            let mut rq = current_run_queue();
+            if axhal::time::wall_time() >= deadline {
+                break Ok(true);
+            }
+            let mut wq = self.queue.lock();
+            if condition() {
+                break Ok(false);
+            }
+            if curr.is_cancelled() {
+                break Err(Cancelled);
+            }
+            rq.block_current_interruptible(move |task| {
+                if !task.in_timer_list() {
+                    crate::timers::set_alarm_wakeup(deadline, task.clone());
+                }
+                task.set_in_wait_queue(true);
+                wq.push_back(task);
+            });
+        };
+        self.cancel_events(curr);
+        res
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_one(&self, resched: bool) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let mut rq = current_run_queue();
         if !self.queue.lock().is_empty() {
             self.notify_one_locked(resched, &mut rq)
         } else {
@@ -170,14 +284,14 @@ impl WaitQueue {
     /// preemption is enabled.
     pub fn notify_all(&self, resched: bool) {
         loop {
-            let mut rq = RUN_QUEUE.lock();
+            let mut rq = current_run_queue();
             if let Some(task) = self.queue.lock().pop_front() {
                 task.set_in_wait_queue(false);
                 rq.unblock_task(task, resched);
             } else {
                 break;
             }
-            drop(rq); // we must unlock `RUN_QUEUE` after unlocking `self.queue`.
+            drop(rq); // we must unlock the run queue after unlocking `self.queue`.
         }
     }
 
@@ -186,7 +300,7 @@ impl WaitQueue {
     /// If `resched` is true, the current task will be preempted when the
     /// preemption is enabled.
     pub fn notify_task(&mut self, resched: bool, task: &AxTaskRef) -> bool {
-        let mut rq = RUN_QUEUE.lock();
+        let mut rq = current_run_queue();
         let mut wq = self.queue.lock();
         if let Some(index) = wq.iter().position(|t| Arc::ptr_eq(t, task)) {
             task.set_in_wait_queue(false);
@@ -0,0 +1,205 @@
+//! Soft-lockup and hung-task watchdog.
+//!
+//! A soft lockup is detected on the timer tick, when a CPU has not
+//! rescheduled for a while, e.g., a task spins with preemption disabled, or
+//! never yields under a cooperative scheduler.
+//!
+//! Hung tasks are detected by a kernel task which periodically scans all
+//! tasks, looking for the ones blocked in uninterruptible waits without a
+//! timeout for too long. Each blocking is reported only once.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use axhal::time::monotonic_time_nanos;
+
+use crate::{current, AxTaskRef, TaskState};
+
+/// The default timeout of soft lockups.
+const DEFAULT_SOFT_LOCKUP_TIMEOUT: Duration = Duration::from_secs(20);
+/// The default timeout of hung tasks.
+const DEFAULT_HUNG_TASK_TIMEOUT: Duration = Duration::from_secs(120);
+/// The interval to scan tasks if the hung-task check is disabled.
+const IDLE_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+// Timeouts in nanoseconds, 0 means disabled.
+static SOFT_LOCKUP_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_SOFT_LOCKUP_TIMEOUT.as_nanos() as _);
+static HUNG_TASK_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_HUNG_TASK_TIMEOUT.as_nanos() as _);
+static PANIC_ON_DETECT: AtomicBool = AtomicBool::new(false);
+
+/// The monotonic time when this CPU last rescheduled.
+#[percpu::def_percpu]
+static LAST_RESCHED: u64 = 0;
+
+/// Whether the current soft lockup of this CPU has been reported.
+#[percpu::def_percpu]
+static LOCKUP_REPORTED: bool = false;
+
+/// Configurations of the watchdog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// The time a CPU can run without rescheduling, or `None` to disable the
+    /// soft-lockup check.
+    pub soft_lockup_timeout: Option<Duration>,
+    /// The time a task can be blocked in an uninterruptible wait without a
+    /// timeout, or `None` to disable the hung-task check.
+    pub hung_task_timeout: Option<Duration>,
+    /// Whether to panic instead of only logging on detection.
+    pub panic_on_detect: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            soft_lockup_timeout: Some(DEFAULT_SOFT_LOCKUP_TIMEOUT),
+            hung_task_timeout: Some(DEFAULT_HUNG_TASK_TIMEOUT),
+            panic_on_detect: false,
+        }
+    }
+}
+
+fn to_nanos(timeout: Option<Duration>) -> u64 {
+    timeout.map_or(0, |t| (t.as_nanos() as u64).max(1))
+}
+
+fn from_nanos(nanos: u64) -> Option<Duration> {
+    (nanos != 0).then(|| Duration::from_nanos(nanos))
+}
+
+/// Returns the current configurations of the watchdog.
+pub fn watchdog_config() -> WatchdogConfig {
+    WatchdogConfig {
+        soft_lockup_timeout: from_nanos(SOFT_LOCKUP_TIMEOUT.load(Ordering::Relaxed)),
+        hung_task_timeout: from_nanos(HUNG_TASK_TIMEOUT.load(Ordering::Relaxed)),
+        panic_on_detect: PANIC_ON_DETECT.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets the configurations of the watchdog.
+///
+/// The new hung-task timeout takes effect after the next scan.
+pub fn set_watchdog_config(config: WatchdogConfig) {
+    SOFT_LOCKUP_TIMEOUT.store(to_nanos(config.soft_lockup_timeout), Ordering::Relaxed);
+    HUNG_TASK_TIMEOUT.store(to_nanos(config.hung_task_timeout), Ordering::Relaxed);
+    PANIC_ON_DETECT.store(config.panic_on_detect, Ordering::Relaxed);
+}
+
+/// Records that the current CPU has rescheduled, called with IRQs disabled.
+pub(crate) fn touch() {
+    unsafe {
+        LAST_RESCHED.write_current_raw(monotonic_time_nanos());
+        LOCKUP_REPORTED.write_current_raw(false);
+    }
+}
+
+/// Checks for a soft lockup of the current CPU, called on every timer tick.
+pub(crate) fn on_timer_tick() {
+    if current().is_idle() {
+        // An idle CPU is never locked up.
+        touch();
+        return;
+    }
+    check_soft_lockup(monotonic_time_nanos());
+}
+
+/// Reports a soft lockup if the current CPU has not rescheduled for the
+/// timeout at `now`, called with IRQs disabled.
+///
+/// Returns `true` if a soft lockup is reported, which is only once until the
+/// CPU reschedules.
+pub(crate) fn check_soft_lockup(now: u64) -> bool {
+    let timeout = SOFT_LOCKUP_TIMEOUT.load(Ordering::Relaxed);
+    let curr = current();
+    let last = unsafe { LAST_RESCHED.read_current_raw() };
+    if timeout == 0 || now < last + timeout || unsafe { LOCKUP_REPORTED.read_current_raw() } {
+        return false;
+    }
+    unsafe { LOCKUP_REPORTED.write_current_raw(true) };
+
+    #[cfg(feature = "preempt")]
+    let preempt_count = curr.preempt_disable_count();
+    #[cfg(not(feature = "preempt"))]
+    let preempt_count = 0;
+    error!(
+        "watchdog: soft lockup on CPU {} for {:?}, task {} state={:?} preempt_count={}",
+        axhal::cpu::this_cpu_id(),
+        Duration::from_nanos(now - last),
+        curr.id_name(),
+        curr.state(),
+        preempt_count,
+    );
+    dump_backtrace(curr.as_task_ref());
+    if PANIC_ON_DETECT.load(Ordering::Relaxed) {
+        panic!("soft lockup in task {}", curr.id_name());
+    }
+    true
+}
+
+/// Logs the backtrace of the current CPU, running the task `curr`.
+fn dump_backtrace(curr: &AxTaskRef) {
+    error!(
+        "watchdog: backtrace of CPU {}:{}",
+        axhal::cpu::this_cpu_id(),
+        curr.backtrace()
+    );
+}
+
+/// Starts the task to check for hung tasks.
+pub(crate) fn init() {
+    crate::spawn_raw(
+        run_hung_task_check,
+        "watchdog".into(),
+        axconfig::TASK_STACK_SIZE,
+    );
+}
+
+/// Returns `true` if the task is blocked in an uninterruptible wait without a
+/// timeout.
+fn is_hung_candidate(task: &AxTaskRef) -> bool {
+    task.state() == TaskState::Blocked
+        && !task.is_idle()
+        && task.hung_check_enabled()
+        && !task.in_timer_list()
+        && !task.in_interruptible_wait()
+}
+
+fn run_hung_task_check() {
+    let mut last_scan = monotonic_time_nanos();
+    loop {
+        let timeout = HUNG_TASK_TIMEOUT.load(Ordering::Relaxed);
+        crate::sleep(from_nanos(timeout).unwrap_or(IDLE_SCAN_INTERVAL));
+        let now = monotonic_time_nanos();
+        if timeout != 0 {
+            check_hung_tasks(timeout, last_scan, now);
+        }
+        last_scan = now;
+    }
+}
+
+/// Returns `true` if the blocked time of `task` crossed `timeout` during
+/// `(last_scan, now]`, i.e., it should be reported by the scan at `now`.
+pub(crate) fn is_newly_hung(task: &AxTaskRef, timeout: u64, last_scan: u64, now: u64) -> bool {
+    if !is_hung_candidate(task) {
+        return false;
+    }
+    let hung_at = task.blocked_since() + timeout;
+    last_scan < hung_at && hung_at <= now
+}
+
+/// Reports tasks whose blocked time crossed `timeout` during `(last_scan, now]`.
+fn check_hung_tasks(timeout: u64, last_scan: u64, now: u64) {
+    for task in crate::task::all_tasks() {
+        if !is_newly_hung(&task, timeout, last_scan, now) {
+            continue;
+        }
+        error!(
+            "watchdog: task {} state={:?} blocked for more than {:?}",
+            task.id_name(),
+            task.state(),
+            Duration::from_nanos(now - task.blocked_since()),
+        );
+        if PANIC_ON_DETECT.load(Ordering::Relaxed) {
+            panic!("hung task {}", task.id_name());
+        }
+    }
+}
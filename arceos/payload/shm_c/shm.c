@@ -0,0 +1,86 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/shm.h>
+#include <sys/syscall.h>
+
+#define CHECK(cond)                                                     \
+    do {                                                                \
+        if (!(cond)) {                                                  \
+            printf("Check failed at line %d: %s\n", __LINE__, #cond);  \
+            exit(-1);                                                   \
+        }                                                               \
+    } while (0)
+
+void test_sysv_shm()
+{
+    int id;
+    char *addr, *addr2;
+
+    id = shmget(IPC_PRIVATE, 0x2000, IPC_CREAT | 0600);
+    CHECK(id >= 0);
+
+    // Two attachments of the same segment see the changes of each other.
+    addr = shmat(id, NULL, 0);
+    CHECK(addr != (void *)-1);
+    addr2 = shmat(id, NULL, 0);
+    CHECK(addr2 != (void *)-1 && addr2 != addr);
+    strcpy(addr + 0x1000, "hello, shm!");
+    CHECK(strcmp(addr2 + 0x1000, "hello, shm!") == 0);
+
+    // Detaching only works at the attached addresses, once.
+    CHECK(shmdt(addr + 0x1000) == -1 && errno == EINVAL);
+    CHECK(shmdt(addr) == 0);
+    CHECK(shmdt(addr) == -1 && errno == EINVAL);
+
+    // The segment is kept until the last attachment is detached.
+    CHECK(shmctl(id, IPC_RMID, NULL) == 0);
+    CHECK(strcmp(addr2 + 0x1000, "hello, shm!") == 0);
+    CHECK(shmdt(addr2) == 0);
+    CHECK(shmat(id, NULL, 0) == (void *)-1 && errno == EINVAL);
+}
+
+void test_posix_shm()
+{
+    int fd;
+    char *addr, *addr2;
+    const char *name = "/arceos_shm";
+
+    fd = shm_open(name, O_RDWR | O_CREAT | O_EXCL, 0600);
+    CHECK(fd >= 0);
+    CHECK(shm_open(name, O_RDWR | O_CREAT | O_EXCL, 0600) == -1 && errno == EEXIST);
+    CHECK(ftruncate(fd, 0x1000) == 0);
+
+    addr = mmap(NULL, 0x1000, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    CHECK(addr != MAP_FAILED);
+    addr2 = mmap(NULL, 0x1000, PROT_READ, MAP_SHARED, fd, 0);
+    CHECK(addr2 != MAP_FAILED);
+    strcpy(addr, "hello, posix shm!");
+    CHECK(strcmp(addr2, "hello, posix shm!") == 0);
+    close(fd);
+
+    // The object is still mapped after unlinked.
+    CHECK(shm_unlink(name) == 0);
+    CHECK(shm_unlink(name) == -1 && errno == ENOENT);
+    CHECK(strcmp(addr2, "hello, posix shm!") == 0);
+    CHECK(munmap(addr, 0x1000) == 0);
+    CHECK(munmap(addr2, 0x1000) == 0);
+
+    // Directory file descriptors are not supported.
+    CHECK(syscall(SYS_unlinkat, 100, "/dev/shm/arceos_shm", 0) == -1 && errno == EINVAL);
+}
+
+int main()
+{
+    printf("Shm ...\n");
+
+    test_sysv_shm();
+    test_posix_shm();
+
+    printf("Shm ok!\n");
+    return 0;
+}
@@ -15,3 +15,5 @@ make run A=tour/u_6_0
 make run A=tour/u_6_1
 make run A=tour/u_7_0 BLK=y
 make run A=tour/u_8_0 BLK=y
+
+make run A=exercises/swap_pages
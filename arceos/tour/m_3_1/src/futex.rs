@@ -0,0 +1,35 @@
+//! The futex syscall.
+
+use alloc::sync::Arc;
+
+use axtask::futex::user::UserFutexes;
+use axtask::{current, TaskExtRef};
+
+pub(crate) fn sys_futex(
+    uaddr: usize,
+    futex_op: i32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> isize {
+    let curr = current();
+    let aspace = &curr.task_ext().aspace;
+    let futexes = UserFutexes::new(Arc::as_ptr(aspace) as usize, || aspace.lock());
+    match futexes.futex(uaddr, futex_op, val, timeout, uaddr2, val3) {
+        Ok(ret) => ret,
+        Err(e) => {
+            debug!("sys_futex => {:?}", e);
+            -e.code() as isize
+        }
+    }
+}
+
+/// Clears the word at `clear_child_tid` of the exiting task, and wakes up a
+/// task waiting on it, as `set_tid_address(2)` describes.
+pub(crate) fn clear_child_tid() {
+    let curr = current();
+    let aspace = &curr.task_ext().aspace;
+    let futexes = UserFutexes::new(Arc::as_ptr(aspace) as usize, || aspace.lock());
+    futexes.clear_child_tid(curr.task_ext().clear_child_tid() as usize);
+}
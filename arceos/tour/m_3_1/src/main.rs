@@ -11,6 +11,7 @@ extern crate axlog;
 mod task;
 mod syscall;
 mod loader;
+mod futex;
 
 use axstd::io;
 use axhal::paging::MappingFlags;
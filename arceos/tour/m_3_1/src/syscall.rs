@@ -17,6 +17,7 @@ const SYS_WRITEV: usize = 66;
 const SYS_EXIT: usize = 93;
 const SYS_EXIT_GROUP: usize = 94;
 const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_FUTEX: usize = 98;
 
 const AT_FDCWD: i32 = -100;
 
@@ -31,12 +32,22 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
         SYS_READ => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITE => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         SYS_WRITEV => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        SYS_FUTEX => crate::futex::sys_futex(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
         SYS_EXIT_GROUP => {
             ax_println!("[SYS_EXIT_GROUP]: system is exiting ..");
+            crate::futex::clear_child_tid();
             axtask::exit(tf.arg0() as _)
         },
         SYS_EXIT => {
             ax_println!("[SYS_EXIT]: system is exiting ..");
+            crate::futex::clear_child_tid();
             axtask::exit(tf.arg0() as _)
         },
         _ => {
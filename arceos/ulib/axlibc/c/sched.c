@@ -1,9 +1,19 @@
+#include <errno.h>
 #include <sched.h>
 #include <stdio.h>
 
-// TODO
+#ifndef AX_CONFIG_MULTITASK
+
 int sched_setaffinity(pid_t __pid, size_t __cpusetsize, const cpu_set_t *__cpuset)
 {
-    unimplemented();
-    return 0;
+    errno = ENOSYS;
+    return -1;
 }
+
+int sched_getaffinity(pid_t __pid, size_t __cpusetsize, cpu_set_t *__cpuset)
+{
+    errno = ENOSYS;
+    return -1;
+}
+
+#endif // AX_CONFIG_MULTITASK
@@ -21,6 +21,8 @@
 #define IOV_MAX    1024
 
 #define PTHREAD_STACK_MIN 2048
+#define PTHREAD_KEYS_MAX  128
+#define SEM_VALUE_MAX     0x7fffffff
 
 #define LOGIN_NAME_MAX 256
 #ifndef NAME_MAX
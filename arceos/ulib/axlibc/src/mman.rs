@@ -0,0 +1,43 @@
+use core::ffi::{c_int, c_void};
+
+use arceos_posix_api::{sys_mmap, sys_msync, sys_munmap};
+
+use crate::{ctypes, utils::e};
+
+/// Map files or devices into memory.
+///
+/// Return the start address of the mapping if success, otherwise return
+/// `MAP_FAILED`.
+#[no_mangle]
+pub unsafe extern "C" fn mmap(
+    addr: *mut c_void,
+    len: ctypes::size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> *mut c_void {
+    let ret = sys_mmap(addr, len, prot, flags, fd, off) as isize;
+    // Error codes are in [-4095, -1], which are never valid addresses.
+    if (-4095..0).contains(&ret) {
+        crate::errno::set_errno(-ret as _);
+        return usize::MAX as *mut c_void; // MAP_FAILED
+    }
+    ret as _
+}
+
+/// Unmap the memory mapped by `mmap`.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn munmap(addr: *mut c_void, len: ctypes::size_t) -> c_int {
+    e(sys_munmap(addr, len))
+}
+
+/// Write the changes to a shared file mapping back to the file.
+///
+/// Return 0 if success.
+#[no_mangle]
+pub unsafe extern "C" fn msync(addr: *mut c_void, len: ctypes::size_t, flags: c_int) -> c_int {
+    e(sys_msync(addr, len, flags))
+}
@@ -0,0 +1,68 @@
+//! A simple executor to run futures on ArceOS tasks.
+//!
+//! Pending futures do not take any CPU time: they are woken up by timers,
+//! network events, or other futures, and then polled again.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use axstd::executor;
+//! use core::time::Duration;
+//!
+//! let handle = executor::spawn(async {
+//!     executor::sleep(Duration::from_millis(10)).await;
+//!     42
+//! });
+//! assert_eq!(executor::block_on(handle), 42);
+//! ```
+
+use core::future::Future;
+use core::time::Duration;
+
+use arceos_api::task as api;
+
+/// A handle to await the output of a future spawned by [`spawn`].
+///
+/// Dropping the handle detaches the future, which keeps running.
+pub type JoinHandle<T> = api::AxJoinHandle<T>;
+
+/// A future returned by [`sleep`] and [`sleep_until`].
+pub type Sleep = api::AxSleepFuture;
+
+/// Runs a future to completion on the current thread.
+///
+/// The current thread is blocked while the future is pending.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    api::ax_block_on(fut)
+}
+
+/// Spawns a future to run in the background.
+///
+/// The future is polled by worker threads, one per CPU, which are created
+/// on first use.
+pub fn spawn<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    api::ax_spawn_async(fut)
+}
+
+/// Returns a future that completes after the given duration.
+///
+/// If the `irq` feature is not enabled, the future is polled repeatedly
+/// until the deadline.
+pub fn sleep(dur: Duration) -> Sleep {
+    sleep_until(arceos_api::time::ax_wall_time() + dur)
+}
+
+/// Returns a future that completes at the given deadline.
+///
+/// If the `irq` feature is not enabled, the future is polled repeatedly
+/// until the deadline.
+pub fn sleep_until(deadline: arceos_api::time::AxTimeValue) -> Sleep {
+    api::ax_sleep_until_async(deadline)
+}
+
+/// Yields to other futures once.
+pub use api::ax_yield_now_async as yield_now;
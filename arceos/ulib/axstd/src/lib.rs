@@ -22,11 +22,16 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `swap`: Enable swapping of anonymous pages to a block device, or to
+//!       a swap file if `fs` is enabled.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
+//!     - `sched_edf`: Use the Earliest Deadline First (EDF) real-time scheduler.
+//!     - `async`: Enable the async [`executor`], and asynchronous socket I/O.
+//!     - `lock-stat`: Collect lock contention statistics.
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
@@ -71,6 +76,8 @@ pub mod sync;
 pub mod thread;
 pub mod time;
 
+#[cfg(feature = "async")]
+pub mod executor;
 #[cfg(feature = "fs")]
 pub mod fs;
 #[cfg(feature = "net")]
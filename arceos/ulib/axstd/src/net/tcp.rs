@@ -2,6 +2,8 @@ use super::{SocketAddr, ToSocketAddrs};
 use crate::io::{self, prelude::*};
 
 use arceos_api::net::{self as api, AxTcpSocketHandle};
+#[cfg(feature = "async")]
+use core::future::poll_fn;
 
 /// A TCP stream between a local and a remote socket.
 pub struct TcpStream(AxTcpSocketHandle);
@@ -43,6 +45,22 @@ impl TcpStream {
     pub fn shutdown(&self) -> io::Result<()> {
         api::ax_tcp_shutdown(&self.0)
     }
+
+    /// Reads data from the stream asynchronously.
+    ///
+    /// On success, returns the number of bytes read.
+    #[cfg(feature = "async")]
+    pub async fn read_async(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| api::ax_tcp_poll_recv(&self.0, cx, buf)).await
+    }
+
+    /// Writes data to the stream asynchronously.
+    ///
+    /// On success, returns the number of bytes written.
+    #[cfg(feature = "async")]
+    pub async fn write_async(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| api::ax_tcp_poll_send(&self.0, cx, buf)).await
+    }
 }
 
 impl Read for TcpStream {
@@ -102,4 +120,12 @@ impl TcpListener {
     pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
         api::ax_tcp_accept(&self.0).map(|(a, b)| (TcpStream(a), b))
     }
+
+    /// Accepts a new incoming connection from this listener asynchronously.
+    #[cfg(feature = "async")]
+    pub async fn accept_async(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        poll_fn(|cx| api::ax_tcp_poll_accept(&self.0, cx))
+            .await
+            .map(|(a, b)| (TcpStream(a), b))
+    }
 }
@@ -2,6 +2,8 @@ use super::{SocketAddr, ToSocketAddrs};
 use crate::io;
 
 use arceos_api::net::{self as api, AxUdpSocketHandle};
+#[cfg(feature = "async")]
+use core::future::poll_fn;
 
 /// A UDP socket.
 pub struct UdpSocket(AxUdpSocketHandle);
@@ -41,6 +43,13 @@ impl UdpSocket {
         api::ax_udp_recv_from(&self.0, buf)
     }
 
+    /// Receives a single datagram message on the socket asynchronously. On
+    /// success, returns the number of bytes read and the origin.
+    #[cfg(feature = "async")]
+    pub async fn recv_from_async(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| api::ax_udp_poll_recv_from(&self.0, cx, buf)).await
+    }
+
     /// Receives a single datagram message on the socket, without removing it from
     /// the queue. On success, returns the number of bytes read and the origin.
     pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
@@ -3,7 +3,7 @@
 extern crate alloc;
 
 use crate::io;
-use alloc::{string::String, sync::Arc};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use core::{cell::UnsafeCell, num::NonZeroU64};
 
 use arceos_api::task::{self as api, AxTaskHandle};
@@ -147,6 +147,19 @@ where
     Builder::new().spawn(f).expect("failed to spawn thread")
 }
 
+/// Waits for any of the given threads, which must be spawned by the current
+/// thread, to finish, and returns the index of the first finished one in
+/// `handles`.
+///
+/// The finished thread can then be joined without blocking. Returns an
+/// [`InvalidInput`](io::Error::InvalidInput) error if `handles` is empty,
+/// or a [`NotFound`](io::Error::NotFound) error if it contains threads not
+/// spawned by the current thread.
+pub fn wait_any<T>(handles: &[JoinHandle<T>]) -> io::Result<usize> {
+    let tasks = handles.iter().map(|h| &h.native).collect::<Vec<_>>();
+    api::ax_wait_any(&tasks).map(|(index, _)| index)
+}
+
 struct Packet<T> {
     result: UnsafeCell<Option<T>>,
 }
@@ -186,4 +199,18 @@ impl<T> JoinHandle<T> {
             .take()
             .ok_or_else(|| ax_err_type!(BadState))
     }
+
+    /// Waits for the associated thread to finish, with a timeout.
+    ///
+    /// If the thread has not finished after `dur`, the handle is returned
+    /// back as [`Err`], so that it can be joined again later. It is only
+    /// available with the `irq` feature, which provides the timers.
+    #[cfg(feature = "irq")]
+    pub fn join_timeout(self, dur: core::time::Duration) -> Result<io::Result<T>, Self> {
+        match api::ax_wait_for_exit_timeout(&self.native, dur) {
+            Ok(Some(_)) => Ok(self.join()),
+            Ok(None) => Err(self),
+            Err(e) => Ok(Err(e)),
+        }
+    }
 }